@@ -11,11 +11,39 @@
 
 #include "wasi_snapshot_preview1.h"
 
+// Precedes every response so that the host can tell guest writes to fd 1
+// apart from protocol messages.
+static const uint8_t RESPONSE_MAGIC[8] = { 0, 'w', 'a', 'z', 'z', 'i', 0, 0 };
+
 noreturn static void fail(const char * err) {
     fprintf(stderr, "%s\n", err);
     exit(1);
 }
 
+// File that stands in for the guest's stdin, since the real one carries
+// requests. -1 if the host didn't provide one.
+static __wasi_fd_t guest_stdin_fd = -1;
+
+// The host puts the stdin file in a preopen and names that preopen's fd in
+// WAZZI_STDIN_FD.
+static void open_guest_stdin(void) {
+    const char * dir_fd = getenv("WAZZI_STDIN_FD");
+
+    if (dir_fd == NULL) return;
+
+    __wasi_errno_t err = __wasi_path_open(
+        atoi(dir_fd),
+        0,
+        "stdin",
+        0,
+        __WASI_RIGHTS_FD_READ | __WASI_RIGHTS_FD_SEEK | __WASI_RIGHTS_FD_TELL,
+        0,
+        0,
+        &guest_stdin_fd
+    );
+    if (err != __WASI_ERRNO_SUCCESS) fail("failed to open guest stdin");
+}
+
 static uint64_t u64_from_bytes(const uint8_t m[8]) {
     return ((uint64_t)m[7] << 56)
         | ((uint64_t)m[6] << 48)
@@ -99,6 +127,7 @@ static void set_ptr_value(void * ptr, const Value * value) {
             * (char **) ptr = calloc(value->string.len + 1, sizeof(uint8_t));
             memcpy(* (char **) ptr, value->string.data, value->string.len);
             (* (char **) ptr)[value->string.len] = '\0';
+            * (uint32_t *) ((char **) ptr + 1) = value->string.len;
 
             break;
         }
@@ -107,7 +136,7 @@ static void set_ptr_value(void * ptr, const Value * value) {
 
             for (int i = 0; i < value->bitflags->n_members; i++)
                 if (value->bitflags->members[i]->value)
-                    val |= 0x1 << i;
+                    val |= (uint64_t) 1 << i;
 
             switch (value->bitflags->repr) {
                 case INT_REPR__U8: * (uint8_t *) ptr = (uint8_t) val; break;
@@ -563,7 +592,7 @@ static void * value_ptr_new(const Value * value) {
         }
         case VALUE__WHICH_STRING: {
             ptr = calloc(1, sizeof(char *) + sizeof(uint32_t));
-            * (uint32_t *) ((char **) ptr)[1] = value->string.len;
+            * (uint32_t *) ((char **) ptr + 1) = value->string.len;
 
             break;
         }
@@ -581,7 +610,7 @@ static void * value_ptr_new(const Value * value) {
         case VALUE__WHICH_HANDLE: ptr = calloc(1, sizeof(int32_t)); break;
         case VALUE__WHICH_ARRAY: {
             ptr = calloc(1, sizeof(void *) + sizeof(uint32_t));
-            * (uint32_t *) ((void **) ptr)[1] = value->array->n_items;
+            * (uint32_t *) ((void **) ptr + 1) = value->array->n_items;
 
             break;
         }
@@ -1110,6 +1139,9 @@ static void handle_call(Request__Call * call) {
             int32_t r0_size = (int32_t) r0_size_ptr;
             uint32_t p1_iovs_len = * (uint32_t *) (&((void **) p1_iovs_ptr)[1]);
 
+            // Serve reads of stdin from the host-provided file.
+            if (p0_fd == 0 && guest_stdin_fd != (__wasi_fd_t) -1) p0_fd = guest_stdin_fd;
+
             int iovs_idx = 0;
             __wasi_size_t to_read = 0;
             __wasi_size_t n_read  = 0;
@@ -1331,7 +1363,7 @@ static void handle_call(Request__Call * call) {
             void * p1_path_ptr = value_ptr_new(call->params[1]);
             int32_t p0_fd = * (int32_t *) p0_fd_ptr;
             int32_t p1_path = (int32_t) * (void **) p1_path_ptr;
-            uint32_t p1_path_len = * (uint32_t *) (((void **) p1_path_ptr)[1]);
+            uint32_t p1_path_len = * (uint32_t *) (&((void **) p1_path_ptr)[1]);
 
             response.errno_some = __imported_wasi_snapshot_preview1_path_create_directory(
                 p0_fd,
@@ -1356,7 +1388,7 @@ static void handle_call(Request__Call * call) {
             int32_t p1_flags = * (int32_t *) p1_flags_ptr;
             int32_t p2_path = * (int32_t *) p2_path_ptr;
             int32_t r0_filestat = (int32_t) r0_filestat_ptr;
-            uint32_t p2_path_len = * (uint32_t *) (((void **) p2_path_ptr)[1]);
+            uint32_t p2_path_len = * (uint32_t *) (&((void **) p2_path_ptr)[1]);
 
             response.errno_some = __imported_wasi_snapshot_preview1_path_filestat_get(
                 p0_fd,
@@ -1389,7 +1421,7 @@ static void handle_call(Request__Call * call) {
             int64_t p3_atim = * (int64_t *) p3_atim_ptr;
             int64_t p4_mtim = * (int64_t *) p4_mtim_ptr;
             int32_t p5_fst_flags = * (uint16_t *) p5_fst_flags_ptr;
-            uint32_t p2_path_len = * (uint32_t *) (((void **) p2_path_ptr)[1]);
+            uint32_t p2_path_len = * (uint32_t *) (&((void **) p2_path_ptr)[1]);
 
             response.errno_some = __imported_wasi_snapshot_preview1_path_filestat_set_times(
                 p0_fd,
@@ -1424,8 +1456,8 @@ static void handle_call(Request__Call * call) {
             int32_t p2_old_path = * (int32_t *) p2_old_path_ptr;
             int32_t p3_new_fd = * (int32_t *) p3_new_fd_ptr;
             int32_t p4_new_path = * (int32_t *) p4_new_path_ptr;
-            uint32_t p2_old_path_len = * (uint32_t *) (((void **) p2_old_path_ptr)[1]);
-            uint32_t p4_new_path_len = * (uint32_t *) (((void **) p4_new_path_ptr)[1]);
+            uint32_t p2_old_path_len = * (uint32_t *) (&((void **) p2_old_path_ptr)[1]);
+            uint32_t p4_new_path_len = * (uint32_t *) (&((void **) p4_new_path_ptr)[1]);
 
             response.errno_some = __imported_wasi_snapshot_preview1_path_link(
                 p0_old_fd,
@@ -1465,7 +1497,7 @@ static void handle_call(Request__Call * call) {
             int64_t p5_fs_rights_inheriting = * (int64_t *) p5_fs_rights_inheriting_ptr;
             int32_t p6_fdflags = * (int16_t *) p6_fdflags_ptr;
             int32_t r0_fd = (int32_t) r0_fd_ptr;
-            uint32_t p2_path_len = * (uint32_t *) (((void **) p2_path_ptr)[1]);
+            uint32_t p2_path_len = * (uint32_t *) (&((void **) p2_path_ptr)[1]);
 
             response.errno_some = __imported_wasi_snapshot_preview1_path_open(
                 p0_fd,
@@ -1499,7 +1531,7 @@ static void handle_call(Request__Call * call) {
             void * p1_path_ptr = value_ptr_new(call->params[1]);
             int32_t p0_fd = * (int32_t *) p0_fd_ptr;
             int32_t p1_path = * (int32_t *) p1_path_ptr;
-            uint32_t p1_path_len = * (uint32_t *) (((void **) p1_path_ptr)[1]);
+            uint32_t p1_path_len = * (uint32_t *) (&((void **) p1_path_ptr)[1]);
 
             response.errno_some = __imported_wasi_snapshot_preview1_path_remove_directory(
                 p0_fd,
@@ -1524,8 +1556,8 @@ static void handle_call(Request__Call * call) {
             int32_t p1_old_path = * (int32_t *) p1_old_path_ptr;
             int32_t p2_new_fd = * (int32_t *) p2_new_fd_ptr;
             int32_t p3_new_path = * (int32_t *) p3_new_path_ptr;
-            uint32_t p1_old_path_len = * (uint32_t *) (((void **) p1_old_path_ptr)[1]);
-            uint32_t p3_new_path_len = * (uint32_t *) (((void **) p3_new_path_ptr)[1]);
+            uint32_t p1_old_path_len = * (uint32_t *) (&((void **) p1_old_path_ptr)[1]);
+            uint32_t p3_new_path_len = * (uint32_t *) (&((void **) p3_new_path_ptr)[1]);
 
             response.errno_some = __imported_wasi_snapshot_preview1_path_rename(
                 p0_fd,
@@ -1553,8 +1585,8 @@ static void handle_call(Request__Call * call) {
             int32_t p0_old_path = * (int32_t *) p0_old_path_ptr;
             int32_t p1_fd = * (int32_t *) p1_fd_ptr;
             int32_t p2_new_path = * (int32_t *) p2_new_path_ptr;
-            uint32_t p0_old_path_len = * (uint32_t *) (((void **) p0_old_path_ptr)[1]);
-            uint32_t p2_new_path_len = * (uint32_t *) (((void **) p2_new_path_ptr)[1]);
+            uint32_t p0_old_path_len = * (uint32_t *) (&((void **) p0_old_path_ptr)[1]);
+            uint32_t p2_new_path_len = * (uint32_t *) (&((void **) p2_new_path_ptr)[1]);
 
             response.errno_some = __imported_wasi_snapshot_preview1_path_symlink(
                 p0_old_path,
@@ -1578,7 +1610,7 @@ static void handle_call(Request__Call * call) {
             void * p1_path_ptr = value_ptr_new(call->params[1]);
             int32_t p0_fd = * (int32_t *) p0_fd_ptr;
             int32_t p1_path = * (int32_t *) p1_path_ptr;
-            uint32_t p1_path_len = * (uint32_t *) (((void **) p1_path_ptr)[1]);
+            uint32_t p1_path_len = * (uint32_t *) (&((void **) p1_path_ptr)[1]);
 
             response.errno_some = __imported_wasi_snapshot_preview1_path_unlink_file(
                 p0_fd,
@@ -1615,7 +1647,10 @@ static void handle_call(Request__Call * call) {
     u64_to_bytes(size_buf, msg_size);
     response__pack(&msg, buf);
 
-    size_t blks_written = fwrite(size_buf, 8, 1, stdout);
+    size_t blks_written = fwrite(RESPONSE_MAGIC, 8, 1, stdout);
+    if (blks_written != 1) fail("failed to write response magic out");
+
+    blks_written = fwrite(size_buf, 8, 1, stdout);
     if (blks_written != 1) fail("failed to write message size out");
 
     blks_written = fwrite(buf, msg_size, 1, stdout);
@@ -1636,6 +1671,8 @@ static void handle_call(Request__Call * call) {
 }
 
 int main(void) {
+    open_guest_stdin();
+
     while (true) {
         Request * req = read_request();
 
@@ -2,12 +2,15 @@ use std::{borrow::Borrow, hash::Hash};
 
 use bimap::BiMap;
 
+/// Values addressable both by key and by the index they were pushed at.
+/// Removing an entry leaves a tombstone in its slot, so the indices of the
+/// remaining entries never shift and removed indices are never reused.
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct IndexSpace<K, V>
 where
     K: PartialEq + Eq + Hash,
 {
-    list: Vec<V>,
+    list: Vec<Option<V>>,
     map:  BiMap<K, usize>,
 }
 
@@ -23,7 +26,7 @@ where
     }
 
     pub fn push(&mut self, k: K, v: V) {
-        self.list.push(v);
+        self.list.push(Some(v));
         self.map.insert(k, self.list.len() - 1);
     }
 
@@ -31,8 +34,21 @@ where
         Iter::new(self)
     }
 
+    /// Number of entries that haven't been removed.
     pub fn len(&self) -> usize {
-        self.list.len()
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.contains_left(k)
     }
 
     pub fn get_by_key<Q>(&self, k: &Q) -> Option<&V>
@@ -40,7 +56,31 @@ where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self.list.get(*self.map.get_by_left(k)?)
+        self.list.get(*self.map.get_by_left(k)?)?.as_ref()
+    }
+
+    /// Removes the entry for `k`, tombstoning its slot.
+    pub fn remove_by_key<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (_k, idx) = self.map.remove_by_left(k)?;
+
+        self.list.get_mut(idx)?.take()
+    }
+
+    /// Index at which the value for `k` was pushed.
+    pub fn index_of_key<Q>(&self, k: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get_by_left(k).copied()
+    }
+
+    pub fn key_at(&self, idx: usize) -> Option<&K> {
+        self.map.get_by_right(&idx)
     }
 }
 
@@ -93,12 +133,16 @@ where
     type Item = (&'s K, &'s V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let v = self.idx_space.list.get(self.next)?;
-        let k = self.idx_space.map.get_by_right(&self.next)?;
+        loop {
+            let slot = self.idx_space.list.get(self.next)?;
+            let idx = self.next;
 
-        self.next += 1;
+            self.next += 1;
 
-        Some((k, v))
+            if let Some(v) = slot {
+                return Some((self.idx_space.map.get_by_right(&idx)?, v));
+            }
+        }
     }
 }
 
@@ -128,4 +172,56 @@ mod tests {
         assert_eq!(t2.0, "c");
         assert_eq!(t2.1, &2);
     }
+
+    #[test]
+    fn reverse_lookup() {
+        let s: IndexSpace<String, i32> = [("a".to_string(), 0), ("b".to_string(), 1)].into_iter().collect();
+
+        assert_eq!(s.index_of_key("b"), Some(1));
+        assert_eq!(s.index_of_key("c"), None);
+        assert_eq!(s.key_at(0).map(String::as_str), Some("a"));
+        assert_eq!(s.key_at(2), None);
+    }
+
+    #[test]
+    fn remove_keeps_indices() {
+        let mut s: IndexSpace<String, i32> = [("a".to_string(), 0), ("b".to_string(), 1), ("c".to_string(), 2)]
+            .into_iter()
+            .collect();
+
+        assert_eq!(s.remove_by_key("b"), Some(1));
+        assert_eq!(s.remove_by_key("b"), None);
+        assert_eq!(s.len(), 2);
+        assert_eq!(s.get_by_key("a"), Some(&0));
+        assert_eq!(s.get_by_key("c"), Some(&2));
+        assert_eq!(s.index_of_key("c"), Some(2));
+        assert_eq!(s.key_at(1), None);
+        assert_eq!(
+            s.iter().map(|(k, v)| (k.as_str(), *v)).collect::<Vec<_>>(),
+            [("a", 0), ("c", 2)]
+        );
+
+        s.push("d".to_string(), 3);
+
+        assert_eq!(s.index_of_key("d"), Some(3));
+    }
+
+    #[test]
+    fn emptiness_and_membership() {
+        let mut s = IndexSpace::new();
+
+        assert!(s.is_empty());
+        assert!(!s.contains_key("a"));
+
+        s.push("a".to_string(), 0i32);
+
+        assert!(!s.is_empty());
+        assert!(s.contains_key("a"));
+        assert!(!s.contains_key("b"));
+
+        s.remove_by_key("a");
+
+        assert!(s.is_empty());
+        assert!(!s.contains_key("a"));
+    }
 }
@@ -1,15 +1,23 @@
 extern crate wazzi_executor_pb_rust as pb;
 
 use std::{
+    collections::BTreeSet,
     ffi::OsString,
     fmt,
     fs,
     io::{self, read_to_string},
     ops::DerefMut as _,
+    os::unix::process::CommandExt as _,
     path::{Path, PathBuf},
     process,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+        Arc,
+        Mutex,
+    },
     thread,
+    time::Duration,
 };
 
 use dunce::canonicalize;
@@ -18,10 +26,76 @@ use protobuf::Message as _;
 use serde::{Deserialize, Serialize};
 use tera::Tera;
 
-#[derive(Clone, Debug)]
+/// Written by the executor ahead of every response. Anything read before it is
+/// output the guest wrote to its own stdout.
+const RESPONSE_MAGIC: [u8; 8] = *b"\0wazzi\0\0";
+
+/// Process groups of the executors alive in this process. Each executor leads
+/// its own group, so these are also their pids.
+static LIVE_EXECUTORS: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// Kills the process group of every executor alive in this process, e.g.
+/// before exiting without dropping them. Being in their own groups, they
+/// would outlive the fuzzer otherwise.
+pub fn kill_live_executors() {
+    for &pgid in LIVE_EXECUTORS.lock().unwrap().iter() {
+        unsafe { libc::kill(-(pgid as libc::pid_t), libc::SIGKILL) };
+    }
+}
+
+/// Default bound on the declared size of a response.
+pub const MAX_MESSAGE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Tries at spawning a runtime before a transient failure is given up on.
+const SPAWN_ATTEMPTS: u32 = 5;
+
+/// Wait before the first retry of a spawn, doubled before each further one.
+const SPAWN_BACKOFF: Duration = Duration::from_millis(20);
+
+/// Whether spawning failed for a reason that may go away by itself, like
+/// `EAGAIN` when many runtimes are being started at once. A missing binary is
+/// not one of them.
+fn is_transient_spawn_error(err: &eyre::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .any(|err| {
+            matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted | io::ErrorKind::OutOfMemory
+            )
+        })
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CallError {
+    #[error("protocol error")]
+    Protocol(#[from] protobuf::Error),
+
+    #[error("executor closed its stdout")]
+    PipeClosed,
+
+    #[error("call did not complete within {0:?}")]
+    Timeout(Duration),
+
+    #[error("executor exited with {0}")]
+    ProcessExited(process::ExitStatus),
+
+    #[error("response declares {size} bytes, more than the limit of {max}")]
+    MessageTooLarge { size: u64, max: u64 },
+}
+
+/// A runtime process running the executor. The process is killed when this is
+/// dropped.
+#[derive(Debug)]
 pub struct RunningExecutor {
-    stdin:  Arc<Mutex<process::ChildStdin>>,
-    stdout: Arc<Mutex<process::ChildStdout>>,
+    child:            Arc<Mutex<process::Child>>,
+    stdin:            Arc<Mutex<process::ChildStdin>>,
+    stdout:           Arc<Mutex<process::ChildStdout>>,
+    stderr_copy:      Option<thread::JoinHandle<u64>>,
+    guest_stdout:     Arc<Mutex<Vec<u8>>>,
+    coverage:         Arc<Mutex<Vec<u8>>>,
+    call_timeout:     Option<Duration>,
+    max_message_size: u64,
 }
 
 impl RunningExecutor {
@@ -31,65 +105,262 @@ impl RunningExecutor {
         working_dir: &Path,
         stderr_logger: Arc<Mutex<W>>,
         preopens: Vec<MappedDir>,
+        envs: Vec<(String, String)>,
+        args: Vec<String>,
     ) -> Result<Self, eyre::Error>
     where
         W: io::Write + Send + 'static,
     {
-        let mut child = wasi_runner
-            .run(executor_bin, working_dir, preopens)
-            .wrap_err(format!("failed to run executor {}", executor_bin.display()))?;
+        let mut backoff = SPAWN_BACKOFF;
+        let mut attempt = 1;
+        let mut child = loop {
+            match wasi_runner.run(executor_bin, working_dir, preopens.clone(), envs.clone(), args.clone()) {
+                | Ok(child) => break child,
+                | Err(err) if attempt < SPAWN_ATTEMPTS && is_transient_spawn_error(&err) => {
+                    tracing::warn!(?err, attempt, ?backoff, "Failed to spawn runtime. Retrying.");
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                },
+                | Err(err) => return Err(err.wrap_err(format!("failed to run executor {}", executor_bin.display()))),
+            }
+        };
         let mut stderr = child.stderr.take().unwrap();
-        let _stderr_copy_handle =
+        let stderr_copy =
             thread::spawn(move || io::copy(&mut stderr, stderr_logger.lock().unwrap().deref_mut()).unwrap());
         let stdin = child.stdin.take().unwrap();
         let stdout = child.stdout.take().unwrap();
 
+        LIVE_EXECUTORS.lock().unwrap().insert(child.id());
+
         Ok(Self {
-            stdin:  Arc::new(Mutex::new(stdin)),
-            stdout: Arc::new(Mutex::new(stdout)),
+            child:            Arc::new(Mutex::new(child)),
+            stdin:            Arc::new(Mutex::new(stdin)),
+            stdout:           Arc::new(Mutex::new(stdout)),
+            stderr_copy:      Some(stderr_copy),
+            guest_stdout:     Default::default(),
+            coverage:         Default::default(),
+            call_timeout:     None,
+            max_message_size: MAX_MESSAGE_SIZE,
         })
     }
 
-    pub fn call(&self, call: pb::request::Call) -> Result<pb::response::Call, protobuf::Error> {
-        let mut stdin = self.stdin.lock().unwrap();
+    /// Kills the executor if a single call takes longer than `call_timeout`.
+    pub fn with_call_timeout(mut self, call_timeout: Duration) -> Self {
+        self.call_timeout = Some(call_timeout);
+        self
+    }
+
+    /// Fails calls whose response declares more than `max_message_size` bytes
+    /// instead of trying to read it.
+    pub fn with_max_message_size(mut self, max_message_size: u64) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Drains what the guest wrote to its stdout since the last drain.
+    pub fn take_guest_stdout(&self) -> Vec<u8> {
+        std::mem::take(&mut self.guest_stdout.lock().unwrap())
+    }
+
+    /// Drains the edge coverage reported since the last drain, merged into one
+    /// bitmap. Empty unless the runtime was built with coverage.
+    pub fn take_coverage(&self) -> Vec<u8> {
+        std::mem::take(&mut self.coverage.lock().unwrap())
+    }
+
+    pub fn call(&self, call: pb::request::Call) -> Result<pb::response::Call, CallError> {
+        let Some(call_timeout) = self.call_timeout else {
+            return self.check_exited(self.exchange(call));
+        };
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let watchdog = thread::spawn({
+            let child = self.child.clone();
+            let timed_out = timed_out.clone();
+
+            move || {
+                if let Err(mpsc::RecvTimeoutError::Timeout) = done_rx.recv_timeout(call_timeout) {
+                    // Killing the child closes its stdout and unblocks the pending read.
+                    timed_out.store(true, Ordering::SeqCst);
+                    let _ = child.lock().unwrap().kill();
+                }
+            }
+        });
+        let result = self.exchange(call);
+
+        drop(done_tx);
+        watchdog.join().unwrap();
+
+        if timed_out.load(Ordering::SeqCst) {
+            return Err(CallError::Timeout(call_timeout));
+        }
+
+        self.check_exited(result)
+    }
+
+    /// Kills the executor and waits for it to exit.
+    pub fn kill(&self) -> Result<(), io::Error> {
+        let mut child = self.child.lock().unwrap();
+
+        child.kill()?;
+        child.wait()?;
+
+        Ok(())
+    }
+
+    /// Returns the executor's exit status if it has already exited.
+    pub fn exit_status(&self) -> Option<process::ExitStatus> {
+        self.child.lock().unwrap().try_wait().ok().flatten()
+    }
+
+    fn check_exited(&self, result: Result<pb::response::Call, CallError>) -> Result<pb::response::Call, CallError> {
+        let err = match result {
+            | Ok(response) => return Ok(response),
+            | Err(err) => err,
+        };
+
+        // The pipe can close slightly before the process can be reaped.
+        for _ in 0..10 {
+            if let Some(status) = self.exit_status() {
+                return Err(CallError::ProcessExited(status));
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        Err(err)
+    }
+
+    fn exchange(&self, call: pb::request::Call) -> Result<pb::response::Call, CallError> {
         let mut stdout = self.stdout.lock().unwrap();
-        let mut os = protobuf::CodedOutputStream::new(stdin.deref_mut());
         let mut is = protobuf::CodedInputStream::new(stdout.deref_mut());
+
+        match self.exchange_inner(call, &mut is) {
+            | Ok(response) => Ok(response),
+            // A read cut short by EOF means the executor went away mid-message.
+            | Err(CallError::Protocol(_)) if is.eof().unwrap_or(true) => Err(CallError::PipeClosed),
+            | Err(err) => Err(err),
+        }
+    }
+
+    fn exchange_inner(
+        &self,
+        call: pb::request::Call,
+        is: &mut protobuf::CodedInputStream,
+    ) -> Result<pb::response::Call, CallError> {
+        let mut stdin = self.stdin.lock().unwrap();
+        let mut os = protobuf::CodedOutputStream::new(stdin.deref_mut());
         let mut request = pb::Request::new();
 
         request.set_call(call);
 
         let message_size = request.compute_size();
 
-        os.write_raw_bytes(&message_size.to_le_bytes()).unwrap();
+        os.write_raw_bytes(&message_size.to_le_bytes())?;
         request.write_to(&mut os)?;
         drop(os);
 
+        let mut guest_stdout = Vec::new();
+
+        while !guest_stdout.ends_with(&RESPONSE_MAGIC) {
+            guest_stdout.push(is.read_raw_byte()?);
+        }
+
+        guest_stdout.truncate(guest_stdout.len() - RESPONSE_MAGIC.len());
+        self.guest_stdout.lock().unwrap().extend(guest_stdout);
+
         let msg_size = is.read_fixed64()?;
-        let raw_bytes = is.read_raw_bytes(msg_size as u32)?;
+        let msg_size = u32::try_from(msg_size)
+            .ok()
+            .filter(|&size| u64::from(size) <= self.max_message_size)
+            .ok_or(CallError::MessageTooLarge {
+                size: msg_size,
+                max:  self.max_message_size,
+            })?;
+        let raw_bytes = is.read_raw_bytes(msg_size)?;
+        let response = pb::Response::parse_from_bytes(&raw_bytes)?.take_call();
+        let mut coverage = self.coverage.lock().unwrap();
+
+        if coverage.len() < response.coverage.len() {
+            coverage.resize(response.coverage.len(), 0);
+        }
+
+        for (edge, &hits) in coverage.iter_mut().zip(&response.coverage) {
+            *edge = (*edge).max(hits);
+        }
+
+        Ok(response)
+    }
+}
+
+impl Drop for RunningExecutor {
+    fn drop(&mut self) {
+        if let Err(err) = self.kill() {
+            tracing::warn!(?err, "Failed to kill executor.");
+        }
+
+        LIVE_EXECUTORS.lock().unwrap().remove(&self.child.lock().unwrap().id());
 
-        Ok(pb::Response::parse_from_bytes(&raw_bytes)?.take_call())
+        // The copy finishes once the dead process's stderr is drained.
+        if let Some(stderr_copy) = self.stderr_copy.take() {
+            let _ = stderr_copy.join();
+        }
     }
 }
 
 pub trait WasiRunner: fmt::Debug + Send + Sync {
+    /// Builds the command that runs `wasm_path` without spawning it.
+    fn command(
+        &self,
+        wasm_path: &Path,
+        working_dir: &Path,
+        preopens: Vec<MappedDir>,
+        envs: Vec<(String, String)>,
+        args: Vec<String>,
+    ) -> Result<process::Command, eyre::Error>;
+
     fn run(
         &self,
         wasm_path: &Path,
         working_dir: &Path,
         preopens: Vec<MappedDir>,
-    ) -> Result<process::Child, eyre::Error>;
+        envs: Vec<(String, String)>,
+        args: Vec<String>,
+    ) -> Result<process::Child, eyre::Error> {
+        self.command(wasm_path, working_dir, preopens, envs, args)?
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped())
+            .current_dir(working_dir)
+            // Keeps a SIGINT from the terminal away from the runtime, which is
+            // instead killed when its executor is dropped.
+            .process_group(0)
+            .spawn()
+            .wrap_err("failed to spawn command")
+    }
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct Node<'p> {
-    name: String,
-    path: &'p Path,
+    name:       String,
+    path:       &'p Path,
+    extra_args: Vec<String>,
 }
 
 impl<'p> Node<'p> {
     pub fn new(name: String, path: &'p Path) -> Self {
-        Self { name, path }
+        Self {
+            name,
+            path,
+            extra_args: Vec::new(),
+        }
+    }
+
+    /// Flags passed to the runtime before the module.
+    pub fn with_extra_args(mut self, extra_args: Vec<String>) -> Self {
+        self.extra_args = extra_args;
+        self
     }
 
     pub fn name(&self) -> &str {
@@ -104,18 +375,22 @@ impl Default for Node<'_> {
 }
 
 impl WasiRunner for Node<'_> {
-    fn run(
+    fn command(
         &self,
         wasm_path: &Path,
         working_dir: &Path,
         preopens: Vec<MappedDir>,
-    ) -> Result<process::Child, eyre::Error> {
+        envs: Vec<(String, String)>,
+        args: Vec<String>,
+    ) -> Result<process::Command, eyre::Error> {
         static GLUE_TMPL: &str = include_str!("run.js.tera.tmpl");
 
         let mut tmpl_ctx = tera::Context::new();
 
         tmpl_ctx.insert("executor", &wasm_path.canonicalize().unwrap());
         tmpl_ctx.insert("preopens", &preopens);
+        tmpl_ctx.insert("envs", &envs);
+        tmpl_ctx.insert("args", &args);
 
         let glue = Tera::one_off(GLUE_TMPL, &tmpl_ctx, false).unwrap();
         let glue_path = working_dir.join("glue.js");
@@ -124,26 +399,32 @@ impl WasiRunner for Node<'_> {
 
         let mut command = process::Command::new(self.path);
 
-        command
-            .arg(glue_path)
-            .stdin(process::Stdio::piped())
-            .stdout(process::Stdio::piped())
-            .stderr(process::Stdio::piped())
-            .current_dir(working_dir)
-            .spawn()
-            .wrap_err("failed to spawn command")
+        command.args(&self.extra_args).arg(glue_path);
+
+        Ok(command)
     }
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct Wasmedge<'p> {
-    name: String,
-    path: &'p Path,
+    name:       String,
+    path:       &'p Path,
+    extra_args: Vec<String>,
 }
 
 impl<'p> Wasmedge<'p> {
     pub fn new(name: String, path: &'p Path) -> Self {
-        Self { name, path }
+        Self {
+            name,
+            path,
+            extra_args: Vec::new(),
+        }
+    }
+
+    /// Flags passed to the runtime before the module.
+    pub fn with_extra_args(mut self, extra_args: Vec<String>) -> Self {
+        self.extra_args = extra_args;
+        self
     }
 
     pub fn name(&self) -> &str {
@@ -158,15 +439,17 @@ impl Default for Wasmedge<'_> {
 }
 
 impl WasiRunner for Wasmedge<'_> {
-    fn run(
+    fn command(
         &self,
         wasm_path: &Path,
-        working_dir: &Path,
+        _working_dir: &Path,
         preopens: Vec<MappedDir>,
-    ) -> Result<process::Child, eyre::Error> {
+        envs: Vec<(String, String)>,
+        args: Vec<String>,
+    ) -> Result<process::Command, eyre::Error> {
         let mut command = process::Command::new(self.path);
 
-        command.arg("run");
+        command.arg("run").args(&self.extra_args);
 
         for dir in preopens {
             let mut dir_arg = OsString::new();
@@ -178,21 +461,21 @@ impl WasiRunner for Wasmedge<'_> {
             command.arg(dir_arg);
         }
 
-        command
-            .arg(canonicalize(wasm_path).unwrap())
-            .stdin(process::Stdio::piped())
-            .stdout(process::Stdio::piped())
-            .stderr(process::Stdio::piped())
-            .current_dir(working_dir)
-            .spawn()
-            .wrap_err("failed to spawn command")
+        for (key, value) in envs {
+            command.arg("--env").arg(format!("{key}={value}"));
+        }
+
+        command.arg(canonicalize(wasm_path).unwrap()).args(args);
+
+        Ok(command)
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct Wasmer<'p> {
-    name: String,
-    path: &'p Path,
+    name:       String,
+    path:       &'p Path,
+    extra_args: Vec<String>,
 }
 
 impl Default for Wasmer<'_> {
@@ -203,7 +486,17 @@ impl Default for Wasmer<'_> {
 
 impl<'p> Wasmer<'p> {
     pub fn new(name: String, path: &'p Path) -> Self {
-        Self { name, path }
+        Self {
+            name,
+            path,
+            extra_args: Vec::new(),
+        }
+    }
+
+    /// Flags passed to the runtime before the module.
+    pub fn with_extra_args(mut self, extra_args: Vec<String>) -> Self {
+        self.extra_args = extra_args;
+        self
     }
 
     pub fn name(&self) -> &str {
@@ -212,15 +505,17 @@ impl<'p> Wasmer<'p> {
 }
 
 impl WasiRunner for Wasmer<'_> {
-    fn run(
+    fn command(
         &self,
         wasm_path: &Path,
-        working_dir: &Path,
+        _working_dir: &Path,
         preopens: Vec<MappedDir>,
-    ) -> Result<process::Child, eyre::Error> {
+        envs: Vec<(String, String)>,
+        args: Vec<String>,
+    ) -> Result<process::Command, eyre::Error> {
         let mut command = process::Command::new(self.path);
 
-        command.arg("run");
+        command.arg("run").args(&self.extra_args);
 
         for dir in preopens {
             let mut mapdir = OsString::new();
@@ -232,21 +527,21 @@ impl WasiRunner for Wasmer<'_> {
             command.arg("--mapdir").arg(mapdir);
         }
 
-        command
-            .arg(wasm_path)
-            .current_dir(working_dir)
-            .stdin(process::Stdio::piped())
-            .stdout(process::Stdio::piped())
-            .stderr(process::Stdio::piped())
-            .spawn()
-            .wrap_err("failed to spawn command")
+        for (key, value) in envs {
+            command.arg("--env").arg(format!("{key}={value}"));
+        }
+
+        command.arg(wasm_path).args(args);
+
+        Ok(command)
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct Wasmtime<'p> {
-    name: String,
-    path: &'p Path,
+    name:       String,
+    path:       &'p Path,
+    extra_args: Vec<String>,
 }
 
 impl Default for Wasmtime<'_> {
@@ -257,7 +552,17 @@ impl Default for Wasmtime<'_> {
 
 impl<'p> Wasmtime<'p> {
     pub fn new(name: String, path: &'p Path) -> Self {
-        Self { name, path }
+        Self {
+            name,
+            path,
+            extra_args: Vec::new(),
+        }
+    }
+
+    /// Flags passed to the runtime before the module.
+    pub fn with_extra_args(mut self, extra_args: Vec<String>) -> Self {
+        self.extra_args = extra_args;
+        self
     }
 
     pub fn name(&self) -> &str {
@@ -266,15 +571,17 @@ impl<'p> Wasmtime<'p> {
 }
 
 impl WasiRunner for Wasmtime<'_> {
-    fn run(
+    fn command(
         &self,
         wasm_path: &Path,
-        working_dir: &Path,
+        _working_dir: &Path,
         preopens: Vec<MappedDir>,
-    ) -> Result<process::Child, eyre::Error> {
+        envs: Vec<(String, String)>,
+        args: Vec<String>,
+    ) -> Result<process::Command, eyre::Error> {
         let mut command = process::Command::new(self.path);
 
-        command.arg("run");
+        command.arg("run").args(&self.extra_args);
 
         for dir in preopens {
             let mut mapdir = OsString::new();
@@ -285,21 +592,77 @@ impl WasiRunner for Wasmtime<'_> {
             command.arg("--dir").arg(mapdir);
         }
 
-        command
-            .arg(canonicalize(wasm_path).unwrap())
-            .current_dir(working_dir)
-            .stdin(process::Stdio::piped())
-            .stdout(process::Stdio::piped())
-            .stderr(process::Stdio::piped())
-            .spawn()
-            .wrap_err("failed to spawn command")
+        for (key, value) in envs {
+            command.arg("--env").arg(format!("{key}={value}"));
+        }
+
+        command.arg(canonicalize(wasm_path).unwrap()).args(args);
+
+        Ok(command)
+    }
+}
+
+/// Wasmtime running the module on its component-model implementation of
+/// preview1, the same host code the preview1 adapter calls into.
+#[derive(Clone, Debug)]
+pub struct WasmtimeComponent<'p> {
+    name:       String,
+    path:       &'p Path,
+    extra_args: Vec<String>,
+}
+
+impl Default for WasmtimeComponent<'_> {
+    fn default() -> Self {
+        Self::new("wasmtime-component".to_string(), Path::new("wasmtime"))
+    }
+}
+
+impl<'p> WasmtimeComponent<'p> {
+    pub fn new(name: String, path: &'p Path) -> Self {
+        Self {
+            name,
+            path,
+            extra_args: Vec::new(),
+        }
+    }
+
+    /// Flags passed to the runtime before the module.
+    pub fn with_extra_args(mut self, extra_args: Vec<String>) -> Self {
+        self.extra_args = extra_args;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl WasiRunner for WasmtimeComponent<'_> {
+    fn command(
+        &self,
+        wasm_path: &Path,
+        working_dir: &Path,
+        preopens: Vec<MappedDir>,
+        envs: Vec<(String, String)>,
+        args: Vec<String>,
+    ) -> Result<process::Command, eyre::Error> {
+        Wasmtime::new(self.name.clone(), self.path)
+            .with_extra_args(
+                ["-W", "component-model=y", "-S", "preview2=y"]
+                    .into_iter()
+                    .map(str::to_owned)
+                    .chain(self.extra_args.iter().cloned())
+                    .collect(),
+            )
+            .command(wasm_path, working_dir, preopens, envs, args)
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct Wamr<'p> {
-    name: String,
-    path: &'p Path,
+    name:       String,
+    path:       &'p Path,
+    extra_args: Vec<String>,
 }
 
 impl Default for Wamr<'_> {
@@ -310,7 +673,17 @@ impl Default for Wamr<'_> {
 
 impl<'p> Wamr<'p> {
     pub fn new(name: String, path: &'p Path) -> Self {
-        Self { name, path }
+        Self {
+            name,
+            path,
+            extra_args: Vec::new(),
+        }
+    }
+
+    /// Flags passed to the runtime before the module.
+    pub fn with_extra_args(mut self, extra_args: Vec<String>) -> Self {
+        self.extra_args = extra_args;
+        self
     }
 
     pub fn name(&self) -> &str {
@@ -319,14 +692,18 @@ impl<'p> Wamr<'p> {
 }
 
 impl WasiRunner for Wamr<'_> {
-    fn run(
+    fn command(
         &self,
         wasm_path: &Path,
-        working_dir: &Path,
+        _working_dir: &Path,
         preopens: Vec<MappedDir>,
-    ) -> Result<process::Child, eyre::Error> {
+        envs: Vec<(String, String)>,
+        args: Vec<String>,
+    ) -> Result<process::Command, eyre::Error> {
         let mut command = process::Command::new(self.path);
 
+        command.args(&self.extra_args);
+
         for dir in preopens {
             let mut dir_arg = OsString::new();
 
@@ -335,27 +712,39 @@ impl WasiRunner for Wamr<'_> {
             command.arg(dir_arg);
         }
 
+        for (key, value) in envs {
+            command.arg(format!("--env={key}={value}"));
+        }
+
         command
             .arg("--stack-size=1000000")
             .arg(canonicalize(wasm_path).unwrap())
-            .stdin(process::Stdio::piped())
-            .stdout(process::Stdio::piped())
-            .stderr(process::Stdio::piped())
-            .current_dir(working_dir)
-            .spawn()
-            .wrap_err("failed to spawn command")
+            .args(args);
+
+        Ok(command)
     }
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct Wazero<'p> {
-    name: String,
-    path: &'p Path,
+    name:       String,
+    path:       &'p Path,
+    extra_args: Vec<String>,
 }
 
 impl<'p> Wazero<'p> {
     pub fn new(name: String, path: &'p Path) -> Self {
-        Self { name, path }
+        Self {
+            name,
+            path,
+            extra_args: Vec::new(),
+        }
+    }
+
+    /// Flags passed to the runtime before the module.
+    pub fn with_extra_args(mut self, extra_args: Vec<String>) -> Self {
+        self.extra_args = extra_args;
+        self
     }
 
     pub fn name(&self) -> &str {
@@ -370,15 +759,17 @@ impl Default for Wazero<'_> {
 }
 
 impl WasiRunner for Wazero<'_> {
-    fn run(
+    fn command(
         &self,
         wasm_path: &Path,
-        working_dir: &Path,
+        _working_dir: &Path,
         preopens: Vec<MappedDir>,
-    ) -> Result<process::Child, eyre::Error> {
+        envs: Vec<(String, String)>,
+        args: Vec<String>,
+    ) -> Result<process::Command, eyre::Error> {
         let mut command = process::Command::new(self.path);
 
-        command.arg("run");
+        command.arg("run").args(&self.extra_args);
 
         for preopen in preopens {
             let mut arg = OsString::new();
@@ -389,19 +780,550 @@ impl WasiRunner for Wazero<'_> {
             command.arg("-mount").arg(arg);
         }
 
-        command
-            .arg(canonicalize(wasm_path).unwrap())
-            .stdin(process::Stdio::piped())
-            .stdout(process::Stdio::piped())
-            .stderr(process::Stdio::piped())
-            .current_dir(working_dir)
-            .spawn()
-            .wrap_err("failed to spawn command")
+        for (key, value) in envs {
+            command.arg("-env").arg(format!("{key}={value}"));
+        }
+
+        command.arg(canonicalize(wasm_path).unwrap()).args(args);
+
+        Ok(command)
     }
 }
 
+/// Env var through which the executor learns the fd of the preopen holding
+/// the guest's stdin file.
+pub const GUEST_STDIN_ENV: &str = "WAZZI_STDIN_FD";
+
+/// Arranges for the guest to read `data` from fd 0.
+///
+/// The executor's stdin carries requests, so it can't be handed to the guest.
+/// Instead `data` is written to a file in `dir`, which is added as the last
+/// preopen, and the executor serves `fd_read` on fd 0 from that file. This
+/// only needs preopens and env vars, so it works the same way on every
+/// backend. It does rely on preopens getting consecutive fds from 3 in the
+/// order given, which Node, Wamr, Wasmedge, Wasmer, Wasmtime and Wazero all do.
+pub fn feed_stdin(
+    dir: &Path,
+    data: &[u8],
+    preopens: &mut Vec<MappedDir>,
+    envs: &mut Vec<(String, String)>,
+) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("stdin"), data)?;
+    envs.push((GUEST_STDIN_ENV.to_string(), (3 + preopens.len()).to_string()));
+    preopens.push(MappedDir {
+        name:      "stdin".to_string(),
+        host_path: dir.to_path_buf(),
+    });
+
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 pub struct MappedDir {
     pub name:      String,
     pub host_path: PathBuf,
 }
+
+/// Stand-ins for real runtimes, for tests in this and dependent crates.
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing {
+    use super::*;
+
+    /// Runs a shell script in place of a real runtime. Preopen `i` is passed
+    /// as positional parameter `i + 1`.
+    #[derive(Debug)]
+    pub struct Shell(pub &'static str);
+
+    impl WasiRunner for Shell {
+        fn command(
+            &self,
+            _wasm_path: &Path,
+            _working_dir: &Path,
+            preopens: Vec<MappedDir>,
+            envs: Vec<(String, String)>,
+            _args: Vec<String>,
+        ) -> Result<process::Command, eyre::Error> {
+            let mut command = process::Command::new("sh");
+
+            command
+                .arg("-c")
+                .arg(self.0)
+                .arg("sh")
+                .args(preopens.into_iter().map(|preopen| preopen.host_path))
+                .envs(envs);
+
+            Ok(command)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, sync::atomic::AtomicUsize};
+
+    use super::{testing::Shell, *};
+
+    fn wasm_path() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml")
+    }
+
+    fn envs() -> Vec<(String, String)> {
+        vec![
+            ("FOO".to_string(), "bar".to_string()),
+            ("EMPTY".to_string(), "".to_string()),
+        ]
+    }
+
+    fn guest_args() -> Vec<String> {
+        vec!["plain".to_string(), "".to_string(), "with  spaces ".to_string()]
+    }
+
+    fn args_of(command: &process::Command) -> Vec<&OsStr> {
+        command.get_args().collect()
+    }
+
+    fn assert_flag_pairs(runner: &dyn WasiRunner, flag: &str) {
+        let dir = tempfile::tempdir().unwrap();
+        let command = runner
+            .command(&wasm_path(), dir.path(), Vec::new(), envs(), Vec::new())
+            .unwrap();
+        let args = args_of(&command);
+
+        for pair in ["FOO=bar", "EMPTY="] {
+            let idx = args
+                .iter()
+                .position(|arg| *arg == OsStr::new(pair))
+                .unwrap_or_else(|| panic!("missing env `{pair}` in {args:?}"));
+
+            assert_eq!(args[idx - 1], OsStr::new(flag));
+        }
+    }
+
+    #[test]
+    fn node_envs() {
+        let dir = tempfile::tempdir().unwrap();
+
+        Node::default()
+            .command(&wasm_path(), dir.path(), Vec::new(), envs(), Vec::new())
+            .unwrap();
+
+        let glue = fs::read_to_string(dir.path().join("glue.js")).unwrap();
+
+        assert!(glue.contains(r#""FOO": "bar","#), "{glue}");
+        assert!(glue.contains(r#""EMPTY": "","#), "{glue}");
+    }
+
+    #[test]
+    fn wasmedge_envs() {
+        assert_flag_pairs(&Wasmedge::default(), "--env");
+    }
+
+    #[test]
+    fn wasmer_envs() {
+        assert_flag_pairs(&Wasmer::default(), "--env");
+    }
+
+    #[test]
+    fn wasmtime_envs() {
+        assert_flag_pairs(&Wasmtime::default(), "--env");
+    }
+
+    #[test]
+    fn wamr_envs() {
+        let dir = tempfile::tempdir().unwrap();
+        let command = Wamr::default()
+            .command(&wasm_path(), dir.path(), Vec::new(), envs(), Vec::new())
+            .unwrap();
+        let args = args_of(&command);
+
+        assert!(args.contains(&OsStr::new("--env=FOO=bar")), "{args:?}");
+        assert!(args.contains(&OsStr::new("--env=EMPTY=")), "{args:?}");
+    }
+
+    #[test]
+    fn wazero_envs() {
+        assert_flag_pairs(&Wazero::default(), "-env");
+    }
+
+    fn assert_trailing_args(runner: &dyn WasiRunner) {
+        let dir = tempfile::tempdir().unwrap();
+        let command = runner
+            .command(&wasm_path(), dir.path(), Vec::new(), Vec::new(), guest_args())
+            .unwrap();
+        let args = args_of(&command);
+
+        assert_eq!(
+            args[args.len() - 3..],
+            ["plain", "", "with  spaces "].map(OsStr::new),
+            "{args:?}"
+        );
+        assert!(Path::new(args[args.len() - 4]).ends_with("Cargo.toml"), "{args:?}");
+    }
+
+    #[test]
+    fn node_args() {
+        let dir = tempfile::tempdir().unwrap();
+
+        Node::default()
+            .command(&wasm_path(), dir.path(), Vec::new(), Vec::new(), guest_args())
+            .unwrap();
+
+        let glue = fs::read_to_string(dir.path().join("glue.js")).unwrap();
+
+        assert!(
+            glue.contains(
+                r#""plain",
+        "",
+        "with  spaces ","#
+            ),
+            "{glue}"
+        );
+    }
+
+    #[test]
+    fn wasmedge_args() {
+        assert_trailing_args(&Wasmedge::default());
+    }
+
+    #[test]
+    fn wasmer_args() {
+        assert_trailing_args(&Wasmer::default());
+    }
+
+    #[test]
+    fn wasmtime_args() {
+        assert_trailing_args(&Wasmtime::default());
+    }
+
+    #[test]
+    fn wamr_args() {
+        assert_trailing_args(&Wamr::default());
+    }
+
+    #[test]
+    fn wazero_args() {
+        assert_trailing_args(&Wazero::default());
+    }
+
+    #[test]
+    fn wazero_mounts() {
+        let dir = tempfile::tempdir().unwrap();
+        let preopens = vec![
+            MappedDir {
+                name:      "base".to_string(),
+                host_path: dir.path().join("base"),
+            },
+            MappedDir {
+                name:      "other".to_string(),
+                host_path: dir.path().join("other"),
+            },
+        ];
+        let command = Wazero::default()
+            .command(&wasm_path(), dir.path(), preopens, Vec::new(), Vec::new())
+            .unwrap();
+        let args = args_of(&command);
+        let mount = |name: &str| {
+            let mut arg = dir.path().join(name).into_os_string();
+
+            arg.push(":");
+            arg.push(name);
+            arg
+        };
+
+        assert_eq!(command.get_program(), OsStr::new("wazero"));
+        assert_eq!(
+            args[..5],
+            [
+                OsStr::new("run"),
+                OsStr::new("-mount"),
+                &mount("base"),
+                OsStr::new("-mount"),
+                &mount("other"),
+            ]
+        );
+    }
+
+    #[test]
+    fn wasmtime_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let preopens = vec![
+            MappedDir {
+                name:      "base".to_string(),
+                host_path: dir.path().join("base"),
+            },
+            MappedDir {
+                name:      "other".to_string(),
+                host_path: dir.path().join("other"),
+            },
+        ];
+        let command = Wasmtime::default()
+            .command(&wasm_path(), dir.path(), preopens, Vec::new(), Vec::new())
+            .unwrap();
+
+        assert_eq!(command.get_program(), OsStr::new("wasmtime"));
+        assert_eq!(
+            args_of(&command),
+            [
+                OsStr::new("run"),
+                OsStr::new("--dir"),
+                dir.path().join("base").as_os_str(),
+                OsStr::new("--dir"),
+                dir.path().join("other").as_os_str(),
+                canonicalize(wasm_path()).unwrap().as_os_str(),
+            ]
+        );
+    }
+
+    #[test]
+    fn wasmtime_component_flags() {
+        let dir = tempfile::tempdir().unwrap();
+        let command = WasmtimeComponent::default()
+            .with_extra_args(vec!["--fuel".to_string(), "1000".to_string()])
+            .command(&wasm_path(), dir.path(), Vec::new(), Vec::new(), Vec::new())
+            .unwrap();
+
+        assert_eq!(command.get_program(), OsStr::new("wasmtime"));
+        assert_eq!(
+            args_of(&command),
+            [
+                OsStr::new("run"),
+                OsStr::new("-W"),
+                OsStr::new("component-model=y"),
+                OsStr::new("-S"),
+                OsStr::new("preview2=y"),
+                OsStr::new("--fuel"),
+                OsStr::new("1000"),
+                canonicalize(wasm_path()).unwrap().as_os_str(),
+            ]
+        );
+    }
+
+    fn shell_executor(script: &'static str) -> RunningExecutor {
+        shell_executor_with(script, Vec::new(), Vec::new())
+    }
+
+    fn shell_executor_with(
+        script: &'static str,
+        preopens: Vec<MappedDir>,
+        envs: Vec<(String, String)>,
+    ) -> RunningExecutor {
+        let dir = tempfile::tempdir().unwrap();
+
+        RunningExecutor::from_wasi_runner(
+            &Shell(script),
+            &wasm_path(),
+            dir.path(),
+            Arc::new(Mutex::new(io::sink())),
+            preopens,
+            envs,
+            Vec::new(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn call_timeout_kills_hung_executor() {
+        let executor = shell_executor("exec sleep 60").with_call_timeout(Duration::from_millis(100));
+
+        match executor.call(pb::request::Call::default()) {
+            | Err(CallError::Timeout(timeout)) => assert_eq!(timeout, Duration::from_millis(100)),
+            | result => panic!("expected timeout, got {result:?}"),
+        }
+
+        assert!(!executor.child.lock().unwrap().wait().unwrap().success());
+    }
+
+    #[test]
+    fn guest_stdout_is_split_from_responses() {
+        // For each 10-byte empty request, some guest output and then an empty response.
+        let executor = shell_executor(
+            r"head -c 10 >/dev/null
+              printf 'hello\n\000wazzi\000\000\000\000\000\000\000\000\000\000'
+              head -c 10 >/dev/null
+              printf 'world\000wazzi\000\000\000\000\000\000\000\000\000\000'
+              exec cat >/dev/null",
+        );
+
+        executor.call(pb::request::Call::default()).unwrap();
+        assert_eq!(executor.take_guest_stdout(), b"hello\n");
+        assert!(executor.take_guest_stdout().is_empty());
+
+        executor.call(pb::request::Call::default()).unwrap();
+        assert_eq!(executor.take_guest_stdout(), b"world");
+    }
+
+    #[test]
+    fn guest_reads_fed_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut preopens = vec![MappedDir {
+            name:      "base".to_string(),
+            host_path: dir.path().join("base"),
+        }];
+        let mut envs = Vec::new();
+
+        feed_stdin(&dir.path().join("stdin"), b"to the guest", &mut preopens, &mut envs).unwrap();
+        assert_eq!(envs, [(GUEST_STDIN_ENV.to_string(), "4".to_string())]);
+
+        // Echoes the stdin file out of the preopen named by the env var, like
+        // the executor would for an `fd_read` on fd 0.
+        let executor = shell_executor_with(
+            r#"head -c 10 >/dev/null
+               eval "cat \"\${$((WAZZI_STDIN_FD - 2))}/stdin\""
+               printf '\000wazzi\000\000\000\000\000\000\000\000\000\000'
+               exec cat >/dev/null"#,
+            preopens,
+            envs,
+        );
+
+        executor.call(pb::request::Call::default()).unwrap();
+        assert_eq!(executor.take_guest_stdout(), b"to the guest");
+    }
+
+    #[test]
+    fn coverage_is_merged_across_calls() {
+        // Two responses reporting coverage bitmaps 01 00 03 and 00 02.
+        let executor = shell_executor(
+            r"head -c 10 >/dev/null
+              printf '\000wazzi\000\000\007\000\000\000\000\000\000\000\012\005\052\003\001\000\003'
+              head -c 10 >/dev/null
+              printf '\000wazzi\000\000\006\000\000\000\000\000\000\000\012\004\052\002\000\002'
+              exec cat >/dev/null",
+        );
+
+        executor.call(pb::request::Call::default()).unwrap();
+        executor.call(pb::request::Call::default()).unwrap();
+        assert_eq!(executor.take_coverage(), [1, 2, 3]);
+        assert!(executor.take_coverage().is_empty());
+    }
+
+    #[test]
+    fn exit_is_reported_with_status() {
+        let executor = shell_executor("head -c 10 >/dev/null; exit 1");
+
+        match executor.call(pb::request::Call::default()) {
+            | Err(CallError::ProcessExited(status)) => assert_eq!(status.code(), Some(1)),
+            | result => panic!("expected exit, got {result:?}"),
+        }
+
+        assert_eq!(executor.exit_status().unwrap().code(), Some(1));
+    }
+
+    #[test]
+    fn stdout_closed_mid_message() {
+        // Starts a response, then closes stdout while staying alive.
+        let executor = shell_executor(
+            r"head -c 10 >/dev/null
+              printf '\000wazzi\000\000\020\000'
+              exec >&- sleep 60",
+        );
+
+        match executor.call(pb::request::Call::default()) {
+            | Err(CallError::PipeClosed) => (),
+            | result => panic!("expected closed pipe, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn oversized_response_is_rejected() {
+        // Declares a response of about a terabyte.
+        let executor = shell_executor(
+            r"head -c 10 >/dev/null
+              printf '\000wazzi\000\000\377\377\377\377\377\000\000\000'
+              exec cat >/dev/null",
+        )
+        .with_max_message_size(1024);
+
+        match executor.call(pb::request::Call::default()) {
+            | Err(CallError::MessageTooLarge { size, max }) => assert_eq!((size, max), (0xff_ffff_ffff, 1024)),
+            | result => panic!("expected oversized message, got {result:?}"),
+        }
+    }
+
+    /// Fails to spawn with `kind` until it has failed `failures` times, then
+    /// runs like `Shell`.
+    #[derive(Debug)]
+    struct Flaky {
+        kind:     io::ErrorKind,
+        failures: usize,
+        attempts: AtomicUsize,
+    }
+
+    impl WasiRunner for Flaky {
+        fn command(
+            &self,
+            wasm_path: &Path,
+            working_dir: &Path,
+            preopens: Vec<MappedDir>,
+            envs: Vec<(String, String)>,
+            args: Vec<String>,
+        ) -> Result<process::Command, eyre::Error> {
+            Shell("exec cat >/dev/null").command(wasm_path, working_dir, preopens, envs, args)
+        }
+
+        fn run(
+            &self,
+            wasm_path: &Path,
+            working_dir: &Path,
+            preopens: Vec<MappedDir>,
+            envs: Vec<(String, String)>,
+            args: Vec<String>,
+        ) -> Result<process::Child, eyre::Error> {
+            if self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(io::Error::from(self.kind)).wrap_err("failed to spawn command");
+            }
+
+            Shell("exec cat >/dev/null").run(wasm_path, working_dir, preopens, envs, args)
+        }
+    }
+
+    #[test]
+    fn transient_spawn_failures_are_retried() {
+        let spawn = |kind, failures| {
+            let dir = tempfile::tempdir().unwrap();
+            let runner = Flaky {
+                kind,
+                failures,
+                attempts: Default::default(),
+            };
+            let result = RunningExecutor::from_wasi_runner(
+                &runner,
+                &wasm_path(),
+                dir.path(),
+                Arc::new(Mutex::new(io::sink())),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+            );
+
+            (result, runner.attempts.into_inner())
+        };
+
+        let (result, attempts) = spawn(io::ErrorKind::WouldBlock, 2);
+
+        assert!(result.is_ok());
+        assert_eq!(attempts, 3);
+
+        let (result, attempts) = spawn(io::ErrorKind::NotFound, 2);
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+
+        let (result, attempts) = spawn(io::ErrorKind::WouldBlock, usize::MAX);
+
+        assert!(result.is_err());
+        assert_eq!(attempts, SPAWN_ATTEMPTS as usize);
+    }
+
+    #[test]
+    fn drop_kills_executor() {
+        let executor = shell_executor("exec sleep 60");
+        let pid = executor.child.lock().unwrap().id();
+
+        assert!(Path::new("/proc").join(pid.to_string()).exists());
+
+        drop(executor);
+
+        assert!(!Path::new("/proc").join(pid.to_string()).exists());
+    }
+}
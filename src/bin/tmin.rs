@@ -0,0 +1,360 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use clap::Parser;
+use color_eyre::eyre;
+use eyre::{eyre as err, Context};
+use itertools::Itertools;
+use petgraph::{
+    dot::Dot,
+    graph::{DiGraph, NodeIndex},
+    visit::{Dfs, Reversed},
+};
+use serde::{Deserialize, Serialize};
+use wazzi::{
+    apply_env_initializers,
+    execute_call,
+    graph::{build_graph, CallGraph, Edge, Node},
+    normalization::{runtime_by_name, Runtime},
+    resource::HighLevelValue,
+    spec::Spec,
+    Call,
+    RuntimeContext,
+};
+use wazzi_runners::{MappedDir, RunningExecutor};
+use wazzi_store::{read_trace, recorded_runtimes};
+
+fn main() -> Result<(), eyre::Error> {
+    color_eyre::install()?;
+
+    let cmd = Command::parse();
+    let runtime = match cmd.runtime {
+        | Some(runtime) => runtime,
+        | None => recorded_runtimes(&cmd.run)
+            .wrap_err("failed to read runtimes dir")?
+            .into_iter()
+            .next()
+            .ok_or_else(|| err!("run has no runtimes"))?,
+    };
+    let runtime_dir = cmd.run.join("runtimes").join(&runtime);
+    let calls: Vec<Call> = read_trace(&runtime_dir).wrap_err("failed to read trace")?;
+    let CallGraph { graph, call_nodes, .. } = build_graph(&calls);
+    // Runs stop at the first divergence, so the last call is the one to reproduce.
+    let target = *call_nodes.last().ok_or_else(|| err!("trace is empty"))?;
+    let mut min_calls = min_calls(&graph, target);
+
+    if cmd.ddmin {
+        let spec = Spec::preview1(&fs::read_to_string(&cmd.spec).wrap_err("failed to read spec file")?)?;
+        let mut traces = BTreeMap::new();
+
+        for runtime in recorded_runtimes(&cmd.run).wrap_err("failed to read runtimes dir")? {
+            let trace: Vec<Call> =
+                read_trace(&cmd.run.join("runtimes").join(&runtime)).wrap_err("failed to read trace")?;
+
+            traces.insert(runtime, trace);
+        }
+
+        let executor =
+            fs::canonicalize(&cmd.executor).wrap_err(format!("executor {} not found", cmd.executor.display()))?;
+        let mut oracle = Oracle::new(spec, executor, traces)?;
+        let target_idx = min_calls.pop().unwrap();
+        let mut prefix = ddmin(min_calls, |prefix| {
+            let mut candidate = prefix.to_vec();
+
+            candidate.push(target_idx);
+            oracle.reproduces(&candidate)
+        });
+
+        prefix.push(target_idx);
+        min_calls = prefix;
+    }
+
+    println!("Keeping {} of {} calls.", min_calls.len(), calls.len());
+
+    fs::write(
+        runtime_dir.join("trace.dot"),
+        format!("{:?}", Dot::with_config(&graph, &[])),
+    )?;
+
+    let out_dir = cmd.run.join("minimized").join(&runtime);
+
+    fs::create_dir_all(&out_dir)?;
+
+    for (i, &action_idx) in min_calls.iter().enumerate() {
+        serde_json::to_writer_pretty(
+            BufWriter::new(File::create(out_dir.join(format!("{i:04}.json")))?),
+            &MinimizedCall {
+                action_idx,
+                call: calls[action_idx].clone(),
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+#[derive(clap::Parser, Debug)]
+struct Command {
+    /// Directory of the run to minimize, i.e. `<store>/runs/<id>`.
+    #[arg()]
+    run: PathBuf,
+
+    /// Runtime whose trace to minimize. Defaults to the first one recorded.
+    #[arg(long)]
+    runtime: Option<String>,
+
+    /// Further shrink the trace by re-executing candidate call sequences
+    /// against every recorded runtime.
+    #[arg(long)]
+    ddmin: bool,
+
+    /// Spec the run was fuzzed with. Only needed with `--ddmin`.
+    #[arg(long, default_value = "preview1.witx")]
+    spec: PathBuf,
+
+    /// Executor module to re-execute calls with. Only needed with `--ddmin`.
+    #[arg(long, default_value = "target/release/wazzi-executor.wasm")]
+    executor: PathBuf,
+}
+
+/// A retained call along with its index in the original trace.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+struct MinimizedCall {
+    action_idx: usize,
+    call:       Call,
+}
+
+/// Trace indices of the calls that `target` transitively depends on through
+/// resources, including `target` itself, in trace order.
+fn min_calls(graph: &DiGraph<Node, Edge>, target: NodeIndex) -> Vec<usize> {
+    let reversed = Reversed(graph);
+    let mut dfs = Dfs::new(reversed, target);
+    let mut idxs = Vec::new();
+
+    while let Some(node_idx) = dfs.next(reversed) {
+        if let Node::Call { idx, .. } = graph[node_idx] {
+            idxs.push(idx);
+        }
+    }
+
+    idxs.into_iter().sorted().collect()
+}
+
+/// Re-executes call subsequences to check whether the divergence of the
+/// recorded target call still shows up.
+struct Oracle {
+    spec:     Spec,
+    executor: PathBuf,
+    traces:   BTreeMap<String, Vec<Call>>,
+    runtimes: Vec<(String, Box<dyn Runtime>)>,
+    expected: Vec<bool>,
+}
+
+impl Oracle {
+    fn new(spec: Spec, executor: PathBuf, traces: BTreeMap<String, Vec<Call>>) -> Result<Self, eyre::Error> {
+        let mut runtimes = Vec::with_capacity(traces.len());
+        let mut expected = Vec::with_capacity(traces.len());
+
+        for (name, trace) in &traces {
+            let runtime = runtime_by_name(name, None, Vec::new()).ok_or_else(|| err!("unknown runtime {name}"))?;
+            let target = trace.last().ok_or_else(|| err!("trace of {name} is empty"))?;
+
+            runtimes.push((name.clone(), runtime));
+            expected.push(target.errno == Some(0));
+        }
+
+        Ok(Self {
+            spec,
+            executor,
+            traces,
+            runtimes,
+            expected,
+        })
+    }
+
+    /// Whether running the calls at `action_idxs` on each runtime ends with the
+    /// same success or failure pattern as the recorded run.
+    fn reproduces(&mut self, action_idxs: &[usize]) -> bool {
+        let mut outcomes = Vec::with_capacity(self.runtimes.len());
+
+        for (name, runtime) in &self.runtimes {
+            let calls = action_idxs.iter().map(|&i| &self.traces[name][i]).collect_vec();
+
+            match run_calls(&self.spec, name, runtime.as_ref(), &self.executor, &calls) {
+                | Ok(errno) => outcomes.push(errno == Some(0)),
+                | Err(err) => {
+                    tracing::warn!(runtime = name, ?err, "Candidate failed to execute.");
+
+                    return false;
+                },
+            }
+        }
+
+        outcomes == self.expected
+    }
+}
+
+/// Runs `calls` in a fresh executor, returning the errno of the last one.
+/// Resources are passed by index, so a handle a runtime numbers differently
+/// this time around is still remapped. Fails if a call needs a resource that
+/// no earlier call in `calls` creates.
+fn run_calls(
+    spec: &Spec,
+    name: &str,
+    runtime: &dyn Runtime,
+    executor_bin: &Path,
+    calls: &[&Call],
+) -> Result<Option<i32>, eyre::Error> {
+    let dir = tempfile::tempdir()?;
+    let base = dir.path().join("base");
+
+    fs::create_dir(&base)?;
+
+    let mapped_dirs = vec![MappedDir {
+        name:      "base".to_string(),
+        host_path: base,
+    }];
+    let executor = RunningExecutor::from_wasi_runner(
+        runtime,
+        executor_bin,
+        dir.path(),
+        Arc::new(Mutex::new(io::sink())),
+        mapped_dirs.clone(),
+        Vec::new(),
+        Vec::new(),
+    )?;
+    let initializer = runtime.initialize_state(name.to_string(), spec, &executor, mapped_dirs)?;
+    let (_env, rtctxs, _preopens) = apply_env_initializers(spec, &[initializer]);
+    let mut rtctx = rtctxs.into_iter().next().unwrap();
+    let mut errno = None;
+
+    for call in calls {
+        let function = spec
+            .get_function(&call.function)
+            .ok_or_else(|| err!("unknown function {}", call.function))?;
+        let params = replay_params(&rtctx, call)?;
+        let results;
+
+        (errno, results, _) = execute_call(spec, &rtctx, function, params, &executor)?;
+
+        for (result, value) in call.results.iter().flatten().zip(results.into_iter().flatten()) {
+            if let Some(idx) = result.resource_idx {
+                rtctx.resources.insert(idx, value);
+            }
+        }
+    }
+
+    Ok(errno)
+}
+
+/// The params of a recorded `call`, with resources passed by index.
+fn replay_params(rtctx: &RuntimeContext, call: &Call) -> Result<Vec<HighLevelValue>, eyre::Error> {
+    call.params
+        .iter()
+        .map(|param| match param.resource_idx {
+            | Some(idx) if rtctx.resources.contains_key(&idx) => Ok(HighLevelValue::Resource(idx)),
+            | Some(idx) => Err(err!("{} needs {idx:?}, which no earlier call creates", call.function)),
+            | None => Ok(HighLevelValue::Concrete(param.value.clone())),
+        })
+        .collect()
+}
+
+/// Delta debugging: shrinks `items` to a subsequence for which `reproduces`
+/// still holds, such that removing any single remaining item breaks it.
+fn ddmin<T: Clone>(mut items: Vec<T>, mut reproduces: impl FnMut(&[T]) -> bool) -> Vec<T> {
+    if reproduces(&[]) {
+        return Vec::new();
+    }
+
+    let mut n = 2;
+
+    while items.len() >= 2 {
+        let chunks = items.chunks(items.len().div_ceil(n)).map(<[T]>::to_vec).collect_vec();
+
+        if let Some(chunk) = chunks.iter().find(|chunk| reproduces(chunk)) {
+            items = chunk.clone();
+            n = 2;
+
+            continue;
+        }
+
+        let complement = (0..chunks.len())
+            .map(|i| {
+                chunks
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .flat_map(|(_, chunk)| chunk.iter().cloned())
+                    .collect_vec()
+            })
+            .find(|complement| reproduces(complement));
+
+        if let Some(complement) = complement {
+            items = complement;
+            n = (n - 1).max(2);
+
+            continue;
+        }
+
+        if n >= items.len() {
+            break;
+        }
+
+        n = (n * 2).min(items.len());
+    }
+
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use wazzi::spec::WasiValue;
+
+    use super::*;
+
+    #[test]
+    fn keeps_calls_on_a_path_to_the_target() {
+        let calls = vec![
+            Call::on_resources("path_open", &[0], &[1]),
+            Call::on_resources("fd_write", &[0], &[]),
+            Call::on_resources("path_open", &[0], &[2]),
+            Call::on_resources("fd_sync", &[2], &[]),
+            Call::on_resources("fd_renumber", &[1, 2], &[]),
+        ];
+        let CallGraph { graph, call_nodes, .. } = build_graph(&calls);
+
+        assert_eq!(min_calls(&graph, *call_nodes.last().unwrap()), vec![0, 2, 4]);
+    }
+
+    #[test]
+    fn ddmin_drops_irrelevant_calls() {
+        // Only the even calls are needed to reproduce the divergence.
+        let mut runs = 0;
+        let kept = ddmin((0..16).collect(), |candidate: &[usize]| {
+            runs += 1;
+            (0..16).step_by(2).all(|i| candidate.contains(&i))
+        });
+
+        assert_eq!(kept, (0..16).step_by(2).collect_vec());
+        assert!(runs < 2usize.pow(16));
+    }
+
+    #[test]
+    fn replay_passes_resources_by_index() {
+        let preopen = serde_json::from_str("0").unwrap();
+        let mut rtctx = RuntimeContext::new();
+
+        rtctx.resources.insert(preopen, WasiValue::Handle(5));
+
+        assert_eq!(
+            replay_params(&rtctx, &Call::on_resources("fd_sync", &[0], &[])).unwrap(),
+            [HighLevelValue::Resource(preopen)]
+        );
+        assert!(replay_params(&rtctx, &Call::on_resources("fd_sync", &[1], &[])).is_err());
+    }
+}
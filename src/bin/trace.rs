@@ -1,8 +1,4 @@
-use std::{
-    collections::{HashMap, HashSet},
-    fs::{self, File},
-    path::PathBuf,
-};
+use std::{collections::HashMap, fs, path::PathBuf};
 
 use clap::Parser;
 use color_eyre::eyre;
@@ -14,7 +10,12 @@ use petgraph::{
     Direction,
 };
 use serde::Serialize;
-use wazzi::{Call, ResourceIdx};
+use wazzi::{
+    graph::{build_graph, CallGraph, Edge, Node},
+    Call,
+    ResourceIdx,
+};
+use wazzi_store::read_trace;
 
 #[derive(Serialize, PartialEq, Eq, Clone, Debug)]
 struct Analysis {
@@ -79,67 +80,20 @@ fn main() -> Result<(), eyre::Error> {
 
         let runtimes = runtimes.as_ref().unwrap();
         let runtime = runtimes.first().unwrap();
-        let trace_dir = runtimes_dir.join(runtime).join("trace");
-        let call_entries = fs::read_dir(&trace_dir)?
-            .collect::<Result<Vec<_>, _>>()?
-            .into_iter()
-            .map(|entry| entry.file_name().into_string().unwrap())
-            .map(|file_name| {
-                (
-                    file_name.strip_suffix(".json").unwrap().parse::<usize>().unwrap(),
-                    file_name,
-                )
-            })
-            .sorted_by(|(i, _), (j, _)| Ord::cmp(i, j))
-            .collect_vec();
-        let mut trace_len = 0;
-        let mut graph = DiGraph::new();
-        let mut resource_node_map = HashMap::new();
-        let mut init_resources = HashSet::new();
-        let ncalls = call_entries.len();
+        let calls: Vec<Call> = read_trace(&runtimes_dir.join(runtime))
+            .wrap_err_with(|| format!("failed to read trace of run {run_idx}"))?;
+        let ncalls = calls.len();
 
         println!("Analyzing run {nruns}.");
 
-        for (idx, file_name) in call_entries {
-            trace_len += 1;
-            total_num_calls += 1;
-
-            let call_file = File::open(trace_dir.join(file_name)).unwrap();
-            let call: Call = serde_json::from_reader(&call_file).unwrap();
-            let call_node_idx = graph.add_node(Node::Call {
-                idx,
-                name: call.function,
-            });
-
-            for param in call.params {
-                if let Some(resource_idx) = param.resource_idx {
-                    let resource_node_idx = match resource_node_map.get(&resource_idx) {
-                        | Some(idx) => *idx,
-                        | None => {
-                            let idx = graph.add_node(Node::Resource { idx: resource_idx });
-
-                            resource_node_map.insert(resource_idx, idx);
-                            init_resources.insert(resource_idx);
-
-                            idx
-                        },
-                    };
-
-                    graph.add_edge(resource_node_idx, call_node_idx, Edge::Param);
-                }
-            }
-
-            if let Some(results) = call.results {
-                for result in results {
-                    if let Some(resource_idx) = result.resource_idx {
-                        let resource_node_idx = graph.add_node(Node::Resource { idx: resource_idx });
+        let CallGraph {
+            graph,
+            resource_nodes: resource_node_map,
+            init_resources,
+            ..
+        } = build_graph(&calls);
 
-                        resource_node_map.insert(resource_idx, resource_node_idx);
-                        graph.add_edge(call_node_idx, resource_node_idx, Edge::Result);
-                    }
-                }
-            }
-        }
+        total_num_calls += ncalls;
 
         runs.push(RunMetadata { id: run_idx, ncalls });
 
@@ -197,11 +151,11 @@ fn main() -> Result<(), eyre::Error> {
         )
         .unwrap();
 
-        if trace_len > max_trace_len {
+        if ncalls > max_trace_len {
             max_trace_len_idx = run_idx;
         }
 
-        max_trace_len = max_trace_len.max(trace_len);
+        max_trace_len = max_trace_len.max(ncalls);
         total_max_calls += most_calls;
         max_resource_depth = max_resource_depth.max(max_resource_depth_);
         total_max_resource_depth += max_resource_depth_;
@@ -255,14 +209,38 @@ struct Command {
     out_dir: PathBuf,
 }
 
-#[derive(PartialEq, Eq, Clone, Debug)]
-enum Node {
-    Resource { idx: ResourceIdx },
-    Call { idx: usize, name: String },
-}
+#[cfg(test)]
+mod tests {
+    use wazzi_store::{RuntimeStore, TraceFormat};
+
+    use super::*;
+
+    #[test]
+    fn compressed_traces_are_analyzed() {
+        let calls = vec![
+            Call::on_resources("path_open", &[0], &[1]),
+            Call::on_resources("fd_close", &[1], &[]),
+        ];
 
-#[derive(PartialEq, Eq, Clone, Debug)]
-enum Edge {
-    Param,
-    Result,
+        for format in [TraceFormat::JsonPretty, TraceFormat::Bincode] {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("wasmtime");
+            let mut store = RuntimeStore::new(&path, true, format, true).unwrap();
+
+            for call in &calls {
+                store.record_call(call.clone()).unwrap();
+            }
+
+            let CallGraph {
+                graph,
+                resource_nodes,
+                init_resources,
+                ..
+            } = build_graph(&read_trace::<Call>(&path).unwrap());
+
+            assert_eq!(graph.node_count(), 4, "{format:?}");
+            assert_eq!(resource_nodes.len(), 2);
+            assert_eq!(init_resources.len(), 1);
+        }
+    }
 }
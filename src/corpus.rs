@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use rand::Rng;
+
+/// What a run reached: the edges each runtime reported and the functions it
+/// called, which stand in for edges when the runtimes report none. Edges are
+/// kept per runtime, since each runtime numbers its own.
+#[derive(PartialEq, Eq, Clone, Default, Debug)]
+pub struct Coverage {
+    edges:     BTreeMap<String, Vec<u8>>,
+    functions: BTreeSet<String>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merges in a bitmap of `runtime` as returned by
+    /// `RunningExecutor::take_coverage`.
+    pub fn add_edges(&mut self, runtime: &str, edges: &[u8]) {
+        let seen = self.edges.entry(runtime.to_string()).or_default();
+
+        if seen.len() < edges.len() {
+            seen.resize(edges.len(), 0);
+        }
+
+        for (edge, &hits) in seen.iter_mut().zip(edges) {
+            *edge = (*edge).max(hits);
+        }
+    }
+
+    pub fn add_function(&mut self, function: &str) {
+        self.functions.insert(function.to_string());
+    }
+
+    /// Whether `self` reaches an edge of any runtime, or a function, that
+    /// `seen` doesn't.
+    fn is_new(&self, seen: &Coverage) -> bool {
+        let new_edge = self.edges.iter().any(|(runtime, edges)| {
+            let seen = seen.edges.get(runtime).map(Vec::as_slice).unwrap_or_default();
+
+            edges
+                .iter()
+                .enumerate()
+                .any(|(i, &hits)| hits != 0 && seen.get(i).is_none_or(|&seen| seen == 0))
+        });
+
+        new_edge || !self.functions.is_subset(&seen.functions)
+    }
+
+    fn merge(&mut self, other: &Coverage) {
+        for (runtime, edges) in &other.edges {
+            self.add_edges(runtime, edges);
+        }
+
+        self.functions.extend(other.functions.iter().cloned());
+    }
+}
+
+/// Inputs whose runs reached new coverage, for new inputs to be mutated from.
+#[derive(Default, Debug)]
+pub struct Corpus {
+    seen:   Coverage,
+    inputs: Vec<Vec<u8>>,
+}
+
+impl Corpus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Keeps `input` if its run reached coverage no earlier run did. Returns
+    /// whether it was kept.
+    pub fn observe(&mut self, input: &[u8], coverage: &Coverage) -> bool {
+        if !coverage.is_new(&self.seen) {
+            return false;
+        }
+
+        self.seen.merge(coverage);
+        self.inputs.push(input.to_vec());
+
+        true
+    }
+
+    /// Fills `buf` with a kept input that has a few bytes changed. Returns
+    /// false, leaving `buf` as is, if nothing has been kept yet.
+    pub fn mutate(&self, rng: &mut impl Rng, buf: &mut [u8]) -> bool {
+        if self.inputs.is_empty() || buf.is_empty() {
+            return false;
+        }
+
+        let input = &self.inputs[rng.gen_range(0..self.inputs.len())];
+        let len = input.len().min(buf.len());
+
+        buf[..len].copy_from_slice(&input[..len]);
+        rng.fill(&mut buf[len..]);
+
+        for _ in 0..rng.gen_range(1..=8) {
+            let i = rng.gen_range(0..buf.len());
+
+            buf[i] = rng.gen();
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    use rand::{rngs::StdRng, SeedableRng};
+    use wazzi_runners::{testing::Shell, RunningExecutor};
+
+    use super::*;
+
+    #[test]
+    fn executor_coverage_drives_corpus_growth() {
+        // Answers three calls with coverage 01, 01 and 00 01.
+        let dir = tempfile::tempdir().unwrap();
+        let executor = RunningExecutor::from_wasi_runner(
+            &Shell(
+                r"head -c 10 >/dev/null
+                  printf '\000wazzi\000\000\005\000\000\000\000\000\000\000\012\003\052\001\001'
+                  head -c 10 >/dev/null
+                  printf '\000wazzi\000\000\005\000\000\000\000\000\000\000\012\003\052\001\001'
+                  head -c 10 >/dev/null
+                  printf '\000wazzi\000\000\006\000\000\000\000\000\000\000\012\004\052\002\000\001'
+                  exec cat >/dev/null",
+            ),
+            Path::new("executor.wasm"),
+            dir.path(),
+            Arc::new(Mutex::new(io::sink())),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+        )
+        .unwrap();
+        let mut corpus = Corpus::new();
+        let mut kept = Vec::new();
+
+        for input in [b"a", b"b", b"c"] {
+            let mut coverage = Coverage::new();
+
+            executor.call(pb::request::Call::default()).unwrap();
+            coverage.add_edges("wasmtime", &executor.take_coverage());
+            kept.push(corpus.observe(input, &coverage));
+        }
+
+        assert_eq!(kept, [true, false, true]);
+        assert_eq!(corpus.len(), 2);
+    }
+
+    #[test]
+    fn only_new_coverage_grows_the_corpus() {
+        let mut corpus = Corpus::new();
+        let coverage = |edges: &[u8], functions: &[&str]| {
+            let mut coverage = Coverage::new();
+
+            coverage.add_edges("wasmtime", edges);
+            functions.iter().for_each(|function| coverage.add_function(function));
+            coverage
+        };
+
+        assert!(corpus.observe(b"a", &coverage(&[1, 0, 0], &["fd_write"])));
+        assert!(!corpus.observe(b"b", &coverage(&[3], &["fd_write"])));
+        assert!(corpus.observe(b"c", &coverage(&[0, 0, 1], &[])));
+        assert!(corpus.observe(b"d", &coverage(&[], &["fd_read"])));
+        assert!(!corpus.observe(b"e", &coverage(&[1, 0, 1], &["fd_read", "fd_write"])));
+        assert_eq!(corpus.inputs, [b"a", b"c", b"d"]);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut buf = [0; 16];
+
+        assert!(corpus.mutate(&mut rng, &mut buf));
+        assert!(!Corpus::new().mutate(&mut rng, &mut buf));
+    }
+
+    #[test]
+    fn edges_are_new_per_runtime() {
+        let mut corpus = Corpus::new();
+        let mut wasmtime = Coverage::new();
+
+        wasmtime.add_edges("wasmtime", &[1]);
+
+        let mut both = wasmtime.clone();
+
+        // The same edge index in another runtime is a different edge.
+        both.add_edges("wasmer", &[1]);
+
+        assert!(corpus.observe(b"a", &wasmtime));
+        assert!(corpus.observe(b"b", &both));
+        assert!(!corpus.observe(b"c", &both));
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::{HashMap, HashSet};
+
+use petgraph::graph::{DiGraph, NodeIndex};
+
+use crate::{Call, ResourceIdx};
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Node {
+    Resource { idx: ResourceIdx },
+    Call { idx: usize, name: String },
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Edge {
+    Param,
+    Result,
+}
+
+/// Links each call of a trace to the resources it consumes and produces.
+#[derive(Clone, Debug)]
+pub struct CallGraph {
+    pub graph:          DiGraph<Node, Edge>,
+    /// The node of every call, in trace order.
+    pub call_nodes:     Vec<NodeIndex>,
+    /// The latest node of every resource.
+    pub resource_nodes: HashMap<ResourceIdx, NodeIndex>,
+    /// Resources that existed before the first call, like preopens.
+    pub init_resources: HashSet<ResourceIdx>,
+}
+
+pub fn build_graph(calls: &[Call]) -> CallGraph {
+    let mut graph = DiGraph::new();
+    let mut resource_nodes = HashMap::new();
+    let mut init_resources = HashSet::new();
+    let mut call_nodes = Vec::with_capacity(calls.len());
+
+    for (idx, call) in calls.iter().enumerate() {
+        let call_node_idx = graph.add_node(Node::Call {
+            idx,
+            name: call.function.clone(),
+        });
+
+        for resource_idx in call.params.iter().filter_map(|param| param.resource_idx) {
+            let resource_node_idx = *resource_nodes.entry(resource_idx).or_insert_with(|| {
+                init_resources.insert(resource_idx);
+                graph.add_node(Node::Resource { idx: resource_idx })
+            });
+
+            graph.add_edge(resource_node_idx, call_node_idx, Edge::Param);
+        }
+
+        for resource_idx in call.results.iter().flatten().filter_map(|result| result.resource_idx) {
+            let resource_node_idx = graph.add_node(Node::Resource { idx: resource_idx });
+
+            resource_nodes.insert(resource_idx, resource_node_idx);
+            graph.add_edge(call_node_idx, resource_node_idx, Edge::Result);
+        }
+
+        call_nodes.push(call_node_idx);
+    }
+
+    CallGraph {
+        graph,
+        call_nodes,
+        resource_nodes,
+        init_resources,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use petgraph::Direction;
+
+    use super::*;
+
+    #[test]
+    fn calls_link_through_resources() {
+        let calls = vec![
+            Call::on_resources("path_open", &[0], &[1]),
+            Call::on_resources("fd_write", &[1], &[]),
+            Call::on_resources("fd_close", &[1], &[]),
+        ];
+        let CallGraph {
+            graph,
+            call_nodes,
+            resource_nodes,
+            init_resources,
+        } = build_graph(&calls);
+        let fd = resource_nodes[&ResourceIdx(1)];
+
+        assert_eq!(call_nodes.len(), 3);
+        assert_eq!(init_resources, HashSet::from([ResourceIdx(0)]));
+        assert_eq!(
+            graph.neighbors_directed(fd, Direction::Incoming).collect::<Vec<_>>(),
+            [call_nodes[0]]
+        );
+        assert_eq!(graph.neighbors_directed(fd, Direction::Outgoing).count(), 2);
+    }
+}
@@ -1,30 +1,72 @@
 extern crate wazzi_executor_pb_rust as pb;
 
+pub mod corpus;
+pub mod graph;
 pub mod normalization;
 pub mod resource;
 pub mod spec;
 mod strategy;
 
 pub use resource::ResourceIdx;
-pub use strategy::{CallStrategy, StatefulStrategy, StatelessStrategy};
+pub use strategy::{CallStrategy, FunctionFilter, ResumeStrategy, ReuseStrategy, StatefulStrategy, StatelessStrategy};
 
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    io,
     path::PathBuf,
+    time::Duration,
 };
 
-use eyre::eyre as err;
+use eyre::Context as _;
 use itertools::Itertools;
-use resource::{HighLevelValue, Resource, Resources};
+use num_bigint::BigInt;
+use resource::{HighLevelValue, Resource, ResourceOrigin, Resources};
 use serde::{Deserialize, Serialize};
-use spec::{Function, RecordValue, Spec, TypeDef, WasiType, WasiValue};
+use spec::{witx::slang, Function, RecordValue, Spec, TypeDef, TypeRef, WasiType, WasiValue};
 use wazzi_executor_pb_rust::WasiFunc;
 use wazzi_runners::RunningExecutor;
+use wazzi_store::RecordedCall;
 
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct EnvironmentInitializer {
-    name:     String,
-    preopens: Vec<(String, PathBuf, WasiValue)>,
+    name:       String,
+    preopens:   Vec<(String, PathBuf, WasiValue)>,
+    /// Files to create under every preopen, relative to it.
+    seed_files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl EnvironmentInitializer {
+    pub(crate) fn new(name: String, preopens: Vec<(String, PathBuf, WasiValue)>) -> Self {
+        Self {
+            name,
+            preopens,
+            seed_files: Vec::new(),
+        }
+    }
+
+    pub fn with_seed_files(mut self, seed_files: Vec<(PathBuf, Vec<u8>)>) -> Self {
+        self.seed_files = seed_files;
+        self
+    }
+
+    /// Writes the seed files into each preopen's host directory. Files aren't
+    /// resources until opened, so nothing is registered for them.
+    pub fn write_seed_files(&self) -> Result<(), io::Error> {
+        for (_name, host_path, _fd) in &self.preopens {
+            for (path, content) in &self.seed_files {
+                let path = host_path.join(path);
+
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+
+                fs::write(path, content)?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 pub fn apply_env_initializers(
@@ -33,7 +75,13 @@ pub fn apply_env_initializers(
 ) -> (Environment, Vec<RuntimeContext>, BTreeMap<ResourceIdx, PathBuf>) {
     let mut resources: Resources = Default::default();
     let mut fds: BTreeSet<ResourceIdx> = Default::default();
-    let fd_tdef = spec.types.get_by_key("fd").unwrap();
+    // Preopens are resources of the spec's stateful handle type.
+    let fd_tdef = spec
+        .types
+        .iter()
+        .map(|(_name, tdef)| tdef)
+        .find(|tdef| tdef.wasi == WasiType::Handle && tdef.state.is_some())
+        .expect("spec has no stateful handle type");
     let fd_type = fd_tdef.state.as_ref().unwrap().record().unwrap();
     let mut preopen_state_members: Vec<WasiValue> = Default::default();
     let mut reverse_resource_index_fd = HashMap::new();
@@ -41,24 +89,14 @@ pub fn apply_env_initializers(
     let mut preopens = BTreeMap::new();
 
     for member in &fd_type.members {
-        preopen_state_members.push(match member.name.as_str() {
-            | "offset" => WasiValue::U64(0),
-            | "flags" => member
-                .tref
-                .resolve(spec)
-                .wasi
-                .flags()
-                .unwrap()
-                .value([].into_iter().collect()),
-            | "type" => member
-                .tref
-                .resolve(spec)
-                .wasi
+        let wasi = &member.tref.resolve(spec).wasi;
+
+        preopen_state_members.push(match &member.preopen {
+            | Some(case) => wasi
                 .variant()
-                .unwrap()
-                .value_from_name("directory", None)
-                .unwrap(),
-            | _ => member.tref.resolve(spec).wasi.zero_value(spec),
+                .and_then(|variant| variant.value_from_name(case, None))
+                .unwrap_or_else(|| panic!("`{}` has no preopen case `{case}`", member.name)),
+            | None => wasi.zero_value(spec),
         });
     }
 
@@ -73,7 +111,10 @@ pub fn apply_env_initializers(
                     let state = WasiValue::Record(RecordValue {
                         members: preopen_state_members.clone(),
                     });
-                    let resource_idx = resources.push(Resource { state: state.clone() });
+                    let resource_idx = resources.push(Resource {
+                        state:  state.clone(),
+                        origin: None,
+                    });
 
                     reverse_resource_index_fd.insert(state, resource_idx);
                     fds.insert(resource_idx);
@@ -100,14 +141,18 @@ pub fn apply_env_initializers(
     (
         Environment {
             resources,
-            resources_by_types: [("fd".to_string(), fds.clone())].into_iter().collect(),
-            resources_types: fds.into_iter().map(|fd| (fd, "fd".to_string())).collect(),
+            resources_by_types: [(fd_tdef.name.clone(), fds.clone())].into_iter().collect(),
+            resources_types: fds.into_iter().map(|fd| (fd, fd_tdef.name.clone())).collect(),
         },
         ctxs,
         preopens,
     )
 }
 
+/// An `Environment` as it was when `Environment::snapshot` was called.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct EnvironmentSnapshot(Environment);
+
 #[derive(Serialize, PartialEq, Eq, Clone, Debug)]
 pub struct Environment {
     resources:          Resources,
@@ -135,13 +180,70 @@ impl Environment {
         resource_idx
     }
 
+    /// Captures the resource tables, so that calls can be tried out and then
+    /// rolled back with `restore`.
+    pub fn snapshot(&self) -> EnvironmentSnapshot {
+        EnvironmentSnapshot(self.clone())
+    }
+
+    /// Rolls back to `snapshot`, forgetting resources created since and undoing
+    /// changes to their states.
+    pub fn restore(&mut self, snapshot: EnvironmentSnapshot) {
+        *self = snapshot.0;
+    }
+
+    /// The call that created a resource, even if it has since been removed.
+    pub fn resource_origin(&self, idx: ResourceIdx) -> Option<&ResourceOrigin> {
+        self.resources.get(idx)?.origin.as_ref()
+    }
+
+    pub fn resource(&self, idx: ResourceIdx) -> Option<&Resource> {
+        self.resources_types.get(&idx)?;
+        self.resources.get(idx)
+    }
+
+    /// Live resources of the type named `ty`, in creation order.
+    pub fn resources_of_type<'a>(&'a self, ty: &str) -> impl Iterator<Item = (ResourceIdx, &'a Resource)> + 'a {
+        self.resources_by_types
+            .get(ty)
+            .into_iter()
+            .flatten()
+            .map(|&idx| (idx, self.resources.get(idx).unwrap()))
+    }
+
+    /// Forgets a resource so that it is no longer picked as an argument. Its
+    /// index is not reused.
+    pub fn remove_resource(&mut self, idx: ResourceIdx) {
+        if let Some(r#type) = self.resources_types.remove(&idx) {
+            if let Some(resources) = self.resources_by_types.get_mut(&r#type) {
+                resources.remove(&idx);
+            }
+        }
+    }
+
+    /// Removes the resources passed for params that `function` consumes.
+    pub fn remove_consumed_resources(&mut self, function: &Function, params: &[HighLevelValue]) {
+        for (param, value) in function.params.iter().zip(params) {
+            if let (true, Some(idx)) = (function.consumes.contains(&param.name), value.as_resource()) {
+                self.remove_resource(idx);
+            }
+        }
+    }
+
+    /// Registers the resources in the results of a successful call and
+    /// updates the state of resources passed as params as the function's
+    /// output contract says, like the `offset` of the fd passed to `fd_seek`.
     pub fn execute_function_effects(
         &mut self,
         spec: &Spec,
         function: &Function,
-        _params: &[(WasiValue, Option<ResourceIdx>)],
+        params: &[(WasiValue, Option<ResourceIdx>)],
         results: &Vec<(String, MaybeResourceValue)>,
     ) -> Vec<Option<ResourceIdx>> {
+        if let Some(contract) = &function.output_contract {
+            self.apply_state_effects(spec, function, contract, params, results);
+        }
+
         let mut resources: HashMap<&str, ResourceIdx> = Default::default();
         let mut result_resource_idxs = Vec::new();
 
@@ -159,6 +261,75 @@ impl Environment {
         result_resource_idxs
     }
 
+    fn apply_state_effects(
+        &mut self,
+        spec: &Spec,
+        function: &Function,
+        contract: &slang::Term,
+        params: &[(WasiValue, Option<ResourceIdx>)],
+        results: &[(String, MaybeResourceValue)],
+    ) {
+        let param_values = function
+            .params
+            .iter()
+            .zip(params)
+            .map(|(param, (value, resource_idx))| {
+                let value = resource_idx
+                    .and_then(|idx| self.resource(idx))
+                    .map_or(value, |resource| &resource.state);
+
+                (param.name.clone(), value.clone())
+            })
+            .collect();
+        let result_values = results
+            .iter()
+            .map(|(name, result)| (name.clone(), result.value.clone()))
+            .collect();
+        let Some(effects) = contract.effects(slang::Scope {
+            spec:     Some(spec),
+            function: Some(function),
+            params:   &param_values,
+            results:  &result_values,
+        }) else {
+            return;
+        };
+
+        for effect in effects {
+            let Some((param, (_value, Some(resource_idx)))) = function
+                .params
+                .iter()
+                .zip(params)
+                .find(|(param, _)| param.name == effect.param)
+            else {
+                continue;
+            };
+            let TypeRef::Named(type_name) = &param.tref else {
+                continue;
+            };
+            let Some(WasiType::Record(record_type)) = spec.get_wasi_type(type_name) else {
+                continue;
+            };
+            let Some(member_type) = record_type
+                .members
+                .iter()
+                .find(|member| member.name == effect.member)
+                .map(|member| member.tref.resolve_wasi(spec))
+            else {
+                continue;
+            };
+            let Some(WasiValue::Record(state)) = self.resources.get_mut(*resource_idx).map(|r| &mut r.state) else {
+                continue;
+            };
+
+            if let (Some(member), Some(value)) = (
+                state.get_mut(&record_type, &effect.member),
+                int_value(&member_type, &effect.value),
+            ) {
+                *member = value;
+            }
+        }
+    }
+
     pub fn resolve_value(&self, value: &HighLevelValue) -> WasiValue {
         match value {
             | &HighLevelValue::Resource(resource_idx) => self.resources.get(resource_idx).unwrap().state.clone(),
@@ -166,11 +337,14 @@ impl Environment {
         }
     }
 
+    /// Registers the resources in a result, recording `origin` as the call
+    /// that created them.
     pub fn lift_recursively(
         &mut self,
         spec: &Spec,
         mut ctxs: Vec<(&mut RuntimeContext, &WasiValue)>,
         tdef: &TypeDef,
+        origin: Option<&ResourceOrigin>,
     ) -> Option<ResourceIdx> {
         let mut passes = Vec::new();
         let mut passes_tdef = None;
@@ -191,7 +365,7 @@ impl Environment {
                 },
                 | (WasiType::Record(_), _) => panic!(),
                 | (WasiType::Flags(_), _) => (),
-                | (WasiType::Pointer(pointer), WasiValue::List(pointer_value)) => {
+                | (WasiType::Pointer(pointer), WasiValue::Pointer(pointer_value)) => {
                     for item in &pointer_value.items {
                         passes_tdef = Some(pointer.item.resolve(spec));
                         passes.push(item);
@@ -224,7 +398,8 @@ impl Environment {
             let id = self.new_resource(
                 tdef.name.clone(),
                 Resource {
-                    state: state.zero_value(spec),
+                    state:  state.zero_value(spec),
+                    origin: origin.cloned(),
                 },
             );
 
@@ -238,7 +413,7 @@ impl Environment {
         if let Some(tdef) = passes_tdef {
             let ctxs = ctxs.into_iter().map(|(ctx, _)| ctx).zip(passes).collect_vec();
 
-            self.lift_recursively(spec, ctxs, tdef);
+            self.lift_recursively(spec, ctxs, tdef, origin);
         }
 
         resource_id
@@ -296,7 +471,8 @@ impl Environment {
             let resource_id = self.new_resource(
                 tdef.name.clone(),
                 Resource {
-                    state: state.zero_value(spec),
+                    state:  state.zero_value(spec),
+                    origin: None,
                 },
             );
 
@@ -307,12 +483,75 @@ impl Environment {
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Call {
     pub function: String,
     pub errno:    Option<i32>,
     pub params:   Vec<MaybeResourceValue>,
     pub results:  Option<Vec<MaybeResourceValue>>,
+    /// Wall-clock time the runtime took to answer the call. Not compared, as
+    /// it varies between otherwise identical calls.
+    #[serde(default)]
+    pub duration: Option<Duration>,
+}
+
+impl Call {
+    /// A call that succeeded without results, made with concrete params, as
+    /// preludes list them.
+    pub fn succeeded(function: &str, params: Vec<WasiValue>) -> Self {
+        Self {
+            function: function.to_string(),
+            errno:    Some(0),
+            params:   params
+                .into_iter()
+                .map(|value| MaybeResourceValue {
+                    value,
+                    resource_idx: None,
+                })
+                .collect(),
+            results:  Some(Vec::new()),
+            duration: None,
+        }
+    }
+
+    /// A call that succeeded, passing and returning the resources at the
+    /// given indices as handles.
+    pub fn on_resources(function: &str, params: &[usize], results: &[usize]) -> Self {
+        let value = |&idx: &usize| MaybeResourceValue {
+            value:        WasiValue::Handle(3),
+            resource_idx: Some(ResourceIdx(idx)),
+        };
+
+        Self {
+            function: function.to_string(),
+            errno:    Some(0),
+            params:   params.iter().map(value).collect(),
+            results:  Some(results.iter().map(value).collect()),
+            duration: None,
+        }
+    }
+}
+
+impl PartialEq for Call {
+    fn eq(&self, other: &Self) -> bool {
+        self.function == other.function
+            && self.errno == other.errno
+            && self.params == other.params
+            && self.results == other.results
+    }
+}
+
+impl Eq for Call {
+}
+
+impl RecordedCall for Call {
+    fn function_name(&self) -> &str {
+        &self.function
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
@@ -346,17 +585,54 @@ impl RuntimeContext {
     }
 }
 
+/// `value` as a value of the integer type `wasi_type`, if it fits.
+fn int_value(wasi_type: &WasiType, value: &BigInt) -> Option<WasiValue> {
+    Some(match wasi_type {
+        | WasiType::S64 => WasiValue::S64(value.try_into().ok()?),
+        | WasiType::U8 => WasiValue::U8(value.try_into().ok()?),
+        | WasiType::U16 => WasiValue::U16(value.try_into().ok()?),
+        | WasiType::U32 => WasiValue::U32(value.try_into().ok()?),
+        | WasiType::U64 => WasiValue::U64(value.try_into().ok()?),
+        | _ => return None,
+    })
+}
+
+/// A spec function that the executor's `WasiFunc` enum has no value for.
+#[derive(thiserror::Error, PartialEq, Eq, Clone, Debug)]
+#[error(
+    "executor doesn't know WASI function `{0}`, add it to `WasiFunc` in executor/wazzi-executor.proto and to the \
+     name mapping in executor/pb/rust, then rebuild to regenerate the protobuf code"
+)]
+pub struct UnknownFunction(pub String);
+
+fn wasi_func(name: &str) -> Result<WasiFunc, UnknownFunction> {
+    WasiFunc::try_from(name).map_err(|()| UnknownFunction(name.to_string()))
+}
+
+/// Errors on the first function in `spec` that the executor can't call.
+pub fn check_executor_functions(spec: &Spec) -> Result<(), UnknownFunction> {
+    for (_name, interface) in spec.interfaces.iter() {
+        for name in interface.functions.keys() {
+            wasi_func(name)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The errno of a call, its results if it succeeded, and its params as read
+/// back from guest memory after the call.
+pub type CallResponse = (Option<i32>, Option<Vec<WasiValue>>, Vec<WasiValue>);
+
 pub fn execute_call(
     spec: &Spec,
     rtctx: &RuntimeContext,
     function: &Function,
     params: Vec<HighLevelValue>,
     executor: &RunningExecutor,
-) -> Result<(Option<i32>, Option<Vec<WasiValue>>), eyre::Error> {
+) -> Result<CallResponse, eyre::Error> {
     let response = executor.call(wazzi_executor_pb_rust::request::Call {
-        func:           WasiFunc::try_from(function.name.as_str())
-            .map_err(|_| err!("unknown WASI function name"))?
-            .into(),
+        func:           wasi_func(&function.name)?.into(),
         params:         function
             .params
             .iter()
@@ -385,6 +661,13 @@ pub fn execute_call(
         | Some(wazzi_executor_pb_rust::response::call::Errno_option::ErrnoSome(i)) => Some(i),
         | _ => None,
     };
+    let written_params = response
+        .params
+        .into_iter()
+        .zip(function.params.iter())
+        .map(|(param_value, param)| WasiValue::from_pb(param_value, spec, param.tref.resolve(spec)))
+        .collect::<Result<Vec<_>, _>>()
+        .wrap_err("failed to decode written params")?;
     let results = match errno {
         | Some(i) if i != 0 => None,
         | _ => Some(
@@ -393,9 +676,250 @@ pub fn execute_call(
                 .into_iter()
                 .zip(function.results.iter())
                 .map(|(result_value, result)| WasiValue::from_pb(result_value, spec, result.tref.resolve(spec)))
-                .collect_vec(),
+                .collect::<Result<Vec<_>, _>>()
+                .wrap_err("failed to decode results")?,
         ),
     };
 
-    Ok((errno, results))
+    Ok((errno, results, written_params))
+}
+
+/// Compares what two runtimes' guests wrote to stdout. With `unordered`, only
+/// the multiset of lines has to match.
+pub fn stdout_matches(a: &[u8], b: &[u8], unordered: bool) -> bool {
+    if !unordered {
+        return a == b;
+    }
+
+    let a = a.split(|&byte| byte == b'\n').sorted().collect_vec();
+    let b = b.split(|&byte| byte == b'\n').sorted().collect_vec();
+
+    a == b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::VariantValue;
+
+    #[test]
+    fn unknown_function_is_named() {
+        let spec = Spec::preview1(include_str!("../preview1.witx")).unwrap();
+        let err = wasi_func("fd_frobnicate").unwrap_err();
+
+        assert_eq!(check_executor_functions(&spec), Ok(()));
+        assert_eq!(err, UnknownFunction("fd_frobnicate".to_string()));
+        assert!(err.to_string().contains("`fd_frobnicate`"), "{err}");
+    }
+
+    #[test]
+    fn resources_are_listed_by_type() {
+        let mut env = Environment::new();
+        let fd = |offset| Resource {
+            state:  WasiValue::U64(offset),
+            origin: None,
+        };
+        let fd_0 = env.new_resource("fd".to_string(), fd(0));
+        let _dir = env.new_resource("dir".to_string(), fd(1));
+        let fd_1 = env.new_resource("fd".to_string(), fd(2));
+
+        assert_eq!(
+            env.resources_of_type("fd").collect_vec(),
+            [(fd_0, &fd(0)), (fd_1, &fd(2))]
+        );
+        assert_eq!(env.resource(fd_1), Some(&fd(2)));
+        assert_eq!(env.resources_of_type("path").count(), 0);
+    }
+
+    #[test]
+    fn closed_fd_is_removed() {
+        let spec = Spec::preview1(include_str!("../preview1.witx")).unwrap();
+        let fd_close = spec.get_function("fd_close").unwrap();
+        let mut env = Environment::new();
+        let fd = |offset| Resource {
+            state:  WasiValue::U64(offset),
+            origin: None,
+        };
+        let fd_0 = env.new_resource("fd".to_string(), fd(0));
+        let fd_1 = env.new_resource("fd".to_string(), fd(1));
+
+        assert_eq!(fd_close.consumes, ["fd"]);
+
+        env.remove_consumed_resources(fd_close, &[HighLevelValue::Resource(fd_0)]);
+
+        assert_eq!(env.resources_of_type("fd").collect_vec(), [(fd_1, &fd(1))]);
+        assert_eq!(env.resource(fd_0), None);
+    }
+
+    #[test]
+    fn restore_forgets_resources_created_after_snapshot() {
+        let mut env = Environment::new();
+        let resource = Resource {
+            state:  WasiValue::Handle(3),
+            origin: None,
+        };
+        let fd = env.new_resource("fd".to_string(), resource.clone());
+        let snapshot = env.snapshot();
+        let opened = env.new_resource("fd".to_string(), resource);
+
+        env.remove_resource(fd);
+        env.restore(snapshot);
+
+        assert!(env.resource(fd).is_some());
+        assert!(env.resource(opened).is_none());
+        assert_eq!(
+            env.resources_of_type("fd").map(|(idx, _)| idx).collect::<Vec<_>>(),
+            [fd]
+        );
+    }
+
+    #[test]
+    fn seek_updates_fd_offset() {
+        let spec = Spec::preview1(include_str!("../preview1.witx")).unwrap();
+        let fd_seek = spec.get_function("fd_seek").unwrap();
+        let fd_type = spec.get_wasi_type("fd").unwrap();
+        let mut state = fd_type.zero_value(&spec);
+        let WasiValue::Record(record) = &mut state else {
+            panic!("fd state is not a record");
+        };
+
+        *record.get_mut(fd_type.record().unwrap(), "offset").unwrap() = WasiValue::U64(4);
+
+        let mut env = Environment::new();
+        let fd = env.new_resource("fd".to_string(), Resource { state, origin: None });
+        let whence_cur = WasiValue::Variant(Box::new(VariantValue {
+            case_idx: 1,
+            payload:  None,
+        }));
+        let offset = |env: &Environment| {
+            env.resource(fd)
+                .unwrap()
+                .state
+                .record()
+                .unwrap()
+                .get(fd_type.record().unwrap(), "offset")
+                .cloned()
+        };
+
+        env.execute_function_effects(
+            &spec,
+            fd_seek,
+            &[
+                (WasiValue::Handle(3), Some(fd)),
+                (WasiValue::S64(6), None),
+                (whence_cur, None),
+            ],
+            &vec![(
+                "ok".to_string(),
+                MaybeResourceValue {
+                    value:        WasiValue::U64(10),
+                    resource_idx: None,
+                },
+            )],
+        );
+
+        assert_eq!(offset(&env), Some(WasiValue::U64(10)));
+    }
+
+    #[test]
+    fn origin_outlives_removed_resource() {
+        let mut env = Environment::new();
+        let origin = ResourceOrigin {
+            call_idx: 7,
+            function: "path_open".to_string(),
+        };
+        let fd = env.new_resource(
+            "fd".to_string(),
+            Resource {
+                state:  WasiValue::U64(0),
+                origin: Some(origin.clone()),
+            },
+        );
+
+        assert_eq!(env.resource_origin(fd), Some(&origin));
+
+        env.remove_resource(fd);
+
+        assert_eq!(env.resource(fd), None);
+        assert_eq!(env.resource_origin(fd), Some(&origin));
+    }
+
+    #[test]
+    fn preopen_state_follows_spec() {
+        let spec = Spec::preview1(
+            "
+(typename $size u64)
+(typename $kind (enum (@witx tag u8) $file $dir))
+(typename $descriptor (handle)
+  (@state
+    (record
+      (field $position $size)
+      (field $kind $kind (@preopen $dir))
+    )
+  )
+)
+",
+        )
+        .unwrap();
+        let initializer = |name: &str| {
+            EnvironmentInitializer::new(
+                name.to_string(),
+                vec![("base".to_string(), PathBuf::from("/tmp/base"), WasiValue::Handle(3))],
+            )
+        };
+        let (env, ctxs, preopens) = apply_env_initializers(&spec, &[initializer("a"), initializer("b")]);
+        let resources = env.resources_of_type("descriptor").collect_vec();
+        let kind = spec.get_wasi_type("kind").unwrap();
+
+        assert_eq!(resources.len(), 1);
+        assert_eq!(
+            resources[0].1.state,
+            WasiValue::Record(RecordValue {
+                members: vec![
+                    WasiValue::U64(0),
+                    kind.variant().unwrap().value_from_name("dir", None).unwrap(),
+                ],
+            })
+        );
+        assert_eq!(preopens.get(&resources[0].0), Some(&PathBuf::from("/tmp/base")));
+        assert!(ctxs.iter().all(|ctx| ctx.resources.len() == 1));
+    }
+
+    #[test]
+    fn seed_files_are_written_to_every_preopen() {
+        let dirs = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+        let seed_files = vec![
+            (PathBuf::from("a"), b"hello".to_vec()),
+            (PathBuf::from("d/e/b"), Vec::new()),
+        ];
+
+        for (i, dir) in dirs.iter().enumerate() {
+            EnvironmentInitializer::new(
+                format!("runtime-{i}"),
+                vec![("base".to_string(), dir.path().to_path_buf(), WasiValue::Handle(3))],
+            )
+            .with_seed_files(seed_files.clone())
+            .write_seed_files()
+            .unwrap();
+        }
+
+        for dir in &dirs {
+            for (path, content) in &seed_files {
+                assert_eq!(&fs::read(dir.path().join(path)).unwrap(), content);
+            }
+        }
+    }
+
+    #[test]
+    fn stdout_exact_by_default() {
+        assert!(stdout_matches(b"a\nb\n", b"a\nb\n", false));
+        assert!(!stdout_matches(b"a\nb\n", b"b\na\n", false));
+        assert!(!stdout_matches(b"a\nb\n", b"a\nb", false));
+    }
+
+    #[test]
+    fn stdout_unordered_compares_lines() {
+        assert!(stdout_matches(b"a\nb\n", b"b\na\n", true));
+        assert!(!stdout_matches(b"a\nb\n", b"a\na\n", true));
+    }
 }
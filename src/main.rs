@@ -1,28 +1,31 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     fs,
-    io::{self, stderr, IsTerminal},
+    io::{self, stderr, BufWriter, IsTerminal, Read as _, Seek as _},
+    os::unix::{ffi::OsStrExt as _, fs::PermissionsExt as _},
     panic,
     path::{Path, PathBuf},
     process,
+    str::FromStr,
     sync::{
         atomic::{self, AtomicBool},
         Arc,
         Condvar,
         Mutex,
+        MutexGuard,
         RwLock,
     },
-    thread,
-    time::Duration,
+    thread::{self, ScopedJoinHandle},
+    time::{Duration, Instant},
 };
 
 use arbitrary::Unstructured;
 use clap::{builder::TypedValueParser, Parser};
 use eyre::{eyre as err, Context as _};
 use itertools::{EitherOrBoth, Itertools as _};
-use memmap::MmapOptions;
+use memmap::{MmapMut, MmapOptions};
 use multiqueue::broadcast_queue;
-use rand::{thread_rng, RngCore};
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
 use serde::{Deserialize, Serialize};
 use threadpool::ThreadPool;
 use tracing::level_filters::LevelFilter;
@@ -31,20 +34,30 @@ use tracing_subscriber::{layer::SubscriberExt as _, EnvFilter};
 use walkdir::WalkDir;
 use wazzi::{
     apply_env_initializers,
+    check_executor_functions,
+    corpus::{Corpus, Coverage},
     execute_call,
-    normalization::Runtime,
-    spec::{Spec, WasiValue},
+    normalization::{runtime_by_name, Runtime, RUNTIME_NAMES},
+    resource::{HighLevelValue, ResourceOrigin},
+    spec::{Function, GenConfig, Spec, SpecSummary, TypeRef, WasiType, WasiValue},
+    stdout_matches,
     Call,
     CallStrategy,
+    Environment,
     EnvironmentInitializer,
+    FunctionFilter,
     MaybeResourceValue,
     ResourceIdx,
+    ResumeStrategy,
+    ReuseStrategy,
+    RuntimeContext,
     StatefulStrategy,
     StatelessStrategy,
 };
-use wazzi_runners::{MappedDir, Node, RunningExecutor, Wamr, Wasmedge, Wasmer, Wasmtime, Wazero};
-use wazzi_store::Store;
+use wazzi_runners::{feed_stdin, kill_live_executors, CallError, MappedDir, RunningExecutor, MAX_MESSAGE_SIZE};
+use wazzi_store::{read_trace, recorded_runtimes, RunStore, RuntimeStore, Store, TraceFormat};
 
+/// Default size of the fuzz input buffer.
 static BUF_SIZE: usize = 131072;
 
 #[derive(Clone, Debug)]
@@ -69,21 +82,142 @@ struct Cmd {
     #[arg(long)]
     data: Option<PathBuf>,
 
-    #[arg()]
-    config: PathBuf,
+    /// Re-run a stored run and check that it makes the same calls again.
+    #[arg(long, conflicts_with = "data")]
+    replay: Option<PathBuf>,
 
-    #[arg()]
-    path: PathBuf,
+    /// Make the calls of a stored run again, then keep fuzzing with fresh data.
+    #[arg(long, conflicts_with_all = ["data", "replay"])]
+    resume: Option<PathBuf>,
+
+    /// Run once on each file in this directory, stopping at the first divergence.
+    #[arg(long, conflicts_with_all = ["data", "replay", "resume"])]
+    corpus: Option<PathBuf>,
+
+    /// Make the calls in this JSON trace first, then call only `--hammer`.
+    #[arg(long, requires = "hammer", conflicts_with_all = ["data", "replay", "resume", "corpus"])]
+    prelude: Option<PathBuf>,
+
+    /// Function to fuzz in isolation after `--prelude`.
+    #[arg(long, requires = "prelude", conflicts_with_all = ["functions", "exclude_functions"])]
+    hammer: Option<String>,
+
+    /// Parse and resolve the config's spec, print what it defines, and exit
+    /// without starting any runtimes.
+    #[arg(long, conflicts_with_all = ["data", "replay", "resume", "corpus", "prelude"])]
+    check_spec: bool,
+
+    /// Print the runtime names a config may use and exit.
+    #[arg(long, exclusive = true)]
+    list_runtimes: bool,
+
+    /// Print the signature of every function in this spec and exit.
+    #[arg(long, exclusive = true)]
+    list_functions: Option<PathBuf>,
+
+    #[arg(required_unless_present_any = ["list_runtimes", "list_functions"])]
+    config: Option<PathBuf>,
+
+    #[arg(required_unless_present_any = ["check_spec", "list_runtimes", "list_functions"])]
+    path: Option<PathBuf>,
 
     #[arg(long, value_enum, default_value_t = Strategy::Stateful)]
     strategy: Strategy,
 
+    /// Stop fuzzing after this long. Also cancels a single `--data` run.
     #[arg(long, value_parser = HumantimeParser)]
     time_limit: Option<Duration>,
 
+    /// Kill a runtime whose executor doesn't answer a single call within this duration.
+    #[arg(long, value_parser = HumantimeParser)]
+    call_timeout: Option<Duration>,
+
+    /// End a run if a runtime takes longer than this to reach a pipeline stage
+    /// the other runtimes are waiting at.
+    #[arg(long, value_parser = HumantimeParser, default_value = "10m")]
+    stage_timeout: Duration,
+
+    /// Only require guest stdout to match across runtimes up to line order.
+    #[arg(long, default_value_t = false)]
+    unordered_stdout: bool,
+
+    /// Don't compare the runtimes' filesystems after each call, which only
+    /// costs time when fuzzing functions that don't touch them.
+    #[arg(long, default_value_t = false)]
+    no_fs_diff: bool,
+
+    /// When two errnos count as the same: `zero-non-zero`, `exact`, or
+    /// `custom:A=B,...` where errno A is treated as errno B.
+    #[arg(long, default_value = "zero-non-zero")]
+    errno_policy: ErrnoPolicy,
+
     #[arg(short = 'c', default_value = "1")]
     fuzzer_count: usize,
 
+    /// How recorded calls are written: `json-pretty`, `json-compact`, or `bincode`.
+    #[arg(long, default_value = "json-pretty")]
+    trace_format: TraceFormat,
+
+    /// Gzip each recorded call.
+    #[arg(long, default_value_t = false)]
+    compress_traces: bool,
+
+    /// Which finished runs to keep: `all`, or only those that diverged (`diffs`).
+    #[arg(long, value_enum, default_value_t = KeepPolicy::All)]
+    keep: KeepPolicy,
+
+    /// Start some runs from mutations of earlier inputs that reached new
+    /// coverage, instead of only from fresh random data.
+    #[arg(long, default_value_t = false)]
+    coverage_feedback: bool,
+
+    /// Stop each run after this many calls.
+    #[arg(long)]
+    max_calls: Option<usize>,
+
+    /// Size of each fuzz input buffer, in bytes or with a `K`, `M` or `G`
+    /// suffix. Must be a multiple of the page size.
+    #[arg(long, default_value_t = BUF_SIZE, value_parser = parse_buf_size)]
+    buf_size: usize,
+
+    /// Treat an executor response declaring more than this many bytes as
+    /// malformed, in bytes or with a `K`, `M` or `G` suffix.
+    #[arg(long, default_value_t = MAX_MESSAGE_SIZE as usize, value_parser = parse_size)]
+    max_message_size: usize,
+
+    /// Generate fuzz buffers from this seed. Implies a single fuzzer so runs are reproducible.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Relative chances of picking functions, as comma-separated `name=weight` pairs. Unlisted functions weigh 1.
+    #[arg(long, value_delimiter = ',', value_parser = parse_weight)]
+    weights: Vec<(String, u32)>,
+
+    /// Only call these comma-separated functions.
+    #[arg(long, value_delimiter = ',')]
+    functions: Vec<String>,
+
+    /// Never call these comma-separated functions.
+    #[arg(long, value_delimiter = ',')]
+    exclude_functions: Vec<String>,
+
+    /// Now and then set flag bits the spec leaves reserved, to probe how
+    /// runtimes validate them. Only the stateless and reuse strategies do.
+    #[arg(long, default_value_t = false)]
+    reserved_flag_bits: bool,
+
+    /// Only run these comma-separated runtimes from the config.
+    #[arg(long, value_delimiter = ',')]
+    runtimes: Vec<String>,
+
+    /// Executor module to run. Overrides the config's `executor`.
+    #[arg(long)]
+    executor: Option<PathBuf>,
+
+    /// Replace the store at `path` if one already exists.
+    #[arg(long, default_value_t = false)]
+    force: bool,
+
     #[arg(long, default_value_t = false)]
     silent: bool,
 }
@@ -93,6 +227,28 @@ struct Cmd {
 enum Strategy {
     Stateful,
     Stateless,
+    /// Stateless, but mostly passing paths earlier calls used.
+    Reuse,
+}
+
+/// Which runs to keep on disk once they finish.
+#[derive(clap::ValueEnum, PartialEq, Eq, Clone, Copy, Debug, Default)]
+enum KeepPolicy {
+    #[default]
+    All,
+    /// Only runs that diverged.
+    Diffs,
+}
+
+impl KeepPolicy {
+    /// Deletes the finished run at `run_path` unless the policy keeps it.
+    fn apply(self, run_path: &Path) -> Result<(), io::Error> {
+        match self {
+            | KeepPolicy::All => Ok(()),
+            | KeepPolicy::Diffs if run_path.join("divergence.json").exists() => Ok(()),
+            | KeepPolicy::Diffs => fs::remove_dir_all(run_path),
+        }
+    }
 }
 
 impl Strategy {
@@ -101,12 +257,187 @@ impl Strategy {
         u: &'a mut Unstructured,
         ctx: &'a z3::Context,
         preopens: BTreeMap<ResourceIdx, PathBuf>,
+        weights: HashMap<String, u32>,
+        filter: FunctionFilter,
+        gen_config: GenConfig,
     ) -> Box<dyn CallStrategy + 'a> {
         match self {
-            | Strategy::Stateful => Box::new(StatefulStrategy::new(u, ctx, preopens)),
-            | Strategy::Stateless => Box::new(StatelessStrategy::new(u)),
+            | Strategy::Stateful => Box::new(
+                StatefulStrategy::new(u, ctx, preopens)
+                    .with_weights(weights)
+                    .with_function_filter(filter),
+            ),
+            | Strategy::Stateless => Box::new(
+                StatelessStrategy::new(u)
+                    .with_gen_config(gen_config)
+                    .with_weights(weights)
+                    .with_function_filter(filter),
+            ),
+            | Strategy::Reuse => Box::new(
+                ReuseStrategy::new(u)
+                    .with_gen_config(gen_config)
+                    .with_weights(weights)
+                    .with_function_filter(filter),
+            ),
+        }
+    }
+}
+
+/// Parses a size in bytes, optionally with a `K`, `M` or `G` suffix.
+fn parse_size(s: &str) -> Result<usize, eyre::Error> {
+    let (digits, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let shift = match unit.trim_end_matches("iB").trim_end_matches('B') {
+        | "" => 0,
+        | "K" | "k" => 10,
+        | "M" | "m" => 20,
+        | "G" | "g" => 30,
+        | _ => return Err(err!("unknown size unit `{unit}`")),
+    };
+
+    digits
+        .parse::<usize>()
+        .wrap_err("invalid size")?
+        .checked_shl(shift)
+        .ok_or_else(|| err!("size {s} is too large"))
+}
+
+fn parse_buf_size(s: &str) -> Result<usize, eyre::Error> {
+    let size = parse_size(s)?;
+    let page_size = rustix::param::page_size();
+
+    if size == 0 || size % page_size != 0 {
+        return Err(err!(
+            "buffer size must be a non-zero multiple of the page size {page_size}"
+        ));
+    }
+
+    Ok(size)
+}
+
+/// Anonymous memory the fuzz input is generated into.
+fn map_buffer(buf_size: usize) -> Result<MmapMut, io::Error> {
+    MmapOptions::new().len(buf_size).map_anon()
+}
+
+/// Logs the params of a call with resources lowered to the values the runtime
+/// will see. Rendering only happens when DEBUG is enabled.
+fn debug_params(runtime: &str, spec: &Spec, rtctx: &RuntimeContext, function: &Function, params: &[HighLevelValue]) {
+    tracing::debug!(
+        runtime,
+        function = function.name,
+        params = function
+            .params
+            .iter()
+            .zip(params)
+            .map(|(param, value)| {
+                let (value, _resource_idx) = rtctx.lower(value.clone());
+
+                format!("{}={}", param.name, value.pretty(spec, &param.tref))
+            })
+            .join(", "),
+        "Lowered params."
+    );
+}
+
+/// Creates the directory for a new store. With `force`, an existing store at
+/// `path` is removed first, but only if it looks like one.
+fn create_store_dir(path: &Path, force: bool) -> Result<(), eyre::Error> {
+    if force && path.exists() {
+        if !path.join("runs").is_dir() && !path.join("metadata.json").is_file() {
+            return Err(err!(
+                "refusing to remove {} since it doesn't look like a store",
+                path.display()
+            ));
+        }
+
+        fs::remove_dir_all(path).wrap_err("failed to remove previous store")?;
+    }
+
+    match fs::create_dir(path) {
+        | Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            Err(err!("{} already exists, pass --force to replace it", path.display()))
+        },
+        | result => result.wrap_err("failed to create store dir"),
+    }
+}
+
+/// Where `cargo build --release` puts the executor, relative to the workspace.
+const DEFAULT_EXECUTOR: &str = "target/release/wazzi-executor.wasm";
+
+/// Canonicalizes the executor module path, defaulting to the release build.
+fn find_executor(path: Option<PathBuf>) -> Result<PathBuf, eyre::Error> {
+    let path = path.unwrap_or_else(|| PathBuf::from(DEFAULT_EXECUTOR));
+
+    fs::canonicalize(&path).wrap_err(format!(
+        "executor {} not found, build it or pass --executor",
+        path.display()
+    ))
+}
+
+/// Runs `executor_bin` under `runtime` with the runtime's `base` preopened,
+/// logging its stderr to the runtime store. The guest reads `guest_stdin`, if
+/// any, from fd 0.
+fn start_executor(
+    runtime: &dyn Runtime,
+    executor_bin: &Path,
+    store: &RuntimeStore<Call>,
+    guest_stdin: Option<&[u8]>,
+) -> Result<RunningExecutor, eyre::Error> {
+    let stderr = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(store.root_path().join("stderr"))
+        .wrap_err("failed to open stderr file")?;
+    let mut preopens = vec![MappedDir {
+        name:      "base".to_string(),
+        host_path: store.base_path().to_path_buf(),
+    }];
+    let mut envs = Vec::new();
+
+    if let Some(data) = guest_stdin {
+        feed_stdin(&store.root_path().join("stdin"), data, &mut preopens, &mut envs)
+            .wrap_err("failed to write guest stdin")?;
+    }
+
+    RunningExecutor::from_wasi_runner(
+        runtime,
+        executor_bin,
+        store.root_path(),
+        Arc::new(Mutex::new(stderr)),
+        preopens,
+        envs,
+        Vec::new(),
+    )
+}
+
+/// Every file under `dir` with its path relative to `dir`.
+fn read_seed_files(dir: &Path) -> Result<Vec<(PathBuf, Vec<u8>)>, eyre::Error> {
+    let mut files = Vec::new();
+
+    for entry in WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry?;
+
+        if entry.file_type().is_file() {
+            files.push((entry.path().strip_prefix(dir)?.to_path_buf(), fs::read(entry.path())?));
         }
     }
+
+    Ok(files)
+}
+
+/// Calls to make before fuzzing, given as a JSON array.
+fn read_prelude(path: &Path) -> Result<Vec<Call>, eyre::Error> {
+    let bytes = fs::read(path).wrap_err(format!("failed to read prelude {}", path.display()))?;
+
+    serde_json::from_slice(&bytes).wrap_err("failed to parse prelude")
+}
+
+fn parse_weight(s: &str) -> Result<(String, u32), eyre::Error> {
+    let (name, weight) = s
+        .split_once('=')
+        .ok_or_else(|| err!("expected `name=weight`, got `{s}`"))?;
+
+    Ok((name.to_string(), weight.parse().wrap_err("invalid weight")?))
 }
 
 fn main() -> Result<(), eyre::Error> {
@@ -114,6 +445,22 @@ fn main() -> Result<(), eyre::Error> {
 
     let cmd = Cmd::parse();
 
+    if cmd.list_runtimes {
+        println!("{}", RUNTIME_NAMES.join("\n"));
+
+        return Ok(());
+    }
+
+    if let Some(spec) = &cmd.list_functions {
+        let spec = fs::read_to_string(spec).wrap_err("failed to read spec file")?;
+
+        for function in list_functions(&spec)? {
+            println!("{function}");
+        }
+
+        return Ok(());
+    }
+
     if !cmd.silent {
         let mut subscriber = tracing_subscriber::fmt::layer()
             .with_thread_names(true)
@@ -137,6 +484,10 @@ fn main() -> Result<(), eyre::Error> {
         .wrap_err("failed to configure tracing")?;
     }
 
+    let cancel = Arc::new(AtomicBool::new(false));
+
+    install_interrupt_handler(cancel.clone())?;
+
     let orig_hook = panic::take_hook();
 
     panic::set_hook(Box::new(move |panic_info| {
@@ -145,45 +496,126 @@ fn main() -> Result<(), eyre::Error> {
         process::exit(1);
     }));
 
-    let config: FuzzConfig = serde_yml::from_reader(
+    let mut config: FuzzConfig = serde_yml::from_reader(
         fs::OpenOptions::new()
             .read(true)
-            .open(&cmd.config)
+            .open(cmd.config.as_ref().ok_or_else(|| err!("missing fuzz config"))?)
             .wrap_err("failed to read fuzz config")?,
     )
     .wrap_err("failed to deserialize fuzz config")?;
 
-    fs::create_dir(&cmd.path)?;
+    config.validate().wrap_err("invalid fuzz config")?;
+
+    if !cmd.runtimes.is_empty() {
+        config
+            .select_runtimes(&cmd.runtimes)
+            .wrap_err("invalid runtime selection")?;
+    }
+
+    if cmd.check_spec {
+        let spec = fs::read_to_string(&config.spec).wrap_err("failed to read spec file")?;
+
+        println!("{}", check_spec(&spec)?);
+
+        return Ok(());
+    }
+
+    let path = cmd.path.ok_or_else(|| err!("missing store path"))?;
+
+    create_store_dir(&path, cmd.force)?;
+
+    let mut store = Store::new(&path)
+        .wrap_err("failed to init fuzz store")?
+        .with_trace_format(cmd.trace_format)
+        .with_compressed_traces(cmd.compress_traces);
+    let mut fuzzer_count = cmd.fuzzer_count;
+
+    if let Some(seed) = cmd.seed {
+        if fuzzer_count != 1 {
+            tracing::warn!(fuzzer_count, "Seeded fuzzing runs a single fuzzer.");
+            fuzzer_count = 1;
+        }
+
+        store = store.with_seed(seed);
+    }
+
+    if let Some(max_calls) = cmd.max_calls {
+        store = store.with_max_calls(max_calls);
+    }
 
-    let store = Store::new(&cmd.path).wrap_err("failed to init fuzz store")?;
     let mut runtimes = Vec::with_capacity(config.runtimes.len());
 
     for runtime in config.runtimes {
-        let rt = match runtime.name.as_str() {
-            | "node" => Box::new(Node::default()) as Box<dyn Runtime>,
-            | "wamr" => Box::new(Wamr::default()) as Box<dyn Runtime>,
-            | "wasmedge" => Box::new(Wasmedge::default()) as Box<dyn Runtime>,
-            | "wasmer" => Box::new(Wasmer::default()) as Box<dyn Runtime>,
-            | "wasmtime" => Box::new(Wasmtime::default()) as Box<dyn Runtime>,
-            | "wazero" => Box::new(Wazero::default()) as Box<dyn Runtime>,
-            | name @ _ => return Err(err!("unknown runtime {name}")),
-        };
+        let rt = runtime.runtime()?;
 
         runtimes.push((runtime.name, rt));
     }
 
+    let spec = fs::read_to_string(config.spec).wrap_err("failed to read spec file")?;
+    let function_filter = match &cmd.hammer {
+        | Some(function) => FunctionFilter::new([function.clone()], []),
+        | None => FunctionFilter::new(cmd.functions, cmd.exclude_functions),
+    };
+    let executor = find_executor(cmd.executor.or(config.executor))?;
+    let seed_files = match &config.seed_dir {
+        | Some(dir) => read_seed_files(dir).wrap_err("failed to read seed dir")?,
+        | None => Vec::new(),
+    };
+    let guest_stdin = match &config.stdin {
+        | Some(path) => Some(fs::read(path).wrap_err("failed to read guest stdin")?),
+        | None => None,
+    };
+
+    let parsed_spec = Spec::preview1(&spec).wrap_err("failed to init spec")?;
+
+    check_executor_functions(&parsed_spec)?;
+    function_filter
+        .validate(&parsed_spec)
+        .wrap_err("invalid function filter")?;
+
     let mut fuzzer = Fuzzer::new(
-        fs::read_to_string(config.spec).wrap_err("failed to read spec file")?,
+        spec,
         cmd.strategy,
         store,
         runtimes,
         cmd.silent,
-    );
-
-    if let Some(data) = cmd.data {
-        fuzzer.fuzz(data)?;
+        cmd.call_timeout,
+        DiffOptions {
+            unordered_stdout: cmd.unordered_stdout,
+            fs_diff:          !cmd.no_fs_diff,
+            compare_mode:     config.compare_mode,
+            errno_policy:     cmd.errno_policy,
+            ignore:           config.ignore,
+        },
+    )
+    .with_weights(cmd.weights.into_iter().collect())
+    .with_function_filter(function_filter)
+    .with_gen_config(GenConfig {
+        reserved_flag_bits: cmd.reserved_flag_bits,
+        ..GenConfig::default()
+    })
+    .with_buf_size(cmd.buf_size)
+    .with_keep_policy(cmd.keep)
+    .with_seed_files(seed_files)
+    .with_guest_stdin(guest_stdin)
+    .with_executor(executor)
+    .with_stage_timeout(Some(cmd.stage_timeout))
+    .with_coverage_feedback(cmd.coverage_feedback)
+    .with_max_message_size(cmd.max_message_size as u64)
+    .with_cancel(cancel);
+
+    if let Some(run_path) = cmd.replay {
+        fuzzer.replay(&run_path)?;
+    } else if let Some(run_path) = cmd.resume {
+        fuzzer.resume(&run_path)?;
+    } else if let Some(corpus) = cmd.corpus {
+        fuzzer.fuzz_corpus(&corpus)?;
+    } else if let Some(prelude) = cmd.prelude {
+        fuzzer.hammer(&prelude)?;
+    } else if let Some(data) = cmd.data {
+        fuzzer.fuzz(data, cmd.time_limit)?;
     } else {
-        fuzzer.fuzz_loop(cmd.fuzzer_count, cmd.time_limit)?;
+        fuzzer.fuzz_loop(fuzzer_count, cmd.time_limit)?;
     }
 
     Ok(())
@@ -191,11 +623,25 @@ fn main() -> Result<(), eyre::Error> {
 
 #[derive(Debug)]
 struct Fuzzer {
-    silent:   bool,
-    spec:     String,
-    strategy: Strategy,
-    store:    Arc<Store>,
-    runtimes: Vec<(String, Box<dyn Runtime>)>,
+    silent:            bool,
+    spec:              String,
+    strategy:          Strategy,
+    store:             Arc<Store>,
+    runtimes:          Vec<(String, Box<dyn Runtime>)>,
+    call_timeout:      Option<Duration>,
+    stage_timeout:     Option<Duration>,
+    diff_options:      DiffOptions,
+    weights:           HashMap<String, u32>,
+    filter:            FunctionFilter,
+    gen_config:        GenConfig,
+    buf_size:          usize,
+    keep:              KeepPolicy,
+    cancel:            Arc<AtomicBool>,
+    seed_files:        Vec<(PathBuf, Vec<u8>)>,
+    guest_stdin:       Option<Vec<u8>>,
+    executor:          PathBuf,
+    coverage_feedback: bool,
+    max_message_size:  u64,
 }
 
 impl Fuzzer {
@@ -205,6 +651,8 @@ impl Fuzzer {
         store: Store,
         runtimes: impl IntoIterator<Item = (String, Box<dyn Runtime>)>,
         silent: bool,
+        call_timeout: Option<Duration>,
+        diff_options: DiffOptions,
     ) -> Self {
         Self {
             silent,
@@ -212,20 +660,131 @@ impl Fuzzer {
             strategy,
             store: Arc::new(store),
             runtimes: runtimes.into_iter().collect(),
+            call_timeout,
+            stage_timeout: None,
+            diff_options,
+            weights: HashMap::new(),
+            filter: FunctionFilter::default(),
+            gen_config: GenConfig::default(),
+            buf_size: BUF_SIZE,
+            keep: KeepPolicy::default(),
+            cancel: Arc::new(AtomicBool::new(false)),
+            seed_files: Vec::new(),
+            guest_stdin: None,
+            executor: PathBuf::from(DEFAULT_EXECUTOR),
+            coverage_feedback: false,
+            max_message_size: MAX_MESSAGE_SIZE,
         }
     }
 
-    pub fn fuzz(&mut self, data: PathBuf) -> Result<(), eyre::Error> {
-        let log_trace = !self.silent;
+    pub fn with_weights(mut self, weights: HashMap<String, u32>) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    pub fn with_function_filter(mut self, filter: FunctionFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Bounds for the values stateless strategies generate.
+    pub fn with_gen_config(mut self, gen_config: GenConfig) -> Self {
+        self.gen_config = gen_config;
+        self
+    }
+
+    pub fn with_buf_size(mut self, buf_size: usize) -> Self {
+        self.buf_size = buf_size;
+        self
+    }
+
+    /// Only applies to `fuzz_loop`, whose runs are too many to keep around.
+    pub fn with_keep_policy(mut self, keep: KeepPolicy) -> Self {
+        self.keep = keep;
+        self
+    }
+
+    /// Files created under every runtime's `base`, relative to it.
+    pub fn with_seed_files(mut self, seed_files: Vec<(PathBuf, Vec<u8>)>) -> Self {
+        self.seed_files = seed_files;
+        self
+    }
+
+    /// Bytes every guest reads from stdin.
+    pub fn with_guest_stdin(mut self, guest_stdin: Option<Vec<u8>>) -> Self {
+        self.guest_stdin = guest_stdin;
+        self
+    }
+
+    pub fn with_executor(mut self, executor: PathBuf) -> Self {
+        self.executor = executor;
+        self
+    }
+
+    /// Ends a run if some runtime takes longer than `stage_timeout` to reach a
+    /// pipeline stage the others are waiting at.
+    pub fn with_stage_timeout(mut self, stage_timeout: Option<Duration>) -> Self {
+        self.stage_timeout = stage_timeout;
+        self
+    }
+
+    /// Only applies to `fuzz_loop`: half of the runs start from a mutation of
+    /// an earlier input whose run reached new coverage.
+    pub fn with_coverage_feedback(mut self, coverage_feedback: bool) -> Self {
+        self.coverage_feedback = coverage_feedback;
+        self
+    }
+
+    /// Treats executor responses declaring more than `max_message_size` bytes
+    /// as malformed.
+    pub fn with_max_message_size(mut self, max_message_size: u64) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Stops fuzzing once `cancel` is set, after the current calls finish.
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Runs the fuzzer once on `data` and returns the id of the new run. The
+    /// run is cancelled once `time_limit` is up, even if a runtime hangs.
+    pub fn fuzz(&mut self, data: PathBuf, time_limit: Option<Duration>) -> Result<String, eyre::Error> {
         let data = fs::read(data)?;
+
+        if let Some(limit) = time_limit {
+            spawn_timer(self.cancel.clone(), limit)?;
+        }
+
+        self.fuzz_after(data, Vec::new())
+    }
+
+    /// Makes `calls` first, then runs the fuzzer on `data`.
+    fn fuzz_after(&mut self, data: Vec<u8>, calls: Vec<Call>) -> Result<String, eyre::Error> {
+        let log_trace = !self.silent;
+        let call_timeout = self.call_timeout;
+        let max_message_size = self.max_message_size;
+        let stage_timeout = self.stage_timeout;
+        let seed = self.store.seed();
+        let max_calls = self.store.max_calls();
+        let run_idx = 0;
+        let diff_options = self.diff_options.clone();
         let store = self.store.clone();
         let spec = self.spec.clone();
-        let cancel = Arc::new(AtomicBool::new(false));
+        let cancel = self.cancel.clone();
         let strategy = self.strategy.clone();
+        let weights = self.weights.clone();
+        let filter = self.filter.clone();
+        let gen_config = self.gen_config.clone();
+        let buf_size = self.buf_size;
+        let seed_files = self.seed_files.clone();
+        let guest_stdin = self.guest_stdin.clone();
+        let executor_bin = self.executor.clone();
         let runtime_initializers = Arc::new(self.runtimes.clone());
         let over = Arc::new(AtomicBool::new(false));
 
-        thread::scope(|scope| -> Result<(), eyre::Error> {
+        let (run_id, run) = thread::scope(|scope| -> Result<_, eyre::Error> {
             let (run_id, mut run) = store.new_run::<Call>()?;
             let spec = Spec::preview1(&spec).wrap_err("failed to init spec")?;
             let mut initializers: Vec<(String, EnvironmentInitializer)> = Default::default();
@@ -237,31 +796,26 @@ impl Fuzzer {
                     .wrap_err("failed to init runtime store")?;
                 let executor = {
                     let store = store.read().unwrap();
-                    let stderr = fs::OpenOptions::new()
-                        .write(true)
-                        .create_new(true)
-                        .open(store.root_path().join("stderr"))
-                        .wrap_err("failed to open stderr file")?;
-                    let executor = RunningExecutor::from_wasi_runner(
-                        runtime.as_ref(),
-                        Path::new("target").join("release").join("wazzi-executor.wasm").as_ref(),
-                        store.root_path(),
-                        Arc::new(Mutex::new(stderr)),
-                        vec![MappedDir {
-                            name:      "base".to_string(),
-                            host_path: store.base_path().to_path_buf(),
-                        }],
-                    )
-                    .unwrap();
-                    let initializer = runtime.initialize_state(
-                        runtime_name.clone(),
-                        &spec,
-                        &executor,
-                        vec![MappedDir {
-                            name:      "base".to_string(),
-                            host_path: store.base_path().to_path_buf(),
-                        }],
-                    )?;
+                    let mut executor = start_executor(runtime.as_ref(), &executor_bin, &store, guest_stdin.as_deref())?
+                        .with_max_message_size(max_message_size);
+
+                    if let Some(call_timeout) = call_timeout {
+                        executor = executor.with_call_timeout(call_timeout);
+                    }
+
+                    let initializer = runtime
+                        .initialize_state(
+                            runtime_name.clone(),
+                            &spec,
+                            &executor,
+                            vec![MappedDir {
+                                name:      "base".to_string(),
+                                host_path: store.base_path().to_path_buf(),
+                            }],
+                        )?
+                        .with_seed_files(seed_files.clone());
+
+                    initializer.write_seed_files().wrap_err("failed to write seed files")?;
 
                     initializers.push((runtime_name.to_string(), initializer));
 
@@ -280,12 +834,13 @@ impl Fuzzer {
             let rtctxs = Arc::new(RwLock::new(rtctxs));
             let fill_init = Arc::new((Mutex::new((0, 0usize)), Condvar::new()));
             let (fill_done_tx, fill_done_rx) = broadcast_queue(1);
-            let mmap = Arc::new(Mutex::new(MmapOptions::new().len(BUF_SIZE).map_anon().unwrap()));
+            let mmap = Arc::new(Mutex::new(map_buffer(buf_size)?));
 
             thread::Builder::new()
                 .name(format!("filler-{run_id}"))
                 .spawn_scoped(scope, {
                     let run = run.clone();
+                    let mut rng = run_rng(seed, run_idx);
                     let fill_init = fill_init.clone();
                     let mmap = mmap.clone();
                     let over = over.clone();
@@ -300,7 +855,7 @@ impl Fuzzer {
                                 let state = mu.lock().unwrap();
                                 let gen = state.1;
                                 let (mut state, result) = cond
-                                    .wait_timeout_while(state, Duration::from_micros(100), |(ready, g)| {
+                                    .wait_timeout_while(state, STAGE_POLL_INTERVAL, |(ready, g)| {
                                         *ready != n_runtimes && gen == *g
                                     })
                                     .unwrap();
@@ -324,7 +879,7 @@ impl Fuzzer {
                                 break;
                             }
 
-                            thread_rng().fill_bytes(&mut mmap.lock().unwrap());
+                            rng.fill_bytes(&mut mmap.lock().unwrap());
                             run.lock().unwrap().write_data(&mmap.lock().unwrap()).unwrap();
                             fill_done_tx.try_send(()).unwrap();
                         }
@@ -334,18 +889,15 @@ impl Fuzzer {
                 })
                 .wrap_err("failed to spawn buf filler thread")?;
 
-            let select_func_init = Arc::new((Mutex::new((0, 0usize)), Condvar::new()));
+            let select_func_init = new_stage(());
             let (select_func_done_tx, select_func_done_rx) = broadcast_queue(1);
-            let prep_params_init = Arc::new((Mutex::new((0, 0usize)), Condvar::new()));
+            let prep_params_init = new_stage(());
             let (prep_params_done_tx, prep_params_done_rx) = broadcast_queue(1);
-            let lift_results_init = Arc::new((
-                Mutex::new((0, 0usize, HashMap::<String, Option<Vec<WasiValue>>>::new(), None::<i32>)),
-                Condvar::new(),
-            ));
+            let lift_results_init = new_stage(LiftedResults::default());
             let (lift_results_done_tx, lift_results_done_rx) = broadcast_queue(1);
-            let solve_output_contract_init = Arc::new((Mutex::new((0, 0usize)), Condvar::new()));
+            let solve_output_contract_init = new_stage(());
             let (solve_output_contract_done_tx, solve_output_contract_done_rx) = broadcast_queue(1);
-            let diff_init = Arc::new((Mutex::new((0, 0usize, None)), Condvar::new()));
+            let diff_init = new_stage(None);
             let (diff_done_tx, diff_done_rx) = broadcast_queue(1);
 
             thread::Builder::new()
@@ -358,6 +910,7 @@ impl Fuzzer {
                     let over = over.clone();
                     let select_func_init = select_func_init.clone();
                     let prep_params_init = prep_params_init.clone();
+                    let rts = rts.clone();
                     let lift_results_init_pair = lift_results_init.clone();
                     let solve_output_contract_init = solve_output_contract_init.clone();
                     let rtctxs = rtctxs.clone();
@@ -368,58 +921,28 @@ impl Fuzzer {
                         let mut u = Unstructured::new(&data);
                         let cfg = z3::Config::new();
                         let ctx = z3::Context::new(&cfg);
-                        let mut strategy = strategy.into_call_strategy(&mut u, &ctx, preopens);
-
-                        'outer: loop {
-                            loop {
-                                let (mu, cond) = &*select_func_init;
-                                let state = mu.lock().unwrap();
-                                let gen = state.1;
-                                let (mut state, result) = cond
-                                    .wait_timeout_while(state, Duration::from_micros(100), |(select, g)| {
-                                        *select != n_runtimes && *g == gen
-                                    })
-                                    .unwrap();
-
-                                if over.load(atomic::Ordering::SeqCst) {
-                                    break 'outer;
-                                }
+                        let mut strategy = ResumeStrategy::new(
+                            calls,
+                            strategy.into_call_strategy(&mut u, &ctx, preopens, weights, filter, gen_config),
+                        );
 
-                                if result.timed_out() {
-                                    continue;
-                                }
+                        let mut call_idx = 0;
 
-                                state.0 = 0;
-                                state.1 = state.1.wrapping_add(1);
-                                break;
+                        'outer: loop {
+                            if wait_for_runtimes("select_function", &select_func_init, &rts, stage_timeout, &over)
+                                .is_err()
+                            {
+                                break 'outer;
                             }
 
                             let function = strategy.select_function(&spec, &env.read().unwrap()).unwrap();
 
                             select_func_done_tx.try_send(function.to_owned()).unwrap();
 
-                            loop {
-                                let (mu, cond) = &*prep_params_init;
-                                let state = mu.lock().unwrap();
-                                let gen = state.1;
-                                let (mut state, result) = cond
-                                    .wait_timeout_while(state, Duration::from_micros(100), |(ready, g)| {
-                                        (*ready != n_runtimes) && *g == gen
-                                    })
-                                    .unwrap();
-
-                                if over.load(atomic::Ordering::SeqCst) {
-                                    break 'outer;
-                                }
-
-                                if result.timed_out() {
-                                    continue;
-                                }
-
-                                state.0 = 0;
-                                state.1 = state.1.wrapping_add(1);
-
-                                break;
+                            if wait_for_runtimes("prepare_params", &prep_params_init, &rts, stage_timeout, &over)
+                                .is_err()
+                            {
+                                break 'outer;
                             }
 
                             let params = strategy
@@ -428,34 +951,25 @@ impl Fuzzer {
 
                             prep_params_done_tx.try_send(params.clone()).unwrap();
 
-                            let (results, errno) = loop {
-                                let (mu, cond) = &*lift_results_init_pair;
-                                let state = mu.lock().unwrap();
-                                let gen = state.1;
-                                let (mut state, result) = cond
-                                    .wait_timeout_while(
-                                        state,
-                                        Duration::from_micros(100),
-                                        |(ready, g, _results, _errno)| (*ready != n_runtimes) && *g == gen,
-                                    )
-                                    .unwrap();
-                                let mut results = HashMap::new();
-
-                                if over.load(atomic::Ordering::SeqCst) {
-                                    break 'outer;
-                                }
-
-                                if result.timed_out() {
-                                    continue;
-                                }
-
-                                state.0 = 0;
-                                state.1 = state.1.wrapping_add(1);
-                                std::mem::swap(&mut state.2, &mut results);
-
-                                break (results, state.3.take());
+                            let (results, errno) = match wait_for_runtimes(
+                                "lift_results",
+                                &lift_results_init_pair,
+                                &rts,
+                                stage_timeout,
+                                &over,
+                            ) {
+                                | Ok(mut state) => {
+                                    let (results, errno) = &mut state.2;
+
+                                    (std::mem::take(results), errno.take())
+                                },
+                                | Err(_) => break 'outer,
                             };
 
+                            let origin = ResourceOrigin {
+                                call_idx,
+                                function: function.name.clone(),
+                            };
                             let mut resource_idxs = Vec::new();
 
                             if errno.is_none() || errno.unwrap() == 0 {
@@ -473,37 +987,26 @@ impl Fuzzer {
 
                                     let mut rtctxs = rtctxs.write().unwrap();
                                     let ctxs = rtctxs.iter_mut().zip(result_values).collect_vec();
-                                    let resource_idx = env.write().unwrap().lift_recursively(&spec, ctxs, tdef);
+                                    let resource_idx =
+                                        env.write().unwrap().lift_recursively(&spec, ctxs, tdef, Some(&origin));
 
                                     resource_idxs.push(resource_idx);
                                 }
                             }
 
                             lift_results_done_tx.try_send(resource_idxs.clone()).unwrap();
-
-                            loop {
-                                let (mu, cond) = &*solve_output_contract_init;
-                                let state = mu.lock().unwrap();
-                                let gen = state.1;
-                                let (mut state, result) = cond
-                                    .wait_timeout_while(state, Duration::from_millis(100), |(ready, g)| {
-                                        (*ready != n_runtimes) && *g == gen
-                                    })
-                                    .unwrap();
-
-                                if over.load(atomic::Ordering::SeqCst) {
-                                    tracing::info!("Fuzz over. Stopping thread.");
-                                    break 'outer;
-                                }
-
-                                if result.timed_out() {
-                                    continue;
-                                }
-
-                                state.0 = 0;
-                                state.1 = state.1.wrapping_add(1);
-
-                                break;
+                            call_idx += 1;
+
+                            if wait_for_runtimes(
+                                "solve_output_contract",
+                                &solve_output_contract_init,
+                                &rts,
+                                stage_timeout,
+                                &over,
+                            )
+                            .is_err()
+                            {
+                                break 'outer;
                             }
 
                             if errno.is_none() || errno.unwrap() == 0 {
@@ -514,11 +1017,12 @@ impl Fuzzer {
                                         &spec,
                                         function,
                                         &mut env.write().unwrap(),
-                                        params,
+                                        params.clone(),
                                         resource_idxs,
                                         result_values.as_ref().map(Vec::as_slice),
                                     )
                                     .unwrap();
+                                env.write().unwrap().remove_consumed_resources(function, &params);
                             }
 
                             solve_output_contract_done_tx.try_send(()).unwrap();
@@ -532,62 +1036,82 @@ impl Fuzzer {
                 .name(format!("diff-{run_id}"))
                 .spawn_scoped(scope, {
                     let run = run.clone();
+                    let spec = spec.clone();
                     let over = over.clone();
-                    let cancel = cancel.clone();
                     let diff_init = diff_init.clone();
+                    let env = env.clone();
+                    let rts = rts.clone();
 
                     move || -> Result<(), FuzzError> {
                         run.lock().unwrap().configure_progress_logging(log_trace);
 
+                        let mut stdouts = vec![Vec::new(); rts.len()];
+
                         loop {
-                            let errnos: Vec<_> = loop {
-                                let (mu, cond) = &*diff_init;
-                                let state = mu.lock().unwrap();
-                                let gen = state.1;
-                                let (mut state, result) = cond
-                                    .wait_timeout_while(state, Duration::from_micros(100), |(ready, g, _)| {
-                                        *ready != n_runtimes && gen == *g
-                                    })
-                                    .unwrap();
+                            let mut errnos: Vec<CallOutput> =
+                                match wait_for_runtimes("diff", &diff_init, &rts, stage_timeout, &over) {
+                                    | Ok(mut state) => state.2.take().unwrap(),
+                                    | Err(_) => return Ok(()),
+                                };
 
-                                if over.load(atomic::Ordering::SeqCst) || cancel.load(atomic::Ordering::SeqCst) {
-                                    return Ok(());
-                                }
+                            let env = env.read().unwrap();
+                            let run = run.lock().unwrap();
 
-                                if result.timed_out() {
-                                    continue;
-                                }
+                            // Runtimes answer in any order, but are compared in the order they were added.
+                            let order = run.runtime_stores().map(|(name, _)| name.to_string()).collect_vec();
 
-                                state.0 = 0;
-                                state.1 = state.1.wrapping_add(1);
+                            errnos.sort_by_key(|(runtime, ..)| order.iter().position(|name| name == runtime));
 
-                                break state.2.take().unwrap();
-                            };
+                            if let Some(diff) = diff_aborts(&errnos) {
+                                write_divergence(&spec, &env, &run, &diff, &errnos)?;
+                                diff_done_tx.try_send(diff).unwrap();
+                                return Ok(());
+                            }
 
-                            let first = errnos.first().unwrap();
+                            let errno_diff = diff_errnos(&errnos, &diff_options.errno_policy, |diff| {
+                                diff_options.suppress(&spec, &env, &run, diff, &errnos)
+                            })?;
 
-                            for (_runtime_name, errno) in errnos.iter().skip(1) {
-                                match (first.1, errno) {
-                                    | (None, None) => continue,
-                                    | (None, Some(_)) | (Some(_), None) => {
-                                        tracing::info!("Errno diff found.");
-                                        diff_done_tx.try_send(DiffResult::Errno).unwrap();
-                                        return Ok(());
-                                    },
-                                    | (Some(l), &Some(r)) => {
-                                        if (l == 0 && r != 0) || (l != 0 && r == 0) {
-                                            tracing::info!("Errno diff found.");
-                                            diff_done_tx.try_send(DiffResult::Errno).unwrap();
-                                            return Ok(());
-                                        }
-                                    },
-                                }
+                            if let Some(diff) = errno_diff {
+                                tracing::info!(
+                                    ?diff,
+                                    errnos = ?errnos
+                                        .iter()
+                                        .map(|(runtime, errno, ..)| {
+                                            format!("{runtime}: {}", render_errno(&spec, *errno))
+                                        })
+                                        .collect_vec(),
+                                    "Errno diff found."
+                                );
+                                write_divergence(&spec, &env, &run, &diff, &errnos)?;
+                                diff_done_tx.try_send(diff).unwrap();
+                                return Ok(());
+                            }
+
+                            let function = last_called_function(&spec, &run);
+
+                            if let Some(diff) = function.and_then(|function| diff_memory(&spec, function, &errnos)) {
+                                tracing::error!(?diff, "Memory diff found.");
+                                write_divergence(&spec, &env, &run, &diff, &errnos)?;
+                                diff_done_tx.try_send(diff).unwrap();
+                                return Ok(());
                             }
 
-                            let run = run.lock().unwrap();
                             let runtimes = run.runtime_stores().collect::<Vec<_>>();
+                            // Walk each tree once up front instead of once per pair.
+                            let digests = diff_options.tree_digests(
+                                &runtimes
+                                    .iter()
+                                    .map(|(_name, runtime)| runtime.read().unwrap().base_path().to_path_buf())
+                                    .collect_vec(),
+                            )?;
+
+                            for ((_name, runtime), stdout) in runtimes.iter().zip(&mut stdouts) {
+                                read_appended(&runtime.read().unwrap().stdout_path(), stdout)
+                                    .wrap_err("failed to read stdout")?;
+                            }
 
-                            'outer: for (i, (runtime_0_name, runtime_0)) in runtimes.iter().enumerate() {
+                            for (i, (runtime_0_name, runtime_0)) in runtimes.iter().enumerate() {
                                 let runtime_0 = runtime_0.read().unwrap();
                                 let call_0 = runtime_0.last_call().unwrap();
 
@@ -596,57 +1120,78 @@ impl Fuzzer {
                                     let runtime_1 = runtime_1.read().unwrap();
                                     let call_1 = runtime_1.last_call().unwrap();
 
-                                    match (call_0.errno, call_1.errno) {
-                                        | (None, None) => {},
-                                        | (Some(errno_0), Some(errno_1))
-                                            if errno_0 == 0 && errno_1 == 0 || errno_0 != 0 && errno_1 != 0 => {},
-                                        | _ => {
-                                            tracing::error!(
-                                                runtime_a = runtime_0_name,
-                                                runtime_b = runtime_1_name,
-                                                runtime_a_errno = call_0.errno,
-                                                runtime_b_errno = call_1.errno,
-                                                "Errno diff found!"
-                                            );
+                                    if let Some(field) = diff_results(&spec, call_0, call_1) {
+                                        tracing::error!(
+                                            runtime_a = runtime_0_name,
+                                            runtime_b = runtime_1_name,
+                                            field,
+                                            "Result diff found."
+                                        );
+                                        let diff = DiffResult::ResultValue {
+                                            runtime_a: runtime_0_name.to_string(),
+                                            runtime_b: runtime_1_name.to_string(),
+                                            field,
+                                        };
 
-                                            over.store(true, atomic::Ordering::SeqCst);
-                                            break 'outer;
-                                        },
+                                        write_divergence(&spec, &env, &run, &diff, &errnos)?;
+                                        diff_done_tx.try_send(diff).unwrap();
+                                        return Ok(());
                                     }
 
-                                    let runtime_0_walk = WalkDir::new(&runtime_0.base_path())
-                                        .sort_by_file_name()
-                                        .min_depth(1)
-                                        .into_iter();
-                                    let runtime_1_walk = WalkDir::new(&runtime_1.base_path())
-                                        .sort_by_file_name()
-                                        .min_depth(1)
-                                        .into_iter();
-
-                                    for pair in runtime_0_walk.zip_longest(runtime_1_walk) {
-                                        match pair {
-                                            | EitherOrBoth::Both(a, b) => {
-                                                let a = a.wrap_err("failed to read dir entry")?;
-                                                let b = b.wrap_err("failed to read dir entry")?;
-
-                                                if a.depth() != b.depth()
-                                                    || a.file_type() != b.file_type()
-                                                    || a.file_name() != b.file_name()
-                                                    || (a.file_type().is_file()
-                                                        && fs::read(a.path()).wrap_err("failed to read file")?
-                                                            != fs::read(b.path()).wrap_err("failed to read file")?)
-                                                {
-                                                    tracing::error!("Fs diff found.");
-                                                    diff_done_tx.try_send(DiffResult::Filesystem).unwrap();
-                                                    return Ok(());
-                                                }
-                                            },
-                                            | EitherOrBoth::Left(_) | EitherOrBoth::Right(_) => {
-                                                tracing::error!("Fs diff found.");
-                                                diff_done_tx.try_send(DiffResult::Filesystem).unwrap();
-                                                return Ok(());
-                                            },
+                                    if !stdout_matches(&stdouts[i], &stdouts[j], diff_options.unordered_stdout) {
+                                        tracing::error!(
+                                            runtime_a = runtime_0_name,
+                                            runtime_b = runtime_1_name,
+                                            "Stdout diff found."
+                                        );
+                                        let diff = DiffResult::Stdout {
+                                            runtime_a: runtime_0_name.to_string(),
+                                            runtime_b: runtime_1_name.to_string(),
+                                        };
+
+                                        write_divergence(&spec, &env, &run, &diff, &errnos)?;
+                                        diff_done_tx.try_send(diff).unwrap();
+                                        return Ok(());
+                                    }
+
+                                    if let Some(diff) = digests
+                                        .as_ref()
+                                        .and_then(|digests| digest_diff(&digests[i], &digests[j]))
+                                    {
+                                        let fs_diff = DiffResult::Filesystem {
+                                            runtime_a: runtime_0_name.to_string(),
+                                            runtime_b: runtime_1_name.to_string(),
+                                            diff:      diff.clone(),
+                                        };
+
+                                        if diff_options.suppress(&spec, &env, &run, &fs_diff, &errnos)? {
+                                            continue;
                                         }
+
+                                        tracing::error!(
+                                            runtime_a = runtime_0_name,
+                                            runtime_b = runtime_1_name,
+                                            only_in_a = ?diff.only_in_a,
+                                            only_in_b = ?diff.only_in_b,
+                                            differs = ?diff.differs,
+                                            "Fs diff found."
+                                        );
+                                        serde_json::to_writer_pretty(
+                                            BufWriter::new(
+                                                fs::File::create(run.root_path().join("diff.json"))
+                                                    .wrap_err("failed to create diff file")?,
+                                            ),
+                                            &serde_json::json!({
+                                                "runtime_a": runtime_0_name,
+                                                "runtime_b": runtime_1_name,
+                                                "diff": diff,
+                                            }),
+                                        )
+                                        .wrap_err("failed to write diff")?;
+
+                                        write_divergence(&spec, &env, &run, &fs_diff, &errnos)?;
+                                        diff_done_tx.try_send(fs_diff).unwrap();
+                                        return Ok(());
                                     }
                                 }
                             }
@@ -660,10 +1205,13 @@ impl Fuzzer {
             let mut runtime_threads = Vec::new();
 
             for (i, (runtime_name, store, executor)) in runtimes.into_iter().enumerate() {
-                runtime_threads.push(
+                let executor = Arc::new(executor);
+
+                runtime_threads.push((
                     thread::Builder::new()
                         .name(format!("drv-{run_id}-{runtime_name}"))
                         .spawn_scoped(scope, {
+                            let executor = executor.clone();
                             let run_id = run_id.clone();
                             let data = data.clone();
                             let run = run.clone();
@@ -696,15 +1244,21 @@ impl Fuzzer {
                                         return Err(FuzzError::Time);
                                     }
 
+                                    if max_calls.is_some_and(|max_calls| iteration >= max_calls) {
+                                        tracing::info!(iteration, "Reached the call limit. Stopping fuzz run.");
+                                        over.store(true, atomic::Ordering::SeqCst);
+                                        break;
+                                    }
+
                                     {
                                         // Let the strategy thread select a function.
 
                                         let (mu, cond) = &*select_func_init;
                                         let mut state = mu.lock().unwrap();
 
-                                        state.0 += 1;
+                                        state.0.insert(runtime_name.clone());
 
-                                        if state.0 == n_runtimes {
+                                        if state.0.len() == n_runtimes {
                                             cond.notify_all();
                                         }
                                     }
@@ -723,9 +1277,9 @@ impl Fuzzer {
                                         let (mu, cond) = &*prep_params_init;
                                         let mut state = mu.lock().unwrap();
 
-                                        state.0 += 1;
+                                        state.0.insert(runtime_name.clone());
 
-                                        if state.0 == n_runtimes {
+                                        if state.0.len() == n_runtimes {
                                             // Dispatch to strategy thread to select a function.
                                             cond.notify_all();
                                         }
@@ -739,24 +1293,54 @@ impl Fuzzer {
                                             break;
                                         },
                                     };
-                                    let (errno, results) = execute_call(
+                                    debug_params(
+                                        &runtime_name,
+                                        &spec,
+                                        rtctxs.read().unwrap().get(i).unwrap(),
+                                        &function,
+                                        &params,
+                                    );
+
+                                    let start = Instant::now();
+                                    let outcome = execute_call(
                                         &spec,
                                         rtctxs.read().unwrap().get(i).unwrap(),
                                         &function,
                                         params.clone(),
                                         &executor,
-                                    )
-                                    .unwrap();
+                                    );
+                                    let duration = start.elapsed();
+                                    let (errno, results, written_params, abort) = match outcome {
+                                        | Ok((errno, results, written_params)) => {
+                                            (errno, results, Some(written_params), None)
+                                        },
+                                        | Err(err) => {
+                                            let abort = CallAbort::from_error(&err);
+
+                                            tracing::error!(
+                                                runtime = runtime_name,
+                                                function = function.name,
+                                                ?abort,
+                                                ?err,
+                                                "Call aborted."
+                                            );
+
+                                            (None, None, None, Some(abort))
+                                        },
+                                    };
 
                                     {
                                         let (mu, cond) = &*lift_results_init;
                                         let mut state = mu.lock().unwrap();
 
-                                        state.0 += 1;
-                                        state.2.insert(runtime_name.clone(), results.clone());
-                                        state.3 = errno;
+                                        state.0.insert(runtime_name.clone());
+
+                                        let (all_results, last_errno) = &mut state.2;
 
-                                        if state.0 == n_runtimes {
+                                        all_results.insert(runtime_name.clone(), results.clone());
+                                        *last_errno = errno;
+
+                                        if state.0.len() == n_runtimes {
                                             cond.notify_all();
                                         }
                                     }
@@ -770,11 +1354,18 @@ impl Fuzzer {
                                         },
                                     };
 
+                                    store
+                                        .write()
+                                        .unwrap()
+                                        .append_stdout(&executor.take_guest_stdout())
+                                        .wrap_err("failed to record guest stdout")?;
+
                                     store
                                         .write()
                                         .unwrap()
                                         .record_call(Call {
                                             function: function.name,
+                                            duration: Some(duration),
                                             errno:    errno,
                                             params:   params
                                                 .iter()
@@ -808,16 +1399,16 @@ impl Fuzzer {
                                         let (mu, cond) = &*diff_init;
                                         let mut state = mu.lock().unwrap();
 
-                                        state.0 += 1;
+                                        state.0.insert(runtime_name.clone());
 
-                                        let errno = (runtime_name.clone(), errno);
+                                        let errno = (runtime_name.clone(), errno, abort, written_params);
 
                                         match &mut state.2 {
                                             | Some(results) => results.push(errno),
                                             | None => state.2 = Some(vec![errno]),
                                         }
 
-                                        if state.0 == n_runtimes {
+                                        if state.0.len() == n_runtimes {
                                             cond.notify_all();
                                         }
                                     }
@@ -833,24 +1424,62 @@ impl Fuzzer {
 
                                     match diff_result {
                                         | DiffResult::Ok => (),
-                                        | DiffResult::Errno => {
+                                        | DiffResult::Errno { .. } => {
                                             tracing::info!("Errno diff found. Stopping fuzz run.");
                                             over.store(true, atomic::Ordering::SeqCst);
                                             break;
                                         },
-                                        | DiffResult::Filesystem => {
+                                        | DiffResult::Filesystem { .. } => {
                                             tracing::info!("Filesystem diff found. Stopping fuzz run.");
                                             over.store(true, atomic::Ordering::SeqCst);
                                             break;
                                         },
+                                        | DiffResult::Timeout => {
+                                            tracing::info!("Timeout diff found. Stopping fuzz run.");
+                                            over.store(true, atomic::Ordering::SeqCst);
+                                            break;
+                                        },
+                                        | DiffResult::ResultValue { .. } => {
+                                            tracing::info!("Result diff found. Stopping fuzz run.");
+                                            over.store(true, atomic::Ordering::SeqCst);
+                                            break;
+                                        },
+                                        | DiffResult::Memory { .. } => {
+                                            tracing::info!("Memory diff found. Stopping fuzz run.");
+                                            over.store(true, atomic::Ordering::SeqCst);
+                                            break;
+                                        },
+                                        | DiffResult::Malformed { runtime } => {
+                                            tracing::info!(
+                                                runtime,
+                                                "Executor sent a malformed response. Stopping fuzz run."
+                                            );
+                                            over.store(true, atomic::Ordering::SeqCst);
+                                            break;
+                                        },
+                                        | DiffResult::PipeClosed { runtime } => {
+                                            tracing::info!(runtime, "Executor closed its stdout. Stopping fuzz run.");
+                                            over.store(true, atomic::Ordering::SeqCst);
+                                            break;
+                                        },
+                                        | DiffResult::Trap { runtime, status } => {
+                                            tracing::info!(runtime, %status, "Trap diff found. Stopping fuzz run.");
+                                            over.store(true, atomic::Ordering::SeqCst);
+                                            break;
+                                        },
+                                        | DiffResult::Stdout { .. } => {
+                                            tracing::info!("Stdout diff found. Stopping fuzz run.");
+                                            over.store(true, atomic::Ordering::SeqCst);
+                                            break;
+                                        },
                                     }
                                     {
                                         let (mu, cond) = &*solve_output_contract_init;
                                         let mut state = mu.lock().unwrap();
 
-                                        state.0 += 1;
+                                        state.0.insert(runtime_name.clone());
 
-                                        if state.0 == n_runtimes {
+                                        if state.0.len() == n_runtimes {
                                             cond.notify_all();
                                         }
                                     }
@@ -869,7 +1498,8 @@ impl Fuzzer {
                             }
                         })
                         .wrap_err(format!("failed to spawn {runtime_name}"))?,
-                );
+                    executor,
+                ));
             }
 
             fill_done_rx.unsubscribe();
@@ -878,36 +1508,129 @@ impl Fuzzer {
             lift_results_done_rx.unsubscribe();
             solve_output_contract_done_rx.unsubscribe();
             diff_done_rx.unsubscribe();
+            kill_on_cancel(&runtime_threads, &cancel, &over);
 
-            Ok(())
-        })
+            Ok((run_id, run))
+        })?;
+
+        // Every thread of the run has been joined, so nothing uses it anymore.
+        let mut run = run.lock().unwrap();
+
+        run.write_suppressed()
+            .wrap_err("failed to write suppressed divergences")?;
+
+        if let Err(err) = run.finish() {
+            tracing::error!(%err, "Runtimes made different calls.");
+        }
+
+        Ok(run_id)
     }
 
-    pub fn fuzz_loop(&mut self, fuzzer_count: usize, time_limit: Option<Duration>) -> Result<(), eyre::Error> {
-        let enable_logging = !self.silent;
-        let cancel = Arc::new(AtomicBool::new(false));
+    /// Re-runs the stored run at `run_path` and checks that every runtime makes
+    /// the same calls again. The strategy only consumes a run's first data
+    /// buffer, so that is what gets replayed.
+    pub fn replay(&mut self, run_path: &Path) -> Result<(), eyre::Error> {
+        let recorded = self.check_recorded_runtimes(run_path)?;
 
-        if let Some(limit) = &time_limit {
-            thread::Builder::new()
-                .name(format!("timer"))
-                .spawn({
-                    let cancel = cancel.clone();
-                    let limit = limit.to_owned();
+        if self.silent {
+            return Err(err!("replay compares call traces, which aren't recorded with --silent"));
+        }
 
-                    move || {
-                        thread::sleep(limit);
-                        cancel.store(true, atomic::Ordering::SeqCst);
-                        tracing::warn!(
-                            duration = humantime::Duration::from(limit).to_string(),
-                            "Time's up. Cancelling."
-                        );
-                    }
-                })
-                .wrap_err("failed to spawn timer thread")?;
+        let run_id = self.fuzz(run_path.join("data").join("0"), None)?;
+
+        compare_runs(run_path, &self.store.root_path().join("runs").join(&run_id), &recorded)?;
+        tracing::info!(run_id, "Replay made the same calls as the recorded run.");
+
+        Ok(())
+    }
+
+    /// Continues the stored run at `run_path` in a new run: its calls are made
+    /// again, since the new run's runtimes start out without the resources they
+    /// created, and then fuzzing goes on with fresh data. Returns the id of the
+    /// new run.
+    pub fn resume(&mut self, run_path: &Path) -> Result<String, eyre::Error> {
+        let recorded = self.check_recorded_runtimes(run_path)?;
+        let runtime = recorded.first().ok_or_else(|| err!("run has no runtimes"))?;
+        let calls: Vec<Call> =
+            read_trace(&run_path.join("runtimes").join(runtime)).wrap_err("failed to read recorded trace")?;
+        let mut data = vec![0; self.buf_size];
+
+        run_rng(self.store.seed(), 0).fill_bytes(&mut data);
+        tracing::info!(ncalls = calls.len(), "Resuming run.");
+
+        self.fuzz_after(data, calls)
+    }
+
+    /// Makes the calls in the JSON trace at `prelude`, then keeps fuzzing with
+    /// fresh data. With a filter that allows a single function, this hammers
+    /// it from the state the prelude built. Returns the id of the new run.
+    pub fn hammer(&mut self, prelude: &Path) -> Result<String, eyre::Error> {
+        let calls = read_prelude(prelude)?;
+        let mut data = vec![0; self.buf_size];
+
+        run_rng(self.store.seed(), 0).fill_bytes(&mut data);
+        tracing::info!(ncalls = calls.len(), "Making prelude calls.");
+
+        self.fuzz_after(data, calls)
+    }
+
+    /// Runs the fuzzer once on each file in `corpus`, stopping at the first run
+    /// that diverges. Returns the ids of the runs made.
+    pub fn fuzz_corpus(&mut self, corpus: &Path) -> Result<Vec<String>, eyre::Error> {
+        let mut run_ids = Vec::new();
+
+        run_corpus(corpus, |data| {
+            let run_id = self.fuzz(data.to_owned(), None)?;
+            let diverged = self
+                .store
+                .root_path()
+                .join("runs")
+                .join(&run_id)
+                .join("divergence.json")
+                .exists();
+
+            tracing::info!(data = %data.display(), run_id, diverged, "Ran corpus input.");
+            run_ids.push(run_id);
+
+            Ok(diverged)
+        })?;
+
+        Ok(run_ids)
+    }
+
+    /// Runtimes the run at `run_path` recorded, which must be the configured
+    /// ones.
+    fn check_recorded_runtimes(&self, run_path: &Path) -> Result<BTreeSet<String>, eyre::Error> {
+        let recorded = recorded_runtimes(run_path).wrap_err("failed to list recorded runtimes")?;
+        let configured: BTreeSet<_> = self.runtimes.iter().map(|(name, _)| name.clone()).collect();
+
+        if recorded != configured {
+            return Err(err!(
+                "run recorded runtimes {recorded:?} but the config has {configured:?}"
+            ));
+        }
+
+        Ok(recorded)
+    }
+
+    pub fn fuzz_loop(&mut self, fuzzer_count: usize, time_limit: Option<Duration>) -> Result<(), eyre::Error> {
+        let enable_logging = !self.silent;
+        let call_timeout = self.call_timeout;
+        let max_message_size = self.max_message_size;
+        let stage_timeout = self.stage_timeout;
+        let seed = self.store.seed();
+        let max_calls = self.store.max_calls();
+        let diff_options = self.diff_options.clone();
+        let cancel = self.cancel.clone();
+
+        if let Some(limit) = time_limit {
+            spawn_timer(cancel.clone(), limit)?;
         }
 
         let pool = ThreadPool::new(fuzzer_count);
+        let corpus = Arc::new(Mutex::new(Corpus::new()));
         let runtime_initializers = Arc::new(self.runtimes.clone());
+        let mut next_run_idx = 0;
 
         while !cancel.load(atomic::Ordering::SeqCst) {
             if pool.active_count() + pool.queued_count() >= pool.max_count() {
@@ -915,6 +1638,9 @@ impl Fuzzer {
                 continue;
             }
 
+            let run_idx = next_run_idx;
+
+            next_run_idx += 1;
             pool.execute({
                 let store = self.store.clone();
                 let (run_id, run) = store.new_run::<Call>()?;
@@ -923,10 +1649,24 @@ impl Fuzzer {
                 let spec = self.spec.clone();
                 let cancel = cancel.clone();
                 let strategy = self.strategy.clone();
+                let weights = self.weights.clone();
+                let filter = self.filter.clone();
+                let gen_config = self.gen_config.clone();
+                let buf_size = self.buf_size;
+                let keep = self.keep;
+                let seed_files = self.seed_files.clone();
+                let guest_stdin = self.guest_stdin.clone();
+                let executor_bin = self.executor.clone();
                 let runtime_initializers = runtime_initializers.clone();
+                let diff_options = diff_options.clone();
                 let over = Arc::new(AtomicBool::new(false));
+                let corpus = corpus.clone();
+                let coverage_feedback = self.coverage_feedback;
+                let coverage = Arc::new(Mutex::new(Coverage::new()));
 
                 move || {
+                    let mut input = Vec::new();
+
                     thread::scope(|scope| -> Result<(), eyre::Error> {
                         let spec = Spec::preview1(&spec).wrap_err("failed to init spec")?;
                         let mut initializers: Vec<(String, EnvironmentInitializer)> = Default::default();
@@ -939,31 +1679,27 @@ impl Fuzzer {
                                 .wrap_err("failed to init runtime store")?;
                             let executor = {
                                 let store = store.read().unwrap();
-                                let stderr = fs::OpenOptions::new()
-                                    .write(true)
-                                    .create_new(true)
-                                    .open(store.root_path().join("stderr"))
-                                    .wrap_err("failed to open stderr file")?;
-                                let executor = RunningExecutor::from_wasi_runner(
-                                    runtime.as_ref(),
-                                    Path::new("target").join("release").join("wazzi-executor.wasm").as_ref(),
-                                    &store.root_path(),
-                                    Arc::new(Mutex::new(stderr)),
-                                    vec![MappedDir {
-                                        name:      "base".to_string(),
-                                        host_path: store.base_path().to_path_buf(),
-                                    }],
-                                )
-                                .unwrap();
-                                let initializer = runtime.initialize_state(
-                                    runtime_name.clone(),
-                                    &spec,
-                                    &executor,
-                                    vec![MappedDir {
-                                        name:      "base".to_string(),
-                                        host_path: store.base_path().to_path_buf(),
-                                    }],
-                                )?;
+                                let mut executor =
+                                    start_executor(runtime.as_ref(), &executor_bin, &store, guest_stdin.as_deref())?
+                                        .with_max_message_size(max_message_size);
+
+                                if let Some(call_timeout) = call_timeout {
+                                    executor = executor.with_call_timeout(call_timeout);
+                                }
+
+                                let initializer = runtime
+                                    .initialize_state(
+                                        runtime_name.clone(),
+                                        &spec,
+                                        &executor,
+                                        vec![MappedDir {
+                                            name:      "base".to_string(),
+                                            host_path: store.base_path().to_path_buf(),
+                                        }],
+                                    )?
+                                    .with_seed_files(seed_files.clone());
+
+                                initializer.write_seed_files().wrap_err("failed to write seed files")?;
 
                                 initializers.push((runtime_name.to_string(), initializer));
 
@@ -982,12 +1718,15 @@ impl Fuzzer {
                         let rtctxs = Arc::new(RwLock::new(rtctxs));
                         let fill_init = Arc::new((Mutex::new((0, 0usize)), Condvar::new()));
                         let (fill_done_tx, fill_done_rx) = broadcast_queue(1);
-                        let mmap = Arc::new(Mutex::new(MmapOptions::new().len(BUF_SIZE).map_anon().unwrap()));
+                        let mmap = Arc::new(Mutex::new(map_buffer(buf_size)?));
 
                         thread::Builder::new()
                             .name(format!("filler-{run_id}"))
                             .spawn_scoped(scope, {
                                 let run = run.clone();
+                                let mut rng = run_rng(seed, run_idx);
+                                let mut from_corpus = coverage_feedback;
+                                let corpus = corpus.clone();
                                 let fill_init = fill_init.clone();
                                 let mmap = mmap.clone();
                                 let over = over.clone();
@@ -1002,7 +1741,7 @@ impl Fuzzer {
                                             let state = mu.lock().unwrap();
                                             let gen = state.1;
                                             let (mut state, result) = cond
-                                                .wait_timeout_while(state, Duration::from_micros(100), |(ready, g)| {
+                                                .wait_timeout_while(state, STAGE_POLL_INTERVAL, |(ready, g)| {
                                                     *ready != n_runtimes && gen == *g
                                                 })
                                                 .unwrap();
@@ -1026,8 +1765,22 @@ impl Fuzzer {
                                             break;
                                         }
 
-                                        thread_rng().fill_bytes(&mut mmap.lock().unwrap());
-                                        run.lock().unwrap().write_data(&mmap.lock().unwrap()).unwrap();
+                                        {
+                                            let mut buf = mmap.lock().unwrap();
+
+                                            // Only the first buffer of a run is mutated, as the strategy
+                                            // rarely gets to the others.
+                                            if !(from_corpus
+                                                && rng.gen_bool(0.5)
+                                                && corpus.lock().unwrap().mutate(&mut rng, &mut buf))
+                                            {
+                                                rng.fill_bytes(&mut buf);
+                                            }
+
+                                            from_corpus = false;
+                                            run.lock().unwrap().write_data(&buf).unwrap();
+                                        }
+
                                         fill_done_tx.try_send(()).unwrap();
                                     }
 
@@ -1050,20 +1803,20 @@ impl Fuzzer {
                                 | Err(_) => return Ok(()),
                             }
 
-                            unsafe { std::slice::from_raw_parts(mmap.lock().unwrap().as_ptr(), BUF_SIZE) }
+                            unsafe { std::slice::from_raw_parts(mmap.lock().unwrap().as_ptr(), buf_size) }
                         };
-                        let select_func_init = Arc::new((Mutex::new((0, 0usize)), Condvar::new()));
+
+                        input = data.to_vec();
+
+                        let select_func_init = new_stage(());
                         let (select_func_done_tx, select_func_done_rx) = broadcast_queue(1);
-                        let prep_params_init = Arc::new((Mutex::new((0, 0usize)), Condvar::new()));
+                        let prep_params_init = new_stage(());
                         let (prep_params_done_tx, prep_params_done_rx) = broadcast_queue(1);
-                        let lift_results_init = Arc::new((
-                            Mutex::new((0, 0usize, HashMap::<String, Option<Vec<WasiValue>>>::new(), None::<i32>)),
-                            Condvar::new(),
-                        ));
+                        let lift_results_init = new_stage(LiftedResults::default());
                         let (lift_results_done_tx, lift_results_done_rx) = broadcast_queue(1);
-                        let solve_output_contract_init = Arc::new((Mutex::new((0, 0usize)), Condvar::new()));
+                        let solve_output_contract_init = new_stage(());
                         let (solve_output_contract_done_tx, solve_output_contract_done_rx) = broadcast_queue(1);
-                        let diff_init = Arc::new((Mutex::new((0, 0usize, None)), Condvar::new()));
+                        let diff_init = new_stage(None);
                         let (diff_done_tx, diff_done_rx) = broadcast_queue(1);
 
                         thread::Builder::new()
@@ -1076,6 +1829,7 @@ impl Fuzzer {
                                 let over = over.clone();
                                 let select_func_init = select_func_init.clone();
                                 let prep_params_init = prep_params_init.clone();
+                                let rts = rts.clone();
                                 let lift_results_init_pair = lift_results_init.clone();
                                 let solve_output_contract_init = solve_output_contract_init.clone();
                                 let rtctxs = rtctxs.clone();
@@ -1086,30 +1840,22 @@ impl Fuzzer {
                                     let mut u = Unstructured::new(&data);
                                     let cfg = z3::Config::new();
                                     let ctx = z3::Context::new(&cfg);
-                                    let mut strategy = strategy.into_call_strategy(&mut u, &ctx, preopens);
-
-                                    'outer: loop {
-                                        loop {
-                                            let (mu, cond) = &*select_func_init;
-                                            let state = mu.lock().unwrap();
-                                            let gen = state.1;
-                                            let (mut state, result) = cond
-                                                .wait_timeout_while(state, Duration::from_micros(100), |(select, g)| {
-                                                    *select != n_runtimes && *g == gen
-                                                })
-                                                .unwrap();
-
-                                            if over.load(atomic::Ordering::SeqCst) {
-                                                break 'outer;
-                                            }
+                                    let mut strategy = strategy
+                                        .into_call_strategy(&mut u, &ctx, preopens, weights, filter, gen_config);
 
-                                            if result.timed_out() {
-                                                continue;
-                                            }
+                                    let mut call_idx = 0;
 
-                                            state.0 = 0;
-                                            state.1 = state.1.wrapping_add(1);
-                                            break;
+                                    'outer: loop {
+                                        if wait_for_runtimes(
+                                            "select_function",
+                                            &select_func_init,
+                                            &rts,
+                                            stage_timeout,
+                                            &over,
+                                        )
+                                        .is_err()
+                                        {
+                                            break 'outer;
                                         }
 
                                         let function = strategy.select_function(&spec, &env.read().unwrap()).unwrap();
@@ -1122,28 +1868,16 @@ impl Fuzzer {
                                             },
                                         }
 
-                                        loop {
-                                            let (mu, cond) = &*prep_params_init;
-                                            let state = mu.lock().unwrap();
-                                            let gen = state.1;
-                                            let (mut state, result) = cond
-                                                .wait_timeout_while(state, Duration::from_micros(100), |(ready, g)| {
-                                                    (*ready != n_runtimes) && *g == gen
-                                                })
-                                                .unwrap();
-
-                                            if over.load(atomic::Ordering::SeqCst) {
-                                                break 'outer;
-                                            }
-
-                                            if result.timed_out() {
-                                                continue;
-                                            }
-
-                                            state.0 = 0;
-                                            state.1 = state.1.wrapping_add(1);
-
-                                            break;
+                                        if wait_for_runtimes(
+                                            "prepare_params",
+                                            &prep_params_init,
+                                            &rts,
+                                            stage_timeout,
+                                            &over,
+                                        )
+                                        .is_err()
+                                        {
+                                            break 'outer;
                                         }
 
                                         let params = strategy
@@ -1152,34 +1886,25 @@ impl Fuzzer {
 
                                         prep_params_done_tx.try_send(params.clone()).unwrap();
 
-                                        let (results, errno) = loop {
-                                            let (mu, cond) = &*lift_results_init_pair;
-                                            let state = mu.lock().unwrap();
-                                            let gen = state.1;
-                                            let (mut state, result) = cond
-                                                .wait_timeout_while(
-                                                    state,
-                                                    Duration::from_micros(100),
-                                                    |(ready, g, _results, _errno)| (*ready != n_runtimes) && *g == gen,
-                                                )
-                                                .unwrap();
-                                            let mut results = HashMap::new();
-
-                                            if over.load(atomic::Ordering::SeqCst) {
-                                                break 'outer;
-                                            }
-
-                                            if result.timed_out() {
-                                                continue;
-                                            }
-
-                                            state.0 = 0;
-                                            state.1 = state.1.wrapping_add(1);
-                                            std::mem::swap(&mut state.2, &mut results);
-
-                                            break (results, state.3.take());
+                                        let (results, errno) = match wait_for_runtimes(
+                                            "lift_results",
+                                            &lift_results_init_pair,
+                                            &rts,
+                                            stage_timeout,
+                                            &over,
+                                        ) {
+                                            | Ok(mut state) => {
+                                                let (results, errno) = &mut state.2;
+
+                                                (std::mem::take(results), errno.take())
+                                            },
+                                            | Err(_) => break 'outer,
                                         };
 
+                                        let origin = ResourceOrigin {
+                                            call_idx,
+                                            function: function.name.clone(),
+                                        };
                                         let mut resource_idxs = Vec::new();
 
                                         if errno.is_none() || errno.unwrap() == 0 {
@@ -1197,38 +1922,30 @@ impl Fuzzer {
 
                                                 let mut rtctxs = rtctxs.write().unwrap();
                                                 let ctxs = rtctxs.iter_mut().zip(result_values).collect_vec();
-                                                let resource_idx =
-                                                    env.write().unwrap().lift_recursively(&spec, ctxs, tdef);
+                                                let resource_idx = env.write().unwrap().lift_recursively(
+                                                    &spec,
+                                                    ctxs,
+                                                    tdef,
+                                                    Some(&origin),
+                                                );
 
                                                 resource_idxs.push(resource_idx);
                                             }
                                         }
 
                                         lift_results_done_tx.try_send(resource_idxs.clone()).unwrap();
-
-                                        loop {
-                                            let (mu, cond) = &*solve_output_contract_init;
-                                            let state = mu.lock().unwrap();
-                                            let gen = state.1;
-                                            let (mut state, result) = cond
-                                                .wait_timeout_while(state, Duration::from_millis(100), |(ready, g)| {
-                                                    (*ready != n_runtimes) && *g == gen
-                                                })
-                                                .unwrap();
-
-                                            if over.load(atomic::Ordering::SeqCst) {
-                                                tracing::info!("Fuzz over. Stopping thread.");
-                                                break 'outer;
-                                            }
-
-                                            if result.timed_out() {
-                                                continue;
-                                            }
-
-                                            state.0 = 0;
-                                            state.1 = state.1.wrapping_add(1);
-
-                                            break;
+                                        call_idx += 1;
+
+                                        if wait_for_runtimes(
+                                            "solve_output_contract",
+                                            &solve_output_contract_init,
+                                            &rts,
+                                            stage_timeout,
+                                            &over,
+                                        )
+                                        .is_err()
+                                        {
+                                            break 'outer;
                                         }
 
                                         if errno.is_none() || errno.unwrap() == 0 {
@@ -1239,11 +1956,12 @@ impl Fuzzer {
                                                     &spec,
                                                     function,
                                                     &mut env.write().unwrap(),
-                                                    params,
+                                                    params.clone(),
                                                     resource_idxs,
                                                     result_values.as_ref().map(Vec::as_slice),
                                                 )
                                                 .unwrap();
+                                            env.write().unwrap().remove_consumed_resources(function, &params);
                                         }
 
                                         solve_output_contract_done_tx.try_send(()).unwrap();
@@ -1257,128 +1975,172 @@ impl Fuzzer {
                             .name(format!("diff-{run_id}"))
                             .spawn_scoped(scope, {
                                 let run = run.clone();
+                                let spec = spec.clone();
                                 let over = over.clone();
-                                let cancel = cancel.clone();
                                 let diff_init = diff_init.clone();
+                                let env = env.clone();
+                                let rts = rts.clone();
 
                                 move || -> Result<(), FuzzError> {
                                     run.lock().unwrap().configure_progress_logging(enable_logging);
 
+                                    let mut stdouts = vec![Vec::new(); rts.len()];
+
                                     loop {
-                                        let errnos: Vec<_> = loop {
-                                            let (mu, cond) = &*diff_init;
-                                            let state = mu.lock().unwrap();
-                                            let gen = state.1;
-                                            let (mut state, result) = cond
-                                                .wait_timeout_while(
-                                                    state,
-                                                    Duration::from_micros(100),
-                                                    |(ready, g, _)| *ready != n_runtimes && gen == *g,
-                                                )
-                                                .unwrap();
+                                        let mut errnos: Vec<CallOutput> =
+                                            match wait_for_runtimes("diff", &diff_init, &rts, stage_timeout, &over) {
+                                                | Ok(mut state) => state.2.take().unwrap(),
+                                                | Err(_) => return Ok(()),
+                                            };
 
-                                            if over.load(atomic::Ordering::SeqCst)
-                                                || cancel.load(atomic::Ordering::SeqCst)
-                                            {
-                                                return Ok(());
-                                            }
+                                        let env = env.read().unwrap();
+                                        let run = run.lock().unwrap();
 
-                                            if result.timed_out() {
-                                                continue;
-                                            }
+                                        // Runtimes answer in any order, but are compared in the order they were added.
+                                        let order =
+                                            run.runtime_stores().map(|(name, _)| name.to_string()).collect_vec();
 
-                                            state.0 = 0;
-                                            state.1 = state.1.wrapping_add(1);
+                                        errnos
+                                            .sort_by_key(|(runtime, ..)| order.iter().position(|name| name == runtime));
 
-                                            break state.2.take().unwrap();
-                                        };
+                                        if let Some(diff) = diff_aborts(&errnos) {
+                                            write_divergence(&spec, &env, &run, &diff, &errnos)?;
+                                            diff_done_tx.try_send(diff).unwrap();
+                                            return Ok(());
+                                        }
 
-                                        let first = errnos.first().unwrap();
+                                        let errno_diff = diff_errnos(&errnos, &diff_options.errno_policy, |diff| {
+                                            diff_options.suppress(&spec, &env, &run, diff, &errnos)
+                                        })?;
 
-                                        for (_runtime_name, errno) in errnos.iter().skip(1) {
-                                            match (first.1, errno) {
-                                                | (None, None) => continue,
-                                                | (None, Some(_)) | (Some(_), None) => {
-                                                    tracing::info!("Errno diff found.");
-                                                    diff_done_tx.try_send(DiffResult::Errno).unwrap();
-                                                    return Ok(());
-                                                },
-                                                | (Some(l), &Some(r)) => {
-                                                    if (l == 0 && r != 0) || (l != 0 && r == 0) {
-                                                        tracing::info!("Errno diff found.");
-                                                        diff_done_tx.try_send(DiffResult::Errno).unwrap();
-                                                        return Ok(());
-                                                    }
-                                                },
-                                            }
+                                        if let Some(diff) = errno_diff {
+                                            tracing::info!(
+                                                ?diff,
+                                                errnos = ?errnos
+                                                    .iter()
+                                                    .map(|(runtime, errno, ..)| {
+                                                        format!("{runtime}: {}", render_errno(&spec, *errno))
+                                                    })
+                                                    .collect_vec(),
+                                                "Errno diff found."
+                                            );
+                                            write_divergence(&spec, &env, &run, &diff, &errnos)?;
+                                            diff_done_tx.try_send(diff).unwrap();
+                                            return Ok(());
                                         }
 
-                                        let run = run.lock().unwrap();
-                                        let runtimes = run.runtime_stores().collect::<Vec<_>>();
-
-                                        'outer: for (i, (runtime_0_name, runtime_0)) in runtimes.iter().enumerate() {
-                                            let runtime_0 = runtime_0.read().unwrap();
-                                            let call_0 = runtime_0.last_call().unwrap();
+                                        let function = last_called_function(&spec, &run);
+
+                                        if let Some(diff) =
+                                            function.and_then(|function| diff_memory(&spec, function, &errnos))
+                                        {
+                                            tracing::error!(?diff, "Memory diff found.");
+                                            write_divergence(&spec, &env, &run, &diff, &errnos)?;
+                                            diff_done_tx.try_send(diff).unwrap();
+                                            return Ok(());
+                                        }
+
+                                        let runtimes = run.runtime_stores().collect::<Vec<_>>();
+                                        // Walk each tree once up front instead of once per pair.
+                                        let digests = diff_options.tree_digests(
+                                            &runtimes
+                                                .iter()
+                                                .map(|(_name, runtime)| {
+                                                    runtime.read().unwrap().base_path().to_path_buf()
+                                                })
+                                                .collect_vec(),
+                                        )?;
+
+                                        for ((_name, runtime), stdout) in runtimes.iter().zip(&mut stdouts) {
+                                            read_appended(&runtime.read().unwrap().stdout_path(), stdout)
+                                                .wrap_err("failed to read stdout")?;
+                                        }
+
+                                        for (i, (runtime_0_name, runtime_0)) in runtimes.iter().enumerate() {
+                                            let runtime_0 = runtime_0.read().unwrap();
+                                            let call_0 = runtime_0.last_call().unwrap();
 
                                             for j in (i + 1)..runtimes.len() {
                                                 let (runtime_1_name, runtime_1) = runtimes.get(j).unwrap();
                                                 let runtime_1 = runtime_1.read().unwrap();
                                                 let call_1 = runtime_1.last_call().unwrap();
 
-                                                match (call_0.errno, call_1.errno) {
-                                                    | (None, None) => {},
-                                                    | (Some(errno_0), Some(errno_1))
-                                                        if errno_0 == 0 && errno_1 == 0
-                                                            || errno_0 != 0 && errno_1 != 0 => {},
-                                                    | _ => {
-                                                        tracing::error!(
-                                                            runtime_a = runtime_0_name,
-                                                            runtime_b = runtime_1_name,
-                                                            runtime_a_errno = call_0.errno,
-                                                            runtime_b_errno = call_1.errno,
-                                                            "Errno diff found!"
-                                                        );
+                                                if let Some(field) = diff_results(&spec, call_0, call_1) {
+                                                    tracing::error!(
+                                                        runtime_a = runtime_0_name,
+                                                        runtime_b = runtime_1_name,
+                                                        field,
+                                                        "Result diff found."
+                                                    );
+                                                    let diff = DiffResult::ResultValue {
+                                                        runtime_a: runtime_0_name.to_string(),
+                                                        runtime_b: runtime_1_name.to_string(),
+                                                        field,
+                                                    };
 
-                                                        over.store(true, atomic::Ordering::SeqCst);
-                                                        break 'outer;
-                                                    },
+                                                    write_divergence(&spec, &env, &run, &diff, &errnos)?;
+                                                    diff_done_tx.try_send(diff).unwrap();
+                                                    return Ok(());
+                                                }
+
+                                                if !stdout_matches(
+                                                    &stdouts[i],
+                                                    &stdouts[j],
+                                                    diff_options.unordered_stdout,
+                                                ) {
+                                                    tracing::error!(
+                                                        runtime_a = runtime_0_name,
+                                                        runtime_b = runtime_1_name,
+                                                        "Stdout diff found."
+                                                    );
+                                                    let diff = DiffResult::Stdout {
+                                                        runtime_a: runtime_0_name.to_string(),
+                                                        runtime_b: runtime_1_name.to_string(),
+                                                    };
+
+                                                    write_divergence(&spec, &env, &run, &diff, &errnos)?;
+                                                    diff_done_tx.try_send(diff).unwrap();
+                                                    return Ok(());
                                                 }
 
-                                                let runtime_0_walk = WalkDir::new(&runtime_0.base_path())
-                                                    .sort_by_file_name()
-                                                    .min_depth(1)
-                                                    .into_iter();
-                                                let runtime_1_walk = WalkDir::new(&runtime_1.base_path())
-                                                    .sort_by_file_name()
-                                                    .min_depth(1)
-                                                    .into_iter();
-
-                                                for pair in runtime_0_walk.zip_longest(runtime_1_walk) {
-                                                    match pair {
-                                                        | EitherOrBoth::Both(a, b) => {
-                                                            let a = a.wrap_err("failed to read dir entry")?;
-                                                            let b = b.wrap_err("failed to read dir entry")?;
-
-                                                            if a.depth() != b.depth()
-                                                                || a.file_type() != b.file_type()
-                                                                || a.file_name() != b.file_name()
-                                                                || (a.file_type().is_file()
-                                                                    && fs::read(a.path())
-                                                                        .wrap_err("failed to read file")?
-                                                                        != fs::read(b.path())
-                                                                            .wrap_err("failed to read file")?)
-                                                            {
-                                                                tracing::error!("Fs diff found.");
-                                                                diff_done_tx.try_send(DiffResult::Filesystem).unwrap();
-                                                                return Ok(());
-                                                            }
-                                                        },
-                                                        | EitherOrBoth::Left(_) | EitherOrBoth::Right(_) => {
-                                                            tracing::error!("Fs diff found.");
-                                                            diff_done_tx.try_send(DiffResult::Filesystem).unwrap();
-                                                            return Ok(());
-                                                        },
+                                                if let Some(diff) = digests
+                                                    .as_ref()
+                                                    .and_then(|digests| digest_diff(&digests[i], &digests[j]))
+                                                {
+                                                    let fs_diff = DiffResult::Filesystem {
+                                                        runtime_a: runtime_0_name.to_string(),
+                                                        runtime_b: runtime_1_name.to_string(),
+                                                        diff:      diff.clone(),
+                                                    };
+
+                                                    if diff_options.suppress(&spec, &env, &run, &fs_diff, &errnos)? {
+                                                        continue;
                                                     }
+
+                                                    tracing::error!(
+                                                        runtime_a = runtime_0_name,
+                                                        runtime_b = runtime_1_name,
+                                                        only_in_a = ?diff.only_in_a,
+                                                        only_in_b = ?diff.only_in_b,
+                                                        differs = ?diff.differs,
+                                                        "Fs diff found."
+                                                    );
+                                                    serde_json::to_writer_pretty(
+                                                        BufWriter::new(
+                                                            fs::File::create(run.root_path().join("diff.json"))
+                                                                .wrap_err("failed to create diff file")?,
+                                                        ),
+                                                        &serde_json::json!({
+                                                            "runtime_a": runtime_0_name,
+                                                            "runtime_b": runtime_1_name,
+                                                            "diff": diff,
+                                                        }),
+                                                    )
+                                                    .wrap_err("failed to write diff")?;
+
+                                                    write_divergence(&spec, &env, &run, &fs_diff, &errnos)?;
+                                                    diff_done_tx.try_send(fs_diff).unwrap();
+                                                    return Ok(());
                                                 }
                                             }
                                         }
@@ -1392,10 +2154,13 @@ impl Fuzzer {
                         let mut runtime_threads = Vec::new();
 
                         for (i, (runtime_name, store, executor)) in runtimes.into_iter().enumerate() {
-                            runtime_threads.push(
+                            let executor = Arc::new(executor);
+
+                            runtime_threads.push((
                                 thread::Builder::new()
                                     .name(format!("drv-{run_id}-{runtime_name}"))
                                     .spawn_scoped(scope, {
+                                        let executor = executor.clone();
                                         let run = run.clone();
                                         let run_id = run_id.clone();
                                         let mmap = mmap.clone();
@@ -1416,6 +2181,7 @@ impl Fuzzer {
                                         let spec = spec.clone();
                                         let rtctxs = rtctxs.clone();
                                         let runtime_name = runtime_name.clone();
+                                        let coverage = coverage.clone();
 
                                         move || -> Result<(), FuzzError> {
                                             run.lock().unwrap().configure_progress_logging(enable_logging);
@@ -1430,15 +2196,24 @@ impl Fuzzer {
                                                     return Err(FuzzError::Time);
                                                 }
 
+                                                if max_calls.is_some_and(|max_calls| iteration >= max_calls) {
+                                                    tracing::info!(
+                                                        iteration,
+                                                        "Reached the call limit. Stopping fuzz run."
+                                                    );
+                                                    over.store(true, atomic::Ordering::SeqCst);
+                                                    break;
+                                                }
+
                                                 {
                                                     // Let the strategy thread select a function.
 
                                                     let (mu, cond) = &*select_func_init;
                                                     let mut state = mu.lock().unwrap();
 
-                                                    state.0 += 1;
+                                                    state.0.insert(runtime_name.clone());
 
-                                                    if state.0 == n_runtimes {
+                                                    if state.0.len() == n_runtimes {
                                                         cond.notify_all();
                                                     }
                                                 }
@@ -1466,9 +2241,9 @@ impl Fuzzer {
                                                     let (mu, cond) = &*prep_params_init;
                                                     let mut state = mu.lock().unwrap();
 
-                                                    state.0 += 1;
+                                                    state.0.insert(runtime_name.clone());
 
-                                                    if state.0 == n_runtimes {
+                                                    if state.0.len() == n_runtimes {
                                                         // Dispatch to strategy thread to select a function.
                                                         cond.notify_all();
                                                     }
@@ -1484,24 +2259,62 @@ impl Fuzzer {
                                                         break;
                                                     },
                                                 };
-                                                let (errno, results) = execute_call(
+                                                debug_params(
+                                                    &runtime_name,
+                                                    &spec,
+                                                    rtctxs.read().unwrap().get(i).unwrap(),
+                                                    &function,
+                                                    &params,
+                                                );
+
+                                                let start = Instant::now();
+                                                let outcome = execute_call(
                                                     &spec,
                                                     rtctxs.read().unwrap().get(i).unwrap(),
                                                     &function,
                                                     params.clone(),
                                                     &executor,
-                                                )
-                                                .unwrap();
+                                                );
+                                                let duration = start.elapsed();
+
+                                                {
+                                                    let mut coverage = coverage.lock().unwrap();
+
+                                                    coverage.add_edges(&runtime_name, &executor.take_coverage());
+                                                    coverage.add_function(&function.name);
+                                                }
+
+                                                let (errno, results, written_params, abort) = match outcome {
+                                                    | Ok((errno, results, written_params)) => {
+                                                        (errno, results, Some(written_params), None)
+                                                    },
+                                                    | Err(err) => {
+                                                        let abort = CallAbort::from_error(&err);
+
+                                                        tracing::error!(
+                                                            runtime = runtime_name,
+                                                            function = function.name,
+                                                            ?abort,
+                                                            ?err,
+                                                            "Call aborted."
+                                                        );
+
+                                                        (None, None, None, Some(abort))
+                                                    },
+                                                };
 
                                                 {
                                                     let (mu, cond) = &*lift_results_init;
                                                     let mut state = mu.lock().unwrap();
 
-                                                    state.0 += 1;
-                                                    state.2.insert(runtime_name.clone(), results.clone());
-                                                    state.3 = errno;
+                                                    state.0.insert(runtime_name.clone());
+
+                                                    let (all_results, last_errno) = &mut state.2;
 
-                                                    if state.0 == n_runtimes {
+                                                    all_results.insert(runtime_name.clone(), results.clone());
+                                                    *last_errno = errno;
+
+                                                    if state.0.len() == n_runtimes {
                                                         cond.notify_all();
                                                     }
                                                 }
@@ -1517,11 +2330,18 @@ impl Fuzzer {
                                                     },
                                                 };
 
+                                                store
+                                                    .write()
+                                                    .unwrap()
+                                                    .append_stdout(&executor.take_guest_stdout())
+                                                    .wrap_err("failed to record guest stdout")?;
+
                                                 store
                                                     .write()
                                                     .unwrap()
                                                     .record_call(Call {
                                                         function: function.name,
+                                                        duration: Some(duration),
                                                         errno:    errno,
                                                         params:   params
                                                             .iter()
@@ -1567,7 +2387,7 @@ impl Fuzzer {
                                                     data = unsafe {
                                                         std::slice::from_raw_parts(
                                                             mmap.lock().unwrap().as_ptr(),
-                                                            BUF_SIZE,
+                                                            buf_size,
                                                         )
                                                     };
                                                     u = Unstructured::new(data);
@@ -1579,16 +2399,16 @@ impl Fuzzer {
                                                     let (mu, cond) = &*diff_init;
                                                     let mut state = mu.lock().unwrap();
 
-                                                    state.0 += 1;
+                                                    state.0.insert(runtime_name.clone());
 
-                                                    let errno = (runtime_name.clone(), errno);
+                                                    let errno = (runtime_name.clone(), errno, abort, written_params);
 
                                                     match &mut state.2 {
                                                         | Some(results) => results.push(errno),
                                                         | None => state.2 = Some(vec![errno]),
                                                     }
 
-                                                    if state.0 == n_runtimes {
+                                                    if state.0.len() == n_runtimes {
                                                         cond.notify_all();
                                                     }
                                                 }
@@ -1604,24 +2424,69 @@ impl Fuzzer {
 
                                                 match diff_result {
                                                     | DiffResult::Ok => (),
-                                                    | DiffResult::Errno => {
+                                                    | DiffResult::Errno { .. } => {
                                                         tracing::info!("Errno diff found. Stopping fuzz run.");
                                                         over.store(true, atomic::Ordering::SeqCst);
                                                         break;
                                                     },
-                                                    | DiffResult::Filesystem => {
+                                                    | DiffResult::Filesystem { .. } => {
                                                         tracing::info!("Filesystem diff found. Stopping fuzz run.");
                                                         over.store(true, atomic::Ordering::SeqCst);
                                                         break;
                                                     },
+                                                    | DiffResult::Timeout => {
+                                                        tracing::info!("Timeout diff found. Stopping fuzz run.");
+                                                        over.store(true, atomic::Ordering::SeqCst);
+                                                        break;
+                                                    },
+                                                    | DiffResult::ResultValue { .. } => {
+                                                        tracing::info!("Result diff found. Stopping fuzz run.");
+                                                        over.store(true, atomic::Ordering::SeqCst);
+                                                        break;
+                                                    },
+                                                    | DiffResult::Memory { .. } => {
+                                                        tracing::info!("Memory diff found. Stopping fuzz run.");
+                                                        over.store(true, atomic::Ordering::SeqCst);
+                                                        break;
+                                                    },
+                                                    | DiffResult::Malformed { runtime } => {
+                                                        tracing::info!(
+                                                            runtime,
+                                                            "Executor sent a malformed response. Stopping fuzz run."
+                                                        );
+                                                        over.store(true, atomic::Ordering::SeqCst);
+                                                        break;
+                                                    },
+                                                    | DiffResult::PipeClosed { runtime } => {
+                                                        tracing::info!(
+                                                            runtime,
+                                                            "Executor closed its stdout. Stopping fuzz run."
+                                                        );
+                                                        over.store(true, atomic::Ordering::SeqCst);
+                                                        break;
+                                                    },
+                                                    | DiffResult::Trap { runtime, status } => {
+                                                        tracing::info!(
+                                                            runtime,
+                                                            %status,
+                                                            "Trap diff found. Stopping fuzz run."
+                                                        );
+                                                        over.store(true, atomic::Ordering::SeqCst);
+                                                        break;
+                                                    },
+                                                    | DiffResult::Stdout { .. } => {
+                                                        tracing::info!("Stdout diff found. Stopping fuzz run.");
+                                                        over.store(true, atomic::Ordering::SeqCst);
+                                                        break;
+                                                    },
                                                 }
                                                 {
                                                     let (mu, cond) = &*solve_output_contract_init;
                                                     let mut state = mu.lock().unwrap();
 
-                                                    state.0 += 1;
+                                                    state.0.insert(runtime_name.clone());
 
-                                                    if state.0 == n_runtimes {
+                                                    if state.0.len() == n_runtimes {
                                                         cond.notify_all();
                                                     }
                                                 }
@@ -1640,7 +2505,8 @@ impl Fuzzer {
                                         }
                                     })
                                     .wrap_err(format!("failed to spawn {runtime_name}"))?,
-                            );
+                                executor,
+                            ));
                         }
 
                         fill_done_rx.unsubscribe();
@@ -1649,12 +2515,35 @@ impl Fuzzer {
                         lift_results_done_rx.unsubscribe();
                         solve_output_contract_done_rx.unsubscribe();
                         diff_done_rx.unsubscribe();
+                        kill_on_cancel(&runtime_threads, &cancel, &over);
 
                         Ok(())
                     })
                     .unwrap();
 
-                    run_.lock().unwrap().finish();
+                    if coverage_feedback && corpus.lock().unwrap().observe(&input, &coverage.lock().unwrap()) {
+                        tracing::info!(
+                            run_id,
+                            corpus = corpus.lock().unwrap().len(),
+                            "Run reached new coverage. Keeping its input."
+                        );
+                    }
+
+                    // Every thread of the run has been joined, so nothing uses its directory anymore.
+                    let mut run = run_.lock().unwrap();
+
+                    if let Err(err) = run.write_suppressed() {
+                        tracing::warn!(%err, run_id, "Failed to write suppressed divergences.");
+                    }
+
+                    match run.finish() {
+                        | Ok(()) => {
+                            if let Err(err) = keep.apply(run.root_path()) {
+                                tracing::warn!(%err, run_id, "Failed to remove run.");
+                            }
+                        },
+                        | Err(err) => tracing::error!(%err, "Runtimes made different calls."),
+                    }
                 }
             });
         }
@@ -1685,8 +2574,59 @@ enum FuzzError {
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 struct FuzzConfig {
-    runtimes: Vec<RuntimeFuzzConfig>,
-    spec:     PathBuf,
+    runtimes:     Vec<RuntimeFuzzConfig>,
+    spec:         PathBuf,
+    /// Whether the filesystem diff also compares Unix permission bits.
+    #[serde(default = "FuzzConfig::default_compare_mode")]
+    compare_mode: bool,
+    /// Known, benign divergences that shouldn't stop a run.
+    #[serde(default)]
+    ignore:       Vec<IgnoreRule>,
+    /// Directory whose files are copied into every runtime's `base` before
+    /// fuzzing.
+    #[serde(default)]
+    seed_dir:     Option<PathBuf>,
+    /// File whose contents every guest reads from stdin.
+    #[serde(default)]
+    stdin:        Option<PathBuf>,
+    /// Executor module to run instead of the release build.
+    #[serde(default)]
+    executor:     Option<PathBuf>,
+}
+
+impl FuzzConfig {
+    fn default_compare_mode() -> bool {
+        true
+    }
+
+    /// Keeps only the named runtimes, in config order.
+    fn select_runtimes(&mut self, names: &[String]) -> Result<(), eyre::Error> {
+        for name in names {
+            if !self.runtimes.iter().any(|runtime| &runtime.name == name) {
+                return Err(err!("runtime `{name}` is not in the config"));
+            }
+        }
+
+        self.runtimes.retain(|runtime| names.contains(&runtime.name));
+        self.validate()
+    }
+
+    fn validate(&self) -> Result<(), eyre::Error> {
+        if self.runtimes.len() < 2 {
+            return Err(err!(
+                "differential fuzzing needs at least two runtimes, but {} {} configured",
+                self.runtimes.len(),
+                if self.runtimes.len() == 1 { "is" } else { "are" },
+            ));
+        }
+
+        // Pipeline stages tell runtimes apart by name.
+        if let Some(name) = self.runtimes.iter().map(|runtime| &runtime.name).duplicates().next() {
+            return Err(err!("runtime `{name}` is configured more than once"));
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
@@ -1697,12 +2637,2347 @@ enum FuzzLoopLimit {
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 struct RuntimeFuzzConfig {
-    name: String,
+    name:       String,
+    /// Binary to run instead of the one found on `PATH`.
+    #[serde(default)]
+    path:       Option<PathBuf>,
+    /// Flags passed to the runtime before the module.
+    #[serde(default)]
+    extra_args: Vec<String>,
+}
+
+impl RuntimeFuzzConfig {
+    fn runtime(&self) -> Result<Box<dyn Runtime>, eyre::Error> {
+        // Runtimes live as long as the fuzzer, so the path can be leaked.
+        let path = self.path.clone().map(|path| &*Box::leak(path.into_boxed_path()));
+
+        runtime_by_name(&self.name, path, self.extra_args.clone()).ok_or_else(|| err!("unknown runtime {}", self.name))
+    }
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
 enum DiffResult {
     Ok,
+    Errno {
+        runtime_a: String,
+        runtime_b: String,
+    },
+    Filesystem {
+        runtime_a: String,
+        runtime_b: String,
+        diff:      FsDiff,
+    },
+    Memory {
+        runtime_a: String,
+        runtime_b: String,
+        param_idx: usize,
+    },
+    Malformed {
+        runtime: String,
+    },
+    PipeClosed {
+        runtime: String,
+    },
+    ResultValue {
+        runtime_a: String,
+        runtime_b: String,
+        /// Path to the differing value, e.g. `ok.size`.
+        field:     String,
+    },
+    Stdout {
+        runtime_a: String,
+        runtime_b: String,
+    },
+    Timeout,
+    Trap {
+        runtime: String,
+        status:  process::ExitStatus,
+    },
+}
+
+/// Machine-readable summary of why a run stopped, written to
+/// `divergence.json` in the run directory.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+struct Divergence {
+    kind:        DivergenceKind,
+    runtimes:    Vec<String>,
+    function:    Option<String>,
+    params:      Vec<MaybeResourceValue>,
+    /// Calls that created the resources passed as params, by param name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    origins:     BTreeMap<String, ResourceOrigin>,
+    errnos:      BTreeMap<String, Option<i32>>,
+    /// Errnos with their symbolic names, e.g. `44 (ENOENT)`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    errno_names: BTreeMap<String, String>,
+    /// Relative to the `base` directory.
+    paths:       Vec<PathBuf>,
+    /// Path to the differing result value, e.g. `ok.size`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field:       Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+enum DivergenceKind {
     Errno,
     Filesystem,
+    Malformed,
+    Memory,
+    PipeClosed,
+    ResultValue,
+    Stdout,
+    Timeout,
+    Trap,
+}
+
+/// Renders an errno as its number and symbolic name, e.g. `44 (ENOENT)`.
+fn render_errno(spec: &Spec, errno: Option<i32>) -> String {
+    match errno {
+        | None => "none".to_string(),
+        | Some(errno) => match spec.errno_name(errno) {
+            | Some(name) => format!("{errno} ({name})"),
+            | None => errno.to_string(),
+        },
+    }
+}
+
+fn write_divergence(
+    spec: &Spec,
+    env: &Environment,
+    run: &RunStore<Call>,
+    diff: &DiffResult,
+    outputs: &[CallOutput],
+) -> Result<(), eyre::Error> {
+    let Some(divergence) = divergence(spec, env, run, diff, outputs) else {
+        return Ok(());
+    };
+
+    run.record_diff(serde_json::to_value(divergence.kind)?.as_str().unwrap_or_default());
+    serde_json::to_writer_pretty(
+        BufWriter::new(
+            fs::File::create(run.root_path().join("divergence.json")).wrap_err("failed to create divergence file")?,
+        ),
+        &divergence,
+    )
+    .wrap_err("failed to write divergence")
+}
+
+fn divergence(
+    spec: &Spec,
+    env: &Environment,
+    run: &RunStore<Call>,
+    diff: &DiffResult,
+    outputs: &[CallOutput],
+) -> Option<Divergence> {
+    let (kind, runtimes, paths) = match diff {
+        | DiffResult::Ok => return None,
+        | DiffResult::Errno { runtime_a, runtime_b } => (
+            DivergenceKind::Errno,
+            vec![runtime_a.clone(), runtime_b.clone()],
+            vec![],
+        ),
+        | DiffResult::Filesystem {
+            runtime_a,
+            runtime_b,
+            diff,
+        } => (
+            DivergenceKind::Filesystem,
+            vec![runtime_a.clone(), runtime_b.clone()],
+            diff.paths().cloned().collect(),
+        ),
+        | DiffResult::Memory {
+            runtime_a, runtime_b, ..
+        } => (
+            DivergenceKind::Memory,
+            vec![runtime_a.clone(), runtime_b.clone()],
+            vec![],
+        ),
+        | DiffResult::Malformed { runtime } => (DivergenceKind::Malformed, vec![runtime.clone()], vec![]),
+        | DiffResult::PipeClosed { runtime } => (DivergenceKind::PipeClosed, vec![runtime.clone()], vec![]),
+        | DiffResult::ResultValue {
+            runtime_a, runtime_b, ..
+        } => (
+            DivergenceKind::ResultValue,
+            vec![runtime_a.clone(), runtime_b.clone()],
+            vec![],
+        ),
+        | DiffResult::Stdout { runtime_a, runtime_b } => (
+            DivergenceKind::Stdout,
+            vec![runtime_a.clone(), runtime_b.clone()],
+            vec![],
+        ),
+        | DiffResult::Timeout => (
+            DivergenceKind::Timeout,
+            outputs
+                .iter()
+                .filter(|(_runtime, _errno, abort, _)| *abort == Some(CallAbort::Timeout))
+                .map(|(runtime, ..)| runtime.clone())
+                .collect(),
+            vec![],
+        ),
+        | DiffResult::Trap { runtime, .. } => (DivergenceKind::Trap, vec![runtime.clone()], vec![]),
+    };
+    let last_call = run
+        .runtime_stores()
+        .find(|(name, _store)| runtimes.iter().any(|runtime| runtime == name))
+        .and_then(|(_name, store)| store.read().unwrap().last_call().cloned());
+
+    let origins = last_call
+        .as_ref()
+        .and_then(|call| Some((spec.get_function(&call.function)?, call)))
+        .map(|(function, call)| {
+            function
+                .params
+                .iter()
+                .zip(&call.params)
+                .filter_map(|(param, value)| {
+                    Some((param.name.clone(), env.resource_origin(value.resource_idx?)?.clone()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Some(Divergence {
+        kind,
+        runtimes,
+        function: last_call.as_ref().map(|call| call.function.clone()),
+        params: last_call.map(|call| call.params).unwrap_or_default(),
+        origins,
+        errnos: outputs
+            .iter()
+            .map(|(runtime, errno, ..)| (runtime.clone(), *errno))
+            .collect(),
+        errno_names: outputs
+            .iter()
+            .filter(|(_runtime, errno, ..)| errno.is_some())
+            .map(|(runtime, errno, ..)| (runtime.clone(), render_errno(spec, *errno)))
+            .collect(),
+        paths,
+        field: match diff {
+            | DiffResult::ResultValue { field, .. } => Some(field.clone()),
+            | _ => None,
+        },
+    })
+}
+
+/// Checks that every runtime made the same calls with the same arguments and
+/// errnos in the `replayed` run as in the `recorded` one.
+fn compare_runs(recorded: &Path, replayed: &Path, runtimes: &BTreeSet<String>) -> Result<(), eyre::Error> {
+    for runtime in runtimes {
+        let trace = |run: &Path| read_trace::<Call>(&run.join("runtimes").join(runtime));
+        let recorded = trace(recorded).wrap_err("failed to read recorded trace")?;
+        let replayed = trace(replayed).wrap_err("failed to read replayed trace")?;
+
+        for (i, pair) in recorded.iter().zip_longest(replayed.iter()).enumerate() {
+            match pair {
+                | EitherOrBoth::Both(a, b) if a.function == b.function && a.errno == b.errno && a.params == b.params =>
+                    {},
+                | EitherOrBoth::Both(a, b) => {
+                    return Err(err!(
+                        "{runtime} call {i} diverged: recorded {} (errno {:?}), replayed {} (errno {:?})",
+                        a.function,
+                        a.errno,
+                        b.function,
+                        b.errno
+                    ))
+                },
+                | EitherOrBoth::Left(_) | EitherOrBoth::Right(_) => {
+                    return Err(err!(
+                        "{runtime} made {} calls in the replay but {} in the recorded run",
+                        replayed.len(),
+                        recorded.len()
+                    ))
+                },
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// How often a pipeline stage wakes up to check whether the run is over.
+const STAGE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A pipeline stage: the runtimes that have arrived, a generation counter and
+/// whatever they hand over.
+type Stage<T> = (Mutex<(BTreeSet<String>, usize, T)>, Condvar);
+
+/// What runtimes hand over at the results stage: each one's result values, and
+/// the errno of the call.
+type LiftedResults = (HashMap<String, Option<Vec<WasiValue>>>, Option<i32>);
+
+fn new_stage<T>(handover: T) -> Arc<Stage<T>> {
+    Arc::new((Mutex::new((BTreeSet::new(), 0, handover)), Condvar::new()))
+}
+
+#[derive(PartialEq, Eq, Debug)]
+enum StageError {
+    Over,
+    /// The runtimes that didn't arrive within the stage timeout.
+    Stalled(Vec<String>),
+}
+
+/// Waits until every one of `runtimes` has arrived at `stage`, then resets it
+/// for the next call. Gives up once the run is over, or ends the run if some
+/// runtime doesn't arrive within `timeout`.
+fn wait_for_runtimes<'a, T>(
+    name: &str,
+    stage: &'a Stage<T>,
+    runtimes: &[String],
+    timeout: Option<Duration>,
+    over: &AtomicBool,
+) -> Result<MutexGuard<'a, (BTreeSet<String>, usize, T)>, StageError> {
+    let (mu, cond) = stage;
+    let start = Instant::now();
+    let mut state = mu.lock().unwrap();
+
+    loop {
+        let result;
+
+        (state, result) = cond
+            .wait_timeout_while(state, STAGE_POLL_INTERVAL, |(arrived, ..)| {
+                arrived.len() != runtimes.len()
+            })
+            .unwrap();
+
+        if over.load(atomic::Ordering::SeqCst) {
+            return Err(StageError::Over);
+        }
+
+        if !result.timed_out() {
+            break;
+        }
+
+        if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+            let missing = runtimes
+                .iter()
+                .filter(|runtime| !state.0.contains(*runtime))
+                .cloned()
+                .collect_vec();
+
+            tracing::error!(
+                stage = name,
+                ?missing,
+                "Runtimes failed to arrive at pipeline stage. Ending run."
+            );
+            over.store(true, atomic::Ordering::SeqCst);
+
+            return Err(StageError::Stalled(missing));
+        }
+    }
+
+    state.0.clear();
+    state.1 = state.1.wrapping_add(1);
+
+    Ok(state)
+}
+
+/// Appends to `buf` what was written to the file at `path` past the bytes
+/// `buf` already holds. Guest stdout is only ever appended to, so this saves
+/// reading it all again after every call.
+fn read_appended(path: &Path, buf: &mut Vec<u8>) -> Result<(), io::Error> {
+    let mut file = fs::File::open(path)?;
+
+    file.seek(io::SeekFrom::Start(buf.len() as u64))?;
+    file.read_to_end(buf)?;
+
+    Ok(())
+}
+
+/// Returns the generator that fills the fuzz buffers of the `run_idx`-th run.
+/// With a seed, every run gets its own reproducible stream.
+fn run_rng(seed: Option<u64>, run_idx: u64) -> StdRng {
+    match seed {
+        | Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(run_idx)),
+        | None => StdRng::from_entropy(),
+    }
+}
+
+/// Knobs controlling what the differ treats as a divergence.
+#[derive(Clone, Debug)]
+struct DiffOptions {
+    unordered_stdout: bool,
+    fs_diff:          bool,
+    compare_mode:     bool,
+    errno_policy:     ErrnoPolicy,
+    ignore:           Vec<IgnoreRule>,
+}
+
+impl DiffOptions {
+    /// Walks each of the `roots` once, or none of them without fs diffing.
+    fn tree_digests(&self, roots: &[PathBuf]) -> Result<Option<Vec<TreeDigest>>, eyre::Error> {
+        if !self.fs_diff {
+            return Ok(None);
+        }
+
+        roots
+            .iter()
+            .map(|root| tree_digest(root, self.compare_mode))
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+
+    /// Whether an ignore rule matches `diff`. Matched diffs are recorded in
+    /// the run, to be written to `suppressed.json` once it ends.
+    fn suppress(
+        &self,
+        spec: &Spec,
+        env: &Environment,
+        run: &RunStore<Call>,
+        diff: &DiffResult,
+        outputs: &[CallOutput],
+    ) -> Result<bool, eyre::Error> {
+        let Some(divergence) = divergence(spec, env, run, diff, outputs) else {
+            return Ok(false);
+        };
+        let Some(rule) = self.ignore.iter().find(|rule| rule.matches(&divergence)) else {
+            return Ok(false);
+        };
+
+        tracing::info!(?diff, ?rule, "Diff suppressed by ignore rule.");
+        run.record_suppressed(serde_json::to_value(SuppressedDivergence {
+            rule: rule.clone(),
+            divergence,
+        })?);
+
+        Ok(true)
+    }
+}
+
+/// A known divergence between runtimes that isn't worth stopping a run for.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+#[serde(untagged)]
+enum IgnoreRule {
+    /// Calls to `function` where one runtime returns each of the two errnos.
+    Errno { function: String, errnos: [i32; 2] },
+    /// Filesystem diffs at paths matching the glob, relative to `base`.
+    Path { path: PathGlob },
+}
+
+impl IgnoreRule {
+    fn matches(&self, divergence: &Divergence) -> bool {
+        match self {
+            | IgnoreRule::Errno { function, errnos } => {
+                let mut actual = divergence
+                    .runtimes
+                    .iter()
+                    .filter_map(|runtime| divergence.errnos.get(runtime).copied().flatten())
+                    .collect_vec();
+                let mut errnos = errnos.to_vec();
+
+                actual.sort();
+                errnos.sort();
+                divergence.kind == DivergenceKind::Errno
+                    && divergence.function.as_ref() == Some(function)
+                    && actual == errnos
+            },
+            | IgnoreRule::Path { path: glob } => {
+                divergence.kind == DivergenceKind::Filesystem
+                    && divergence.paths.iter().all(|path| glob.matcher.is_match(path))
+            },
+        }
+    }
+}
+
+/// A path glob, compiled when the config is loaded.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(try_from = "String", into = "String")]
+struct PathGlob {
+    glob:    globset::Glob,
+    matcher: globset::GlobMatcher,
+}
+
+impl TryFrom<String> for PathGlob {
+    type Error = globset::Error;
+
+    fn try_from(glob: String) -> Result<Self, Self::Error> {
+        let glob = globset::Glob::new(&glob)?;
+
+        Ok(Self {
+            matcher: glob.compile_matcher(),
+            glob,
+        })
+    }
+}
+
+impl From<PathGlob> for String {
+    fn from(glob: PathGlob) -> Self {
+        glob.glob.glob().to_string()
+    }
+}
+
+impl PartialEq for PathGlob {
+    fn eq(&self, other: &Self) -> bool {
+        self.glob == other.glob
+    }
+}
+
+impl Eq for PathGlob {
+}
+
+/// An entry of `suppressed.json`.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+struct SuppressedDivergence {
+    rule:       IgnoreRule,
+    divergence: Divergence,
+}
+
+/// Decides whether two runtimes returning different errnos is a divergence.
+#[derive(PartialEq, Eq, Clone, Debug)]
+enum ErrnoPolicy {
+    /// Only success versus failure matters.
+    ZeroNonZero,
+    Exact,
+    /// Errnos are compared exactly after mapping through the table.
+    Custom(HashMap<i32, i32>),
+}
+
+impl ErrnoPolicy {
+    fn equivalent(&self, a: i32, b: i32) -> bool {
+        match self {
+            | Self::ZeroNonZero => (a == 0) == (b == 0),
+            | Self::Exact => a == b,
+            | Self::Custom(map) => map.get(&a).unwrap_or(&a) == map.get(&b).unwrap_or(&b),
+        }
+    }
+}
+
+impl FromStr for ErrnoPolicy {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            | "zero-non-zero" => return Ok(Self::ZeroNonZero),
+            | "exact" => return Ok(Self::Exact),
+            | _ => (),
+        }
+
+        let pairs = s
+            .strip_prefix("custom:")
+            .ok_or_else(|| err!("unknown errno policy `{s}`"))?;
+        let mut map = HashMap::new();
+
+        for pair in pairs.split(',').filter(|pair| !pair.is_empty()) {
+            let (from, to) = pair
+                .split_once('=')
+                .ok_or_else(|| err!("expected `A=B` in errno policy, got `{pair}`"))?;
+
+            map.insert(
+                from.trim().parse().wrap_err("invalid errno")?,
+                to.trim().parse().wrap_err("invalid errno")?,
+            );
+        }
+
+        Ok(Self::Custom(map))
+    }
+}
+
+/// Every entry at which two runtimes' filesystems diverge, by path relative
+/// to the `base` directory.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Default, Debug)]
+struct FsDiff {
+    only_in_a: BTreeSet<PathBuf>,
+    only_in_b: BTreeSet<PathBuf>,
+    /// Entries both trees have, but that differ.
+    differs:   BTreeMap<PathBuf, FsDiffKind>,
+}
+
+impl FsDiff {
+    fn is_empty(&self) -> bool {
+        self.only_in_a.is_empty() && self.only_in_b.is_empty() && self.differs.is_empty()
+    }
+
+    fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.only_in_a.iter().chain(&self.only_in_b).chain(self.differs.keys())
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+enum FsDiffKind {
+    Type,
+    /// File contents or symlink targets differ.
+    Content,
+    Mode,
+}
+
+/// What the differ compares of one entry in a `base` tree.
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct EntryDigest {
+    file_type: fs::FileType,
+    /// Hash of a file's contents or a symlink's target.
+    content:   Option<blake3::Hash>,
+    /// Only recorded when comparing modes.
+    mode:      Option<u32>,
+}
+
+/// A `base` tree keyed by relative path. Paths order by component, so this
+/// iterates in the same order as a sorted walk.
+type TreeDigest = BTreeMap<PathBuf, EntryDigest>;
+
+fn tree_digest(root: &Path, compare_mode: bool) -> Result<TreeDigest, eyre::Error> {
+    let mut digest = TreeDigest::new();
+
+    for entry in WalkDir::new(root).min_depth(1) {
+        let entry = entry.wrap_err("failed to read dir entry")?;
+        let file_type = entry.file_type();
+        let content = if file_type.is_file() {
+            Some(file_hash(entry.path()).wrap_err("failed to hash file")?)
+        } else if file_type.is_symlink() {
+            // Links aren't followed, so compare where they point instead. This
+            // also keeps dangling links from failing the run.
+            Some(blake3::hash(
+                fs::read_link(entry.path())
+                    .wrap_err("failed to read link")?
+                    .as_os_str()
+                    .as_bytes(),
+            ))
+        } else {
+            None
+        };
+        let mode = if compare_mode {
+            Some(
+                fs::symlink_metadata(entry.path())
+                    .wrap_err("failed to read metadata")?
+                    .permissions()
+                    .mode(),
+            )
+        } else {
+            None
+        };
+
+        digest.insert(
+            entry.path().strip_prefix(root).unwrap().to_path_buf(),
+            EntryDigest {
+                file_type,
+                content,
+                mode,
+            },
+        );
+    }
+
+    Ok(digest)
+}
+
+/// Hashes the file at `path` a chunk at a time, so that memory use doesn't
+/// grow with whatever size a fuzzed `fd_write` left the file at.
+fn file_hash(path: &Path) -> Result<blake3::Hash, io::Error> {
+    let mut hasher = blake3::Hasher::new();
+
+    hasher.update_reader(fs::File::open(path)?)?;
+
+    Ok(hasher.finalize())
+}
+
+/// Compares two trees as sets of paths, so that an entry only one of them has
+/// doesn't shift the comparison of the others.
+fn digest_diff(a: &TreeDigest, b: &TreeDigest) -> Option<FsDiff> {
+    let mut diff = FsDiff::default();
+
+    for pair in a.iter().merge_join_by(b, |(a_path, _), (b_path, _)| a_path.cmp(b_path)) {
+        match pair {
+            | EitherOrBoth::Left((path, _)) => {
+                diff.only_in_a.insert(path.clone());
+            },
+            | EitherOrBoth::Right((path, _)) => {
+                diff.only_in_b.insert(path.clone());
+            },
+            | EitherOrBoth::Both((path, a), (_, b)) => {
+                let kind = if a.file_type != b.file_type {
+                    FsDiffKind::Type
+                } else if a.content != b.content {
+                    FsDiffKind::Content
+                } else if a.mode != b.mode {
+                    FsDiffKind::Mode
+                } else {
+                    continue;
+                };
+
+                diff.differs.insert(path.clone(), kind);
+            },
+        }
+    }
+
+    (!diff.is_empty()).then_some(diff)
+}
+
+/// Why a runtime's executor failed to answer a call.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum CallAbort {
+    Timeout,
+    PipeClosed,
+    Exited(process::ExitStatus),
+    /// The executor answered with something that couldn't be decoded, e.g. an
+    /// oversized message or a value that doesn't match the spec.
+    Malformed,
+}
+
+impl CallAbort {
+    fn from_error(err: &eyre::Error) -> Self {
+        match err.downcast_ref::<CallError>() {
+            | Some(CallError::Timeout(_)) => Self::Timeout,
+            | Some(CallError::PipeClosed) => Self::PipeClosed,
+            | Some(&CallError::ProcessExited(status)) => Self::Exited(status),
+            | Some(CallError::Protocol(_) | CallError::MessageTooLarge { .. }) | None => Self::Malformed,
+        }
+    }
+}
+
+/// A runtime's answer to the last call: its errno, why it aborted if it did,
+/// and its params as read back from guest memory if it didn't.
+type CallOutput = (String, Option<i32>, Option<CallAbort>, Option<Vec<WasiValue>>);
+
+/// Reports a diff if any runtime aborted the last call. Traps, then closed
+/// pipes, then malformed responses take precedence over timeouts since they
+/// point at the runtime that misbehaved first.
+fn diff_aborts(outcomes: &[CallOutput]) -> Option<DiffResult> {
+    let aborted = outcomes
+        .iter()
+        .filter_map(|(runtime, _errno, abort, _written_params)| abort.map(|abort| (runtime, abort)))
+        .collect_vec();
+
+    if aborted.is_empty() {
+        return None;
+    }
+
+    if aborted.len() == outcomes.len() {
+        tracing::warn!("All runtimes aborted the call.");
+    }
+
+    let trap = aborted.iter().find_map(|&(runtime, abort)| match abort {
+        | CallAbort::Exited(status) => Some(DiffResult::Trap {
+            runtime: runtime.to_owned(),
+            status,
+        }),
+        | CallAbort::Timeout | CallAbort::PipeClosed | CallAbort::Malformed => None,
+    });
+    let pipe_closed = aborted.iter().find_map(|&(runtime, abort)| match abort {
+        | CallAbort::PipeClosed => Some(DiffResult::PipeClosed {
+            runtime: runtime.to_owned(),
+        }),
+        | CallAbort::Timeout | CallAbort::Exited(_) | CallAbort::Malformed => None,
+    });
+    let malformed = aborted.iter().find_map(|&(runtime, abort)| match abort {
+        | CallAbort::Malformed => Some(DiffResult::Malformed {
+            runtime: runtime.to_owned(),
+        }),
+        | CallAbort::Timeout | CallAbort::Exited(_) | CallAbort::PipeClosed => None,
+    });
+    let diff = trap.or(pipe_closed).or(malformed).unwrap_or(DiffResult::Timeout);
+
+    tracing::error!(?aborted, ?diff, "Abort diff found.");
+
+    Some(diff)
+}
+
+/// Sets `cancel` once `limit` has passed.
+fn spawn_timer(cancel: Arc<AtomicBool>, limit: Duration) -> Result<(), eyre::Error> {
+    thread::Builder::new()
+        .name("timer".to_string())
+        .spawn(move || {
+            thread::sleep(limit);
+            cancel.store(true, atomic::Ordering::SeqCst);
+            tracing::warn!(
+                duration = humantime::Duration::from(limit).to_string(),
+                "Time's up. Cancelling."
+            );
+        })
+        .wrap_err("failed to spawn timer thread")?;
+
+    Ok(())
+}
+
+/// How long runtimes get to stop on their own after a run is cancelled.
+const CANCEL_GRACE_PERIOD: Duration = Duration::from_secs(1);
+
+/// Waits for the runtime threads of a run to finish. Once `cancel` is set, ends
+/// the run and kills the executors of runtimes still stuck in a call after the
+/// grace period, since they would never notice otherwise.
+fn kill_on_cancel<T>(
+    runtime_threads: &[(ScopedJoinHandle<'_, T>, Arc<RunningExecutor>)],
+    cancel: &AtomicBool,
+    over: &AtomicBool,
+) {
+    let all_finished = || runtime_threads.iter().all(|(thread, _)| thread.is_finished());
+
+    while !cancel.load(atomic::Ordering::SeqCst) {
+        if all_finished() {
+            return;
+        }
+
+        thread::sleep(STAGE_POLL_INTERVAL);
+    }
+
+    over.store(true, atomic::Ordering::SeqCst);
+
+    let start = Instant::now();
+
+    while !all_finished() && start.elapsed() < CANCEL_GRACE_PERIOD {
+        thread::sleep(STAGE_POLL_INTERVAL);
+    }
+
+    for (thread, executor) in runtime_threads {
+        if thread.is_finished() {
+            continue;
+        }
+
+        tracing::warn!(thread = thread.thread().name(), "Killing executor stuck in a call.");
+
+        if let Err(err) = executor.kill() {
+            tracing::error!(?err, "Failed to kill executor.");
+        }
+    }
+}
+
+/// Sets `cancel` on SIGINT, so that runs stop after their current call and the
+/// store's metadata still gets written. A second SIGINT kills the executors,
+/// which don't get the terminal's SIGINT, and exits right away.
+fn install_interrupt_handler(cancel: Arc<AtomicBool>) -> Result<(), eyre::Error> {
+    ctrlc::set_handler(move || {
+        if cancel.swap(true, atomic::Ordering::SeqCst) {
+            kill_live_executors();
+            process::exit(130);
+        }
+
+        tracing::warn!("Interrupted. Stopping after the current calls.");
+    })
+    .wrap_err("failed to install interrupt handler")
+}
+
+/// Feeds each file in `corpus` to `run` in name order, stopping after the
+/// first one it reports a divergence for. Returns the files that ran.
+fn run_corpus(
+    corpus: &Path,
+    mut run: impl FnMut(&Path) -> Result<bool, eyre::Error>,
+) -> Result<Vec<PathBuf>, eyre::Error> {
+    let mut inputs = Vec::new();
+
+    for entry in fs::read_dir(corpus).wrap_err("failed to read corpus dir")? {
+        let entry = entry.wrap_err("failed to read corpus dir entry")?;
+
+        if entry.file_type()?.is_file() {
+            inputs.push(entry.path());
+        }
+    }
+
+    inputs.sort();
+
+    let mut ran = Vec::with_capacity(inputs.len());
+
+    for input in inputs {
+        let diverged = run(&input).wrap_err_with(|| format!("failed to run corpus input {}", input.display()))?;
+
+        ran.push(input);
+
+        if diverged {
+            break;
+        }
+    }
+
+    Ok(ran)
+}
+
+/// Signatures of the functions `spec` defines, in name order.
+fn list_functions(spec: &str) -> Result<Vec<String>, eyre::Error> {
+    let spec = Spec::preview1(spec).wrap_err("failed to parse spec")?;
+
+    Ok(spec
+        .interface("wasi_snapshot_preview1")
+        .ok_or_else(|| err!("spec has no wasi_snapshot_preview1 interface"))?
+        .functions()
+        .map(|function| function.to_string())
+        .collect())
+}
+
+/// Parses `spec` and checks that everything it refers to resolves.
+fn check_spec(spec: &str) -> Result<SpecSummary, eyre::Error> {
+    let spec = Spec::preview1(spec).wrap_err("failed to parse spec")?;
+
+    check_executor_functions(&spec)?;
+    spec.check()
+}
+
+/// Reports the first pair of runtimes whose errnos for the last call aren't
+/// equivalent, skipping pairs whose diff `suppress` accepts.
+fn diff_errnos(
+    outputs: &[CallOutput],
+    policy: &ErrnoPolicy,
+    mut suppress: impl FnMut(&DiffResult) -> Result<bool, eyre::Error>,
+) -> Result<Option<DiffResult>, eyre::Error> {
+    for ((runtime_a, errno_a, ..), (runtime_b, errno_b, ..)) in outputs.iter().tuple_combinations() {
+        let equivalent = match (errno_a, errno_b) {
+            | (None, None) => true,
+            | (None, Some(_)) | (Some(_), None) => false,
+            | (&Some(a), &Some(b)) => policy.equivalent(a, b),
+        };
+
+        if equivalent {
+            continue;
+        }
+
+        let diff = DiffResult::Errno {
+            runtime_a: runtime_a.clone(),
+            runtime_b: runtime_b.clone(),
+        };
+
+        if !suppress(&diff)? {
+            return Ok(Some(diff));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The function the runtimes of `run` were last asked to call.
+fn last_called_function<'s>(spec: &'s Spec, run: &RunStore<Call>) -> Option<&'s Function> {
+    let (_name, runtime) = run.runtime_stores().next()?;
+    let name = runtime.read().unwrap().last_call()?.function.clone();
+
+    spec.get_function(&name)
+}
+
+/// Compares what successful calls of `function` left in guest memory behind
+/// their params, e.g. the buffers filled by `fd_read` or `fd_pread`. Handles
+/// are skipped wherever they are nested, since each runtime numbers its own.
+fn diff_memory(spec: &Spec, function: &Function, outputs: &[CallOutput]) -> Option<DiffResult> {
+    let written = outputs
+        .iter()
+        .filter_map(
+            |(runtime, errno, _abort, written_params)| match (errno, written_params) {
+                | (None | Some(0), Some(params)) => Some((runtime, params)),
+                | _ => None,
+            },
+        )
+        .collect_vec();
+    let ((runtime_a, params_a), rest) = written.split_first()?;
+
+    for (runtime_b, params_b) in rest {
+        for (param_idx, (param, (a, b))) in function
+            .params
+            .iter()
+            .zip(params_a.iter().zip(params_b.iter()))
+            .enumerate()
+        {
+            if diff_value(spec, &param.tref, a, b, param.name.clone()).is_some() {
+                return Some(DiffResult::Memory {
+                    runtime_a: runtime_a.to_string(),
+                    runtime_b: runtime_b.to_string(),
+                    param_idx,
+                });
+            }
+        }
+    }
+
+    None
+}
+
+/// Types whose values are picked by each runtime or host and so never line up.
+const RUNTIME_SPECIFIC_TYPES: &[&str] = &["device", "inode", "timestamp", "dircookie"];
+
+/// Compares the result values of two successful calls, returning the path to
+/// the first field that differs, e.g. `ok.size`. Results marked unspecified
+/// in the spec are skipped.
+fn diff_results(spec: &Spec, a: &Call, b: &Call) -> Option<String> {
+    if !matches!(a.errno, None | Some(0)) || !matches!(b.errno, None | Some(0)) {
+        return None;
+    }
+
+    let function = spec.get_function(&a.function)?;
+
+    function
+        .results
+        .iter()
+        .zip(a.results.as_ref()?.iter().zip(b.results.as_ref()?))
+        .filter(|(result, _values)| !result.unspecified)
+        .find_map(|(result, (a, b))| diff_value(spec, &result.tref, &a.value, &b.value, result.name.clone()))
+}
+
+fn diff_value(spec: &Spec, tref: &TypeRef, a: &WasiValue, b: &WasiValue, path: String) -> Option<String> {
+    if matches!(tref, TypeRef::Named(name) if RUNTIME_SPECIFIC_TYPES.contains(&name.as_str())) {
+        return None;
+    }
+
+    let wasi = tref.resolve_wasi(spec);
+
+    if a.equivalent_as(b, &wasi, spec) {
+        return None;
+    }
+
+    // The values may only differ in nested runtime-specific types, which don't
+    // count, so look for the field that differs.
+    match (wasi, a, b) {
+        | (WasiType::Record(record), WasiValue::Record(a), WasiValue::Record(b)) => record
+            .members
+            .iter()
+            .zip(a.members.iter().zip(&b.members))
+            .find_map(|(member, (a, b))| diff_value(spec, &member.tref, a, b, format!("{path}.{}", member.name))),
+        | (WasiType::Variant(variant), WasiValue::Variant(a), WasiValue::Variant(b)) => {
+            if a.case_idx != b.case_idx {
+                return Some(path);
+            }
+
+            let case = &variant.cases[a.case_idx];
+
+            match (
+                case.payload.as_ref().and_then(|payload| payload.tref()),
+                &a.payload,
+                &b.payload,
+            ) {
+                | (Some(tref), Some(a), Some(b)) => diff_value(spec, tref, a, b, format!("{path}.{}", case.name)),
+                | _ => None,
+            }
+        },
+        | (WasiType::List(list), WasiValue::List(a), WasiValue::List(b)) => {
+            diff_items(spec, &list.item, &a.items, &b.items, path)
+        },
+        | (WasiType::Pointer(pointer), WasiValue::Pointer(a), WasiValue::Pointer(b)) => {
+            diff_items(spec, &pointer.item, &a.items, &b.items, path)
+        },
+        | _ => Some(path),
+    }
+}
+
+fn diff_items(spec: &Spec, item: &TypeRef, a: &[WasiValue], b: &[WasiValue], path: String) -> Option<String> {
+    if a.len() != b.len() {
+        return Some(path);
+    }
+
+    a.iter()
+        .zip(b)
+        .enumerate()
+        .find_map(|(i, (a, b))| diff_value(spec, item, a, b, format!("{path}[{i}]")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::{fs::PermissionsExt as _, process::ExitStatusExt as _};
+
+    use wazzi::spec::{ListValue, RecordValue, VariantValue};
+    use wazzi_runners::WasiRunner;
+
+    use super::*;
+
+    #[test]
+    fn exited_runtime_is_a_trap_diff() {
+        let status = process::ExitStatus::from_raw(1 << 8);
+        let outcomes = vec![
+            ("wasmtime".to_string(), Some(0), None, None),
+            ("wasmer".to_string(), None, Some(CallAbort::Exited(status)), None),
+            ("wamr".to_string(), None, Some(CallAbort::Timeout), None),
+        ];
+
+        assert_eq!(status.code(), Some(1));
+        assert_eq!(
+            diff_aborts(&outcomes),
+            Some(DiffResult::Trap {
+                runtime: "wasmer".to_string(),
+                status,
+            })
+        );
+    }
+
+    #[test]
+    fn undecodable_response_is_a_malformed_diff() {
+        let too_large = CallAbort::from_error(&eyre::Error::new(CallError::MessageTooLarge { size: 2048, max: 1024 }));
+        let undecodable = err!("`Handle(3)` is not a value of u32").wrap_err("failed to decode results");
+        let outcomes = vec![
+            ("wasmtime".to_string(), Some(0), None, None),
+            ("wasmer".to_string(), None, Some(too_large), None),
+            ("wamr".to_string(), None, Some(CallAbort::Timeout), None),
+        ];
+
+        assert_eq!(CallAbort::from_error(&undecodable), CallAbort::Malformed);
+        assert_eq!(
+            diff_aborts(&outcomes),
+            Some(DiffResult::Malformed {
+                runtime: "wasmer".to_string(),
+            })
+        );
+        assert_eq!(parse_size("64M").unwrap(), 64 << 20);
+    }
+
+    #[test]
+    fn errno_diff_writes_divergence() {
+        let spec = Spec::preview1(include_str!("../preview1.witx")).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path()).unwrap();
+        let (_run_id, mut run) = store.new_run::<Call>().unwrap();
+        let params = vec![MaybeResourceValue {
+            value:        WasiValue::Handle(3),
+            resource_idx: None,
+        }];
+
+        for (runtime, errno) in [("wasmtime", 0), ("wasmer", 8)] {
+            run.new_runtime(runtime.to_string(), false)
+                .unwrap()
+                .write()
+                .unwrap()
+                .record_call(Call {
+                    function: "fd_close".to_string(),
+                    errno:    Some(errno),
+                    params:   params.clone(),
+                    results:  Some(Vec::new()),
+                    duration: None,
+                })
+                .unwrap();
+        }
+
+        let outputs = vec![
+            ("wasmtime".to_string(), Some(0), None, None),
+            ("wasmer".to_string(), Some(8), None, None),
+        ];
+        let diff = diff_errnos(&outputs, &ErrnoPolicy::ZeroNonZero, |_| Ok(false))
+            .unwrap()
+            .unwrap();
+
+        write_divergence(&spec, &Environment::new(), &run, &diff, &outputs).unwrap();
+
+        let divergence: Divergence =
+            serde_json::from_slice(&fs::read(run.root_path().join("divergence.json")).unwrap()).unwrap();
+
+        assert_eq!(
+            divergence,
+            Divergence {
+                kind: DivergenceKind::Errno,
+                runtimes: vec!["wasmtime".to_string(), "wasmer".to_string()],
+                function: Some("fd_close".to_string()),
+                params,
+                origins: BTreeMap::new(),
+                errnos: BTreeMap::from([("wasmer".to_string(), Some(8)), ("wasmtime".to_string(), Some(0))]),
+                errno_names: BTreeMap::from([
+                    ("wasmer".to_string(), "8 (EBADF)".to_string()),
+                    ("wasmtime".to_string(), "0 (ESUCCESS)".to_string()),
+                ]),
+                paths: vec![],
+                field: None,
+            }
+        );
+    }
+
+    /// Compares two runtimes' `base` directories.
+    fn tree_diff(a_root: &Path, b_root: &Path, compare_mode: bool) -> Result<Option<FsDiff>, eyre::Error> {
+        Ok(digest_diff(
+            &tree_digest(a_root, compare_mode)?,
+            &tree_digest(b_root, compare_mode)?,
+        ))
+    }
+
+    #[test]
+    fn trees_differing_only_in_mode() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+
+        for dir in [&a, &b] {
+            fs::create_dir(dir.path().join("d")).unwrap();
+            fs::write(dir.path().join("d").join("f"), b"x").unwrap();
+        }
+
+        fs::set_permissions(b.path().join("d").join("f"), fs::Permissions::from_mode(0o600)).unwrap();
+        fs::set_permissions(a.path().join("d").join("f"), fs::Permissions::from_mode(0o644)).unwrap();
+
+        assert!(tree_diff(a.path(), b.path(), true).unwrap().is_some());
+        assert!(tree_diff(a.path(), b.path(), false).unwrap().is_none());
+    }
+
+    #[test]
+    fn trees_differing_in_symlink_target() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+
+        std::os::unix::fs::symlink("target-a", a.path().join("link")).unwrap();
+        std::os::unix::fs::symlink("target-b", b.path().join("link")).unwrap();
+
+        assert!(tree_diff(a.path(), b.path(), false).unwrap().is_some());
+
+        fs::remove_file(b.path().join("link")).unwrap();
+        std::os::unix::fs::symlink("target-a", b.path().join("link")).unwrap();
+
+        assert!(tree_diff(a.path(), b.path(), false).unwrap().is_none());
+    }
+
+    #[test]
+    fn no_fs_diff_skips_the_walk() {
+        let dir = tempfile::tempdir().unwrap();
+        let roots = [dir.path().to_path_buf(), dir.path().join("missing")];
+        let options = |fs_diff| DiffOptions {
+            unordered_stdout: false,
+            fs_diff,
+            compare_mode: false,
+            errno_policy: ErrnoPolicy::Exact,
+            ignore: Vec::new(),
+        };
+
+        // Walking the missing root would fail.
+        assert!(options(true).tree_digests(&roots).is_err());
+        assert_eq!(options(false).tree_digests(&roots).unwrap(), None);
+        assert_eq!(options(true).tree_digests(&roots[..1]).unwrap().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn fs_diff_reports_mutated_path() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+
+        for root in [a.path(), b.path()] {
+            fs::create_dir_all(root.join("x").join("y")).unwrap();
+            fs::write(root.join("x").join("first"), b"same").unwrap();
+            fs::write(root.join("x").join("y").join("file"), b"same").unwrap();
+            fs::write(root.join("z"), b"same").unwrap();
+        }
+
+        fs::write(b.path().join("x").join("y").join("file"), b"mutated").unwrap();
+
+        assert_eq!(
+            tree_diff(a.path(), b.path(), false).unwrap(),
+            Some(FsDiff {
+                differs: BTreeMap::from([(PathBuf::from("x/y/file"), FsDiffKind::Content)]),
+                ..Default::default()
+            })
+        );
+
+        fs::remove_file(b.path().join("z")).unwrap();
+        fs::write(b.path().join("x").join("y").join("file"), b"same").unwrap();
+
+        assert_eq!(
+            tree_diff(a.path(), b.path(), false).unwrap(),
+            Some(FsDiff {
+                only_in_a: BTreeSet::from([PathBuf::from("z")]),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn extra_entry_is_reported_alone() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+
+        for root in [a.path(), b.path()] {
+            for name in ["a", "b", "c", "d"] {
+                fs::write(root.join(name), name).unwrap();
+            }
+        }
+
+        fs::write(a.path().join(".hidden"), b"").unwrap();
+
+        assert_eq!(
+            tree_diff(a.path(), b.path(), false).unwrap(),
+            Some(FsDiff {
+                only_in_a: BTreeSet::from([PathBuf::from(".hidden")]),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn large_files_differing_in_one_byte() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+        let mut content = vec![7u8; 8 * 1024 * 1024 + 3];
+
+        fs::write(a.path().join("big"), &content).unwrap();
+        fs::write(b.path().join("big"), &content).unwrap();
+        assert_eq!(tree_diff(a.path(), b.path(), false).unwrap(), None);
+
+        *content.last_mut().unwrap() = 8;
+        fs::write(b.path().join("big"), &content).unwrap();
+        assert_eq!(
+            tree_diff(a.path(), b.path(), false).unwrap(),
+            Some(FsDiff {
+                differs: BTreeMap::from([(PathBuf::from("big"), FsDiffKind::Content)]),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn deep_mutations_are_reported_precisely() {
+        let deep = Path::new("d0/d1/d2/d3/d4/d5");
+        let only_in_a = |path: &Path| FsDiff {
+            only_in_a: BTreeSet::from([path.to_path_buf()]),
+            ..Default::default()
+        };
+        let only_in_b = |path: &Path| FsDiff {
+            only_in_b: BTreeSet::from([path.to_path_buf()]),
+            ..Default::default()
+        };
+        let differs = |path: &Path, kind| FsDiff {
+            differs: BTreeMap::from([(path.to_path_buf(), kind)]),
+            ..Default::default()
+        };
+        // A mutation of tree A, with the diff expected without and with
+        // comparing modes.
+        type Mutation<'a> = (Box<dyn Fn(&Path) + 'a>, Option<FsDiff>, Option<FsDiff>);
+
+        let mutations: Vec<Mutation> = vec![
+            (Box::new(|_| {}), None, None),
+            (
+                Box::new(|a| fs::write(a.join(deep).join("f7"), "changed").unwrap()),
+                Some(differs(&deep.join("f7"), FsDiffKind::Content)),
+                Some(differs(&deep.join("f7"), FsDiffKind::Content)),
+            ),
+            (
+                Box::new(|a| fs::remove_file(a.join(deep).join("f7")).unwrap()),
+                Some(only_in_b(&deep.join("f7"))),
+                Some(only_in_b(&deep.join("f7"))),
+            ),
+            (
+                Box::new(|a| fs::write(a.join(deep).join("f7a"), "new").unwrap()),
+                Some(only_in_a(&deep.join("f7a"))),
+                Some(only_in_a(&deep.join("f7a"))),
+            ),
+            (
+                Box::new(|a| {
+                    fs::remove_file(a.join(deep).join("link")).unwrap();
+                    std::os::unix::fs::symlink("f1", a.join(deep).join("link")).unwrap();
+                }),
+                Some(differs(&deep.join("link"), FsDiffKind::Content)),
+                Some(differs(&deep.join("link"), FsDiffKind::Content)),
+            ),
+            (
+                Box::new(|a| fs::set_permissions(a.join(deep).join("f9"), fs::Permissions::from_mode(0o600)).unwrap()),
+                None,
+                Some(differs(&deep.join("f9"), FsDiffKind::Mode)),
+            ),
+            (
+                Box::new(|a| fs::create_dir(a.join("d0").join("zz")).unwrap()),
+                Some(only_in_a(Path::new("d0/zz"))),
+                Some(only_in_a(Path::new("d0/zz"))),
+            ),
+        ];
+
+        for (mutate, expected, expected_with_mode) in mutations {
+            let a = tempfile::tempdir().unwrap();
+            let b = tempfile::tempdir().unwrap();
+
+            // A few hundred entries, six levels deep.
+            for root in [a.path(), b.path()] {
+                let mut dir = root.to_path_buf();
+
+                for depth in 0..6 {
+                    dir = dir.join(format!("d{depth}"));
+                    fs::create_dir_all(&dir).unwrap();
+
+                    for i in 0..50 {
+                        let file = dir.join(format!("f{i}"));
+
+                        fs::write(&file, format!("{depth}-{i}")).unwrap();
+                        fs::set_permissions(&file, fs::Permissions::from_mode(0o644)).unwrap();
+                    }
+
+                    std::os::unix::fs::symlink("f0", dir.join("link")).unwrap();
+                }
+            }
+
+            mutate(a.path());
+
+            assert_eq!(tree_diff(a.path(), b.path(), false).unwrap(), expected);
+            assert_eq!(tree_diff(a.path(), b.path(), true).unwrap(), expected_with_mode);
+        }
+    }
+
+    #[test]
+    fn errno_policies() {
+        // EBADF versus ENOTDIR.
+        let (badf, notdir) = (8, 54);
+
+        assert!(ErrnoPolicy::ZeroNonZero.equivalent(badf, notdir));
+        assert!(!ErrnoPolicy::ZeroNonZero.equivalent(0, notdir));
+        assert!(!ErrnoPolicy::Exact.equivalent(badf, notdir));
+        assert!(ErrnoPolicy::Exact.equivalent(badf, badf));
+
+        let custom: ErrnoPolicy = "custom:54=8".parse().unwrap();
+
+        assert_eq!(custom, ErrnoPolicy::Custom(HashMap::from([(54, 8)])));
+        assert!(custom.equivalent(badf, notdir));
+        assert!(!custom.equivalent(badf, 2));
+        assert!("bogus".parse::<ErrnoPolicy>().is_err());
+    }
+
+    #[test]
+    fn buf_sizes() {
+        let page_size = rustix::param::page_size();
+
+        assert_eq!(parse_buf_size("64KiB").unwrap(), 64 << 10);
+        assert_eq!(parse_buf_size("2M").unwrap(), 2 << 20);
+        assert_eq!(parse_buf_size(&page_size.to_string()).unwrap(), page_size);
+        assert!(parse_buf_size("0").is_err());
+        assert!(parse_buf_size(&(page_size + 1).to_string()).is_err());
+        assert!(parse_buf_size("1 parsec").is_err());
+    }
+
+    #[test]
+    fn custom_buf_size_is_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path()).unwrap();
+        let (run_id, mut run) = store.new_run::<Call>().unwrap();
+        let buf_size = 4 * rustix::param::page_size();
+        let mut mmap = map_buffer(buf_size).unwrap();
+
+        run_rng(Some(7), 0).fill_bytes(&mut mmap);
+        run.write_data(&mmap).unwrap();
+
+        let data = fs::read(dir.path().join("runs").join(run_id).join("data").join("0")).unwrap();
+
+        assert_eq!(data.len(), buf_size);
+    }
+
+    #[test]
+    fn same_seed_makes_same_calls() {
+        let calls = |seed: u64| {
+            let dir = tempfile::tempdir().unwrap();
+            let prelude = dir.path().join("prelude.json");
+            let store_path = dir.path().join("store");
+
+            fs::create_dir(&store_path).unwrap();
+            fs::write(&prelude, "[]").unwrap();
+
+            let store = Store::new(&store_path).unwrap().with_seed(seed).with_max_calls(8);
+            let mut fuzzer = failing_runtime_fuzzer(dir.path(), store).with_buf_size(256);
+
+            fuzzer.silent = false;
+
+            let run_id = fuzzer.hammer(&prelude).unwrap();
+            let calls: Vec<Call> =
+                read_trace(&store_path.join("runs").join(run_id).join("runtimes").join("wasmtime")).unwrap();
+
+            assert_eq!(calls.len(), 8);
+
+            calls
+        };
+
+        assert_eq!(calls(7), calls(7));
+        assert_ne!(calls(7), calls(8));
+    }
+
+    fn record_run(store: &Store, functions: &[&str]) -> PathBuf {
+        let (run_id, mut run) = store.new_run::<Call>().unwrap();
+        let runtime = run.new_runtime("wasmtime".to_string(), true).unwrap();
+
+        for function in functions {
+            runtime
+                .write()
+                .unwrap()
+                .record_call(Call {
+                    function: function.to_string(),
+                    errno:    Some(0),
+                    params:   Vec::new(),
+                    results:  None,
+                    duration: Some(Duration::from_micros(250)),
+                })
+                .unwrap();
+        }
+
+        store.root_path().join("runs").join(run_id)
+    }
+
+    #[test]
+    fn list_runtimes_names_every_runtime() {
+        let cmd = Cmd::try_parse_from(["wazzi", "--list-runtimes"]).unwrap();
+
+        assert!(cmd.list_runtimes);
+        assert!(Cmd::try_parse_from(["wazzi", "--list-runtimes", "config.yaml"]).is_err());
+
+        for name in ["node", "wamr", "wasmedge", "wasmer", "wasmtime", "wazero"] {
+            assert!(RUNTIME_NAMES.contains(&name), "{name}");
+        }
+
+        for name in RUNTIME_NAMES {
+            assert!(runtime_by_name(name, None, Vec::new()).is_some(), "{name}");
+        }
+
+        let functions = list_functions(include_str!("../preview1.witx")).unwrap();
+
+        assert!(functions.contains(&"fd_close(fd: fd) -> expected<_, errno>".to_string()));
+    }
+
+    #[test]
+    fn recorded_calls_keep_their_duration() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path()).unwrap();
+        let run = record_run(&store, &["fd_write"]);
+        let calls: Vec<Call> = read_trace(&run.join("runtimes").join("wasmtime")).unwrap();
+
+        assert_eq!(calls[0].duration, Some(Duration::from_micros(250)));
+    }
+
+    #[test]
+    fn replayed_run_matches_recording() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path()).unwrap();
+        let recorded = record_run(&store, &["fd_write", "fd_close"]);
+        let same = record_run(&store, &["fd_write", "fd_close"]);
+        let diverged = record_run(&store, &["fd_write", "fd_sync"]);
+        let shorter = record_run(&store, &["fd_write"]);
+        let runtimes = recorded_runtimes(&recorded).unwrap();
+
+        assert_eq!(runtimes, BTreeSet::from(["wasmtime".to_string()]));
+        assert!(compare_runs(&recorded, &same, &runtimes).is_ok());
+        assert!(compare_runs(&recorded, &diverged, &runtimes).is_err());
+        assert!(compare_runs(&recorded, &shorter, &runtimes).is_err());
+    }
+
+    #[test]
+    fn runtime_config_overrides_path_and_args() {
+        let config: FuzzConfig = serde_yml::from_str(
+            "
+spec: preview1.witx
+runtimes:
+  - name: wasmtime
+    path: /opt/wasmtime/bin/wasmtime
+    extra_args: [-W, unknown-imports-trap=y]
+  - name: wamr
+",
+        )
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let wasm_path = dir.path().join("executor.wasm");
+
+        fs::write(&wasm_path, b"").unwrap();
+
+        let command = |runtime: &RuntimeFuzzConfig| {
+            runtime
+                .runtime()
+                .unwrap()
+                .command(&wasm_path, dir.path(), Vec::new(), Vec::new(), Vec::new())
+                .unwrap()
+        };
+        let wasmtime = command(&config.runtimes[0]);
+        let wamr = command(&config.runtimes[1]);
+
+        assert_eq!(wasmtime.get_program(), "/opt/wasmtime/bin/wasmtime");
+        assert_eq!(
+            wasmtime.get_args().take(3).collect_vec(),
+            ["run", "-W", "unknown-imports-trap=y"]
+        );
+        assert_eq!(wamr.get_program(), "iwasm");
+    }
+
+    #[test]
+    fn runtimes_are_selected_from_config() {
+        let config: FuzzConfig = serde_yml::from_str(
+            "
+spec: preview1.witx
+runtimes:
+  - name: wasmtime
+  - name: node
+  - name: wamr
+",
+        )
+        .unwrap();
+        let select = |names: &[&str]| {
+            let mut config = config.clone();
+
+            config
+                .select_runtimes(&names.iter().map(|name| name.to_string()).collect_vec())
+                .map(|()| config.runtimes.into_iter().map(|runtime| runtime.name).collect_vec())
+        };
+
+        assert_eq!(select(&["node", "wasmtime"]).unwrap(), ["wasmtime", "node"]);
+        assert!(select(&["node", "wasmer"]).is_err());
+        assert!(select(&["node"]).is_err());
+    }
+
+    #[test]
+    fn duplicate_runtimes_are_rejected() {
+        let config: FuzzConfig = serde_yml::from_str(
+            "
+spec: preview1.witx
+runtimes:
+  - name: wasmtime
+  - name: wasmer
+  - name: wasmtime
+",
+        )
+        .unwrap();
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn params_are_logged_at_debug() {
+        #[derive(Clone, Default)]
+        struct Buf(Arc<Mutex<Vec<u8>>>);
+
+        impl io::Write for Buf {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let spec = Spec::preview1(include_str!("../preview1.witx")).unwrap();
+        let function = spec.get_function("fd_seek").unwrap();
+        let fd: ResourceIdx = serde_json::from_str("0").unwrap();
+        let mut rtctx = RuntimeContext::new();
+
+        rtctx.resources.insert(fd, WasiValue::Handle(3));
+
+        let params = [
+            HighLevelValue::Resource(fd),
+            HighLevelValue::Concrete(WasiValue::S64(-1)),
+            HighLevelValue::Concrete(WasiValue::Variant(Box::new(VariantValue {
+                case_idx: 2,
+                payload:  None,
+            }))),
+        ];
+        let logged = |level| {
+            let buf = Buf::default();
+            let subscriber = tracing_subscriber::fmt()
+                .with_max_level(level)
+                .with_ansi(false)
+                .with_writer({
+                    let buf = buf.clone();
+
+                    move || buf.clone()
+                })
+                .finish();
+
+            tracing::subscriber::with_default(subscriber, || {
+                debug_params("wasmtime", &spec, &rtctx, function, &params)
+            });
+
+            let out = buf.0.lock().unwrap().clone();
+
+            String::from_utf8(out).unwrap()
+        };
+
+        assert!(logged(LevelFilter::DEBUG).contains("params=\"fd=3, offset=-1, whence=whence::end\""));
+        assert_eq!(logged(LevelFilter::INFO), "");
+    }
+
+    #[test]
+    fn ignore_rule_suppresses_errno_diff() {
+        let spec = Spec::preview1(include_str!("../preview1.witx")).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path()).unwrap();
+        let (_run_id, mut run) = store.new_run::<Call>().unwrap();
+
+        for (runtime, errno) in [("wasmtime", 0), ("wasmer", 8)] {
+            run.new_runtime(runtime.to_string(), false)
+                .unwrap()
+                .write()
+                .unwrap()
+                .record_call(Call {
+                    function: "fd_close".to_string(),
+                    errno:    Some(errno),
+                    params:   Vec::new(),
+                    results:  Some(Vec::new()),
+                    duration: None,
+                })
+                .unwrap();
+        }
+
+        let config: FuzzConfig = serde_yml::from_str(
+            "
+spec: preview1.witx
+runtimes: [{ name: wasmtime }, { name: wasmer }]
+ignore:
+  - function: fd_close
+    errnos: [8, 0]
+  - path: '**/*.tmp'
+",
+        )
+        .unwrap();
+        let options = |ignore| DiffOptions {
+            unordered_stdout: false,
+            fs_diff: true,
+            compare_mode: true,
+            errno_policy: ErrnoPolicy::Exact,
+            ignore,
+        };
+        let outputs = vec![
+            ("wasmtime".to_string(), Some(0), None, None),
+            ("wasmer".to_string(), Some(8), None, None),
+        ];
+        let diff = diff_errnos(&outputs, &ErrnoPolicy::Exact, |_| Ok(false))
+            .unwrap()
+            .unwrap();
+
+        assert!(!options(Vec::new())
+            .suppress(&spec, &Environment::new(), &run, &diff, &outputs)
+            .unwrap());
+        assert!(!run.root_path().join("suppressed.json").exists());
+        assert!(options(config.ignore.clone())
+            .suppress(&spec, &Environment::new(), &run, &diff, &outputs)
+            .unwrap());
+        assert!(!run.root_path().join("suppressed.json").exists());
+
+        run.write_suppressed().unwrap();
+
+        let suppressed: Vec<SuppressedDivergence> =
+            serde_json::from_slice(&fs::read(run.root_path().join("suppressed.json")).unwrap()).unwrap();
+
+        assert_eq!(suppressed.len(), 1);
+        assert_eq!(suppressed[0].rule, config.ignore[0]);
+        assert_eq!(suppressed[0].divergence.kind, DivergenceKind::Errno);
+    }
+
+    #[test]
+    fn errno_diff_between_later_runtimes_is_not_hidden_by_ignored_pairs() {
+        let spec = Spec::preview1(include_str!("../preview1.witx")).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path()).unwrap();
+        let (_run_id, mut run) = store.new_run::<Call>().unwrap();
+        let outputs = vec![
+            ("wasmtime".to_string(), Some(0), None, None),
+            ("wasmer".to_string(), Some(8), None, None),
+            ("wamr".to_string(), Some(2), None, None),
+        ];
+
+        for (runtime, errno, ..) in &outputs {
+            run.new_runtime(runtime.to_string(), false)
+                .unwrap()
+                .write()
+                .unwrap()
+                .record_call(Call {
+                    function: "fd_close".to_string(),
+                    errno:    *errno,
+                    params:   Vec::new(),
+                    results:  Some(Vec::new()),
+                    duration: None,
+                })
+                .unwrap();
+        }
+
+        let config: FuzzConfig = serde_yml::from_str(
+            "
+spec: preview1.witx
+runtimes: [{ name: wasmtime }, { name: wasmer }, { name: wamr }]
+ignore:
+  - function: fd_close
+    errnos: [0, 8]
+  - function: fd_close
+    errnos: [0, 2]
+",
+        )
+        .unwrap();
+        let options = DiffOptions {
+            unordered_stdout: false,
+            fs_diff:          false,
+            compare_mode:     false,
+            errno_policy:     ErrnoPolicy::Exact,
+            ignore:           config.ignore,
+        };
+        let diff = diff_errnos(&outputs, &options.errno_policy, |diff| {
+            options.suppress(&spec, &Environment::new(), &run, diff, &outputs)
+        })
+        .unwrap();
+
+        assert_eq!(
+            diff,
+            Some(DiffResult::Errno {
+                runtime_a: "wasmer".to_string(),
+                runtime_b: "wamr".to_string(),
+            })
+        );
+
+        run.write_suppressed().unwrap();
+
+        let suppressed: Vec<SuppressedDivergence> =
+            serde_json::from_slice(&fs::read(run.root_path().join("suppressed.json")).unwrap()).unwrap();
+
+        assert_eq!(suppressed.len(), 2);
+    }
+
+    #[test]
+    fn invalid_ignore_glob_is_rejected_on_load() {
+        let config = serde_yml::from_str::<FuzzConfig>(
+            "
+spec: preview1.witx
+runtimes: [{ name: wasmtime }, { name: wasmer }]
+ignore:
+  - path: 'a/[b'
+",
+        );
+
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn force_replaces_previous_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store");
+
+        create_store_dir(&path, false).unwrap();
+        fs::create_dir(path.join("runs")).unwrap();
+        fs::write(path.join("runs").join("stale"), b"").unwrap();
+
+        assert!(create_store_dir(&path, false)
+            .unwrap_err()
+            .to_string()
+            .contains("pass --force"));
+
+        create_store_dir(&path, true).unwrap();
+        assert_eq!(fs::read_dir(&path).unwrap().count(), 0);
+
+        let other = dir.path().join("other");
+
+        fs::create_dir(&other).unwrap();
+        fs::write(other.join("notes.txt"), b"").unwrap();
+        assert!(create_store_dir(&other, true).is_err());
+        assert!(other.join("notes.txt").exists());
+    }
+
+    #[test]
+    fn custom_executor_is_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let fake_runtime = dir.path().join("fake-wasmtime");
+        let executor_bin = dir.path().join("custom-executor.wasm");
+
+        fs::write(&fake_runtime, "#!/bin/sh\necho \"$@\" >&2\n").unwrap();
+        fs::set_permissions(&fake_runtime, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(&executor_bin, b"").unwrap();
+        assert!(find_executor(Some(dir.path().join("missing.wasm"))).is_err());
+
+        let executor_bin = find_executor(Some(executor_bin)).unwrap();
+        let runtime = RuntimeFuzzConfig {
+            name:       "wasmtime".to_string(),
+            path:       Some(fake_runtime),
+            extra_args: Vec::new(),
+        }
+        .runtime()
+        .unwrap();
+        let store = Store::new(dir.path()).unwrap();
+        let (_run_id, mut run) = store.new_run::<Call>().unwrap();
+        let runtime_store = run.new_runtime("wasmtime".to_string(), false).unwrap();
+        let runtime_store = runtime_store.read().unwrap();
+
+        let executor = start_executor(runtime.as_ref(), &executor_bin, &runtime_store, None).unwrap();
+        let stderr_path = runtime_store.root_path().join("stderr");
+        let start = Instant::now();
+
+        // The fake runtime exits right away, but its stderr is copied asynchronously.
+        while !fs::read_to_string(&stderr_path).unwrap().ends_with('\n') {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        drop(executor);
+
+        let stderr = fs::read_to_string(&stderr_path).unwrap();
+
+        assert!(stderr.contains(executor_bin.to_str().unwrap()), "{stderr}");
+    }
+
+    #[test]
+    fn guest_stdin_is_fed_to_the_executor() {
+        let dir = tempfile::tempdir().unwrap();
+        let fake_runtime = dir.path().join("fake-wasmtime");
+        let executor_bin = dir.path().join("executor.wasm");
+
+        fs::write(&fake_runtime, "#!/bin/sh\necho \"$@\" >&2\n").unwrap();
+        fs::set_permissions(&fake_runtime, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(&executor_bin, b"").unwrap();
+
+        let runtime = RuntimeFuzzConfig {
+            name:       "wasmtime".to_string(),
+            path:       Some(fake_runtime),
+            extra_args: Vec::new(),
+        }
+        .runtime()
+        .unwrap();
+        let store = Store::new(dir.path()).unwrap();
+        let (_run_id, mut run) = store.new_run::<Call>().unwrap();
+        let runtime_store = run.new_runtime("wasmtime".to_string(), false).unwrap();
+        let runtime_store = runtime_store.read().unwrap();
+
+        let executor = start_executor(runtime.as_ref(), &executor_bin, &runtime_store, Some(b"input")).unwrap();
+        let stderr_path = runtime_store.root_path().join("stderr");
+        let start = Instant::now();
+
+        while !fs::read_to_string(&stderr_path).unwrap().ends_with('\n') {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        drop(executor);
+
+        let stderr = fs::read_to_string(&stderr_path).unwrap();
+
+        assert_eq!(
+            fs::read(runtime_store.root_path().join("stdin").join("stdin")).unwrap(),
+            b"input"
+        );
+        assert!(stderr.contains("WAZZI_STDIN_FD=4"), "{stderr}");
+    }
+
+    #[test]
+    fn one_runtime_is_not_enough() {
+        let config: FuzzConfig = serde_yml::from_str(
+            "
+spec: preview1.witx
+runtimes:
+  - name: wasmtime
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "differential fuzzing needs at least two runtimes, but 1 is configured"
+        );
+    }
+
+    #[test]
+    fn no_abort_no_diff() {
+        let outcomes = vec![
+            ("wasmtime".to_string(), Some(0), None, None),
+            ("wasmer".to_string(), Some(0), None, None),
+        ];
+
+        assert_eq!(diff_aborts(&outcomes), None);
+    }
+
+    #[test]
+    fn differing_memory_is_a_diff() {
+        let spec = Spec::preview1(
+            r#"
+            (typename $fd (handle))
+            (typename $slot (record (field $fd $fd) (field $buf string)))
+            (module $wasi_snapshot_preview1
+              (@interface func (export "read_slots")
+                (param $fd $fd)
+                (param $slots (list $slot))
+                (param $len u32)
+              )
+            )
+            "#,
+        )
+        .unwrap();
+        let read_slots = spec.get_function("read_slots").unwrap();
+        let output = |runtime: &str, errno, fd, buf: &[u8]| {
+            let slot = WasiValue::Record(RecordValue {
+                members: vec![WasiValue::Handle(fd), WasiValue::String(buf.to_vec())],
+            });
+
+            (
+                runtime.to_string(),
+                Some(errno),
+                None,
+                Some(vec![
+                    WasiValue::Handle(fd),
+                    WasiValue::List(ListValue { items: vec![slot] }),
+                    WasiValue::U32(4),
+                ]),
+            )
+        };
+        let outputs = vec![
+            output("wasmtime", 0, 3, b"abcd"),
+            output("wasmer", 0, 4, b"abcd"),
+            output("wamr", 8, 3, b"\0\0\0\0"),
+        ];
+
+        // Handle numbers, even nested ones, and the failed call's buffer don't count.
+        assert_eq!(diff_memory(&spec, read_slots, &outputs), None);
+
+        let outputs = vec![output("wasmtime", 0, 3, b"abcd"), output("wasmer", 0, 3, b"abcx")];
+
+        assert_eq!(
+            diff_memory(&spec, read_slots, &outputs),
+            Some(DiffResult::Memory {
+                runtime_a: "wasmtime".to_string(),
+                runtime_b: "wasmer".to_string(),
+                param_idx: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn result_diffs_skip_runtime_specific_fields() {
+        let spec = Spec::preview1(include_str!("../preview1.witx")).unwrap();
+        let filestat = |ino: u64, size: u64, atim: u64| {
+            let filestat_type = spec.get_wasi_type("filestat").unwrap();
+            let record_type = filestat_type.record().unwrap();
+            let mut value = filestat_type.zero_value(&spec);
+            let record = value.record_mut().unwrap();
+
+            *record.get_mut(record_type, "ino").unwrap() = WasiValue::U64(ino);
+            *record.get_mut(record_type, "size").unwrap() = WasiValue::U64(size);
+            *record.get_mut(record_type, "atim").unwrap() = WasiValue::U64(atim);
+
+            Call {
+                function: "fd_filestat_get".to_string(),
+                errno:    Some(0),
+                params:   vec![MaybeResourceValue {
+                    value:        WasiValue::Handle(3),
+                    resource_idx: None,
+                }],
+                results:  Some(vec![MaybeResourceValue {
+                    value,
+                    resource_idx: None,
+                }]),
+                duration: None,
+            }
+        };
+
+        assert_eq!(diff_results(&spec, &filestat(1, 4, 10), &filestat(2, 4, 20)), None);
+        assert_eq!(
+            diff_results(&spec, &filestat(1, 4, 10), &filestat(1, 5, 10)),
+            Some("ok.size".to_string())
+        );
+    }
+
+    #[test]
+    fn check_valid_and_invalid_specs() {
+        let summary = check_spec(include_str!("../preview1.witx")).unwrap();
+
+        assert_eq!(summary.functions, 20);
+        assert_eq!(summary.resources, 1);
+
+        let err = check_spec(
+            r#"
+            (module $wasi_snapshot_preview1
+              (@interface func (export "fd_close")
+                (param $fd $fd)
+              )
+            )
+            "#,
+        )
+        .unwrap_err();
+
+        assert!(format!("{err:?}").contains("undefined type `fd` at 4:28"), "{err:?}");
+    }
+
+    #[test]
+    fn corpus_inputs_run_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+
+        fs::write(dir.path().join("b"), b"second").unwrap();
+        fs::write(dir.path().join("a"), b"first").unwrap();
+        fs::create_dir(dir.path().join("nested")).unwrap();
+
+        let mut data = Vec::new();
+        let ran = run_corpus(dir.path(), |path| {
+            data.push(fs::read(path)?);
+
+            Ok(false)
+        })
+        .unwrap();
+
+        assert_eq!(ran, vec![dir.path().join("a"), dir.path().join("b")]);
+        assert_eq!(data, vec![b"first".to_vec(), b"second".to_vec()]);
+        assert_eq!(
+            run_corpus(dir.path(), |_path| Ok(true)).unwrap(),
+            vec![dir.path().join("a")]
+        );
+    }
+
+    /// A fuzzer whose one runtime runs the shell `script` instead of the
+    /// executor.
+    fn fake_runtime_fuzzer(dir: &Path, script: &str, store: Store) -> Fuzzer {
+        fake_runtimes_fuzzer(dir, &[("wasmtime", script)], store)
+    }
+
+    /// A fuzzer whose runtimes each run a shell script instead of the
+    /// executor, given by runtime name.
+    fn fake_runtimes_fuzzer(dir: &Path, scripts: &[(&str, &str)], store: Store) -> Fuzzer {
+        let runtimes = scripts
+            .iter()
+            .map(|&(name, script)| {
+                let runtime_path = dir.join(name);
+
+                fs::write(&runtime_path, script).unwrap();
+                fs::set_permissions(&runtime_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+                let runtime_path: &'static Path = Box::leak(runtime_path.into_boxed_path());
+
+                (
+                    name.to_string(),
+                    runtime_by_name(name, Some(runtime_path), Vec::new()).unwrap(),
+                )
+            })
+            .collect_vec();
+
+        Fuzzer::new(
+            include_str!("../preview1.witx").to_string(),
+            Strategy::Stateless,
+            store,
+            runtimes,
+            true,
+            None,
+            DiffOptions {
+                unordered_stdout: false,
+                fs_diff:          false,
+                compare_mode:     false,
+                errno_policy:     ErrnoPolicy::Exact,
+                ignore:           Vec::new(),
+            },
+        )
+        .with_executor(dir.join(scripts[0].0))
+    }
+
+    /// A fuzzer whose one runtime reports no preopens, then never answers
+    /// another call. Its store is at `dir/store`.
+    fn silent_runtime_fuzzer(dir: &Path) -> Fuzzer {
+        let store_path = dir.join("store");
+
+        fs::create_dir(&store_path).unwrap();
+        fake_runtime_fuzzer(
+            dir,
+            r"#!/bin/sh
+            printf '\000wazzi\000\000\004\000\000\000\000\000\000\000\012\002\010\010'
+            exec sleep 1000",
+            Store::new(&store_path).unwrap(),
+        )
+    }
+
+    /// A fuzzer whose one runtime fails every call with errno 8.
+    fn failing_runtime_fuzzer(dir: &Path, store: Store) -> Fuzzer {
+        fake_runtime_fuzzer(dir, &failing_runtime_script(""), store)
+    }
+
+    /// A runtime that fails every call with errno 8, its guest writing
+    /// `stdout` before each answer.
+    fn failing_runtime_script(stdout: &str) -> String {
+        // Requests are read a byte at a time so that none is read ahead. Only
+        // the shell gets killed, so stderr is closed to not leave it open in
+        // the pipeline.
+        format!(
+            r#"#!/bin/sh
+            exec 2>/dev/null
+            while len=$(dd bs=1 count=8 2>/dev/null | od -An -tu8 | tr -d ' ') && [ -n "$len" ]; do
+                dd bs=1 count="$len" of=/dev/null 2>/dev/null
+                printf '{stdout}\000wazzi\000\000\004\000\000\000\000\000\000\000\012\002\010\010'
+            done"#
+        )
+    }
+
+    #[test]
+    fn interrupted_fuzzing_still_writes_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut fuzzer = silent_runtime_fuzzer(dir.path()).with_cancel(cancel.clone());
+
+        install_interrupt_handler(cancel.clone()).unwrap();
+        // The runtime never answers, so the run only ends once its executor is
+        // killed after the grace period.
+        thread::spawn(|| {
+            thread::sleep(Duration::from_millis(500));
+            assert_eq!(unsafe { libc::raise(libc::SIGINT) }, 0);
+        });
+        fuzzer.fuzz_loop(1, None).unwrap();
+
+        assert!(cancel.load(atomic::Ordering::SeqCst));
+        assert!(dir.path().join("store").join("metadata.json").is_file());
+    }
+
+    #[test]
+    fn single_run_is_counted() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("data");
+        let mut fuzzer = silent_runtime_fuzzer(dir.path());
+
+        fs::write(&data, [0; 64]).unwrap();
+        fuzzer.fuzz(data, Some(Duration::from_millis(100))).unwrap();
+
+        assert_eq!(fuzzer.store.metadata().nruns, 1);
+    }
+
+    #[test]
+    fn runs_stop_after_max_calls() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("data");
+        let store_path = dir.path().join("store");
+
+        fs::create_dir(&store_path).unwrap();
+        fs::write(&data, (0..=255u8).cycle().take(1 << 16).collect_vec()).unwrap();
+
+        let mut fuzzer = failing_runtime_fuzzer(dir.path(), Store::new(&store_path).unwrap().with_max_calls(3));
+
+        fuzzer.fuzz(data, Some(Duration::from_secs(60))).unwrap();
+
+        assert_eq!(fuzzer.store.metadata().ncalls, 3);
+    }
+
+    #[test]
+    fn runtimes_writing_different_stdout_diverge() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("data");
+        let store_path = dir.path().join("store");
+
+        fs::create_dir(&store_path).unwrap();
+        fs::write(&data, (0..=255u8).cycle().take(1 << 16).collect_vec()).unwrap();
+
+        let mut fuzzer = fake_runtimes_fuzzer(
+            dir.path(),
+            &[
+                ("wasmtime", &failing_runtime_script("hello\\n")),
+                ("wamr", &failing_runtime_script("world\\n")),
+            ],
+            Store::new(&store_path).unwrap().with_max_calls(8),
+        );
+
+        fuzzer.fuzz(data, Some(Duration::from_secs(60))).unwrap();
+
+        let divergence: Divergence =
+            serde_json::from_slice(&fs::read(store_path.join("runs").join("0").join("divergence.json")).unwrap())
+                .unwrap();
+
+        assert_eq!(divergence.kind, DivergenceKind::Stdout);
+        assert_eq!(divergence.runtimes, ["wasmtime", "wamr"]);
+        assert_eq!(fuzzer.store.metadata().ncalls, 1);
+    }
+
+    #[test]
+    fn hammer_calls_only_its_function_after_prelude() {
+        let dir = tempfile::tempdir().unwrap();
+        let prelude = dir.path().join("prelude.json");
+        let store_path = dir.path().join("store");
+        let clock_id = WasiValue::Variant(Box::new(VariantValue {
+            case_idx: 1,
+            payload:  None,
+        }));
+
+        fs::create_dir(&store_path).unwrap();
+        fs::write(
+            &prelude,
+            serde_json::to_vec(&[
+                Call::succeeded("args_sizes_get", vec![]),
+                Call::succeeded("clock_res_get", vec![clock_id]),
+            ])
+            .unwrap(),
+        )
+        .unwrap();
+
+        let mut fuzzer = failing_runtime_fuzzer(dir.path(), Store::new(&store_path).unwrap().with_max_calls(6))
+            .with_function_filter(FunctionFilter::new(["clock_time_get".to_string()], []));
+
+        fuzzer.silent = false;
+
+        let run_id = fuzzer.hammer(&prelude).unwrap();
+        let calls: Vec<Call> =
+            read_trace(&store_path.join("runs").join(run_id).join("runtimes").join("wasmtime")).unwrap();
+
+        assert_eq!(
+            calls.iter().map(|call| call.function.as_str()).collect_vec(),
+            ["args_sizes_get", "clock_res_get"]
+                .into_iter()
+                .chain(["clock_time_get"; 4])
+                .collect_vec()
+        );
+    }
+
+    #[test]
+    fn hanging_runtime_is_cancelled_after_time_limit() {
+        /// Reads calls but never answers them.
+        #[derive(Debug)]
+        struct Hang;
+
+        impl WasiRunner for Hang {
+            fn command(
+                &self,
+                _wasm_path: &Path,
+                _working_dir: &Path,
+                _preopens: Vec<MappedDir>,
+                _envs: Vec<(String, String)>,
+                _args: Vec<String>,
+            ) -> Result<process::Command, eyre::Error> {
+                let mut command = process::Command::new("sh");
+
+                command.arg("-c").arg("exec cat >/dev/null");
+
+                Ok(command)
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let executor = RunningExecutor::from_wasi_runner(
+            &Hang,
+            Path::new("executor.wasm"),
+            dir.path(),
+            Arc::new(Mutex::new(io::sink())),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+        )
+        .unwrap();
+        let executor = Arc::new(executor);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let over = AtomicBool::new(false);
+        let start = Instant::now();
+
+        spawn_timer(cancel.clone(), Duration::from_millis(100)).unwrap();
+
+        let result = thread::scope(|scope| {
+            let runtime_threads = [(
+                scope.spawn({
+                    let executor = executor.clone();
+
+                    move || executor.call(wazzi_executor_pb_rust::request::Call::default())
+                }),
+                executor.clone(),
+            )];
+
+            kill_on_cancel(&runtime_threads, &cancel, &over);
+
+            let [(thread, _)] = runtime_threads;
+
+            thread.join().unwrap()
+        });
+
+        assert!(
+            matches!(result, Err(CallError::PipeClosed | CallError::ProcessExited(_))),
+            "{result:?}"
+        );
+        assert!(over.load(atomic::Ordering::SeqCst));
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn only_diverged_runs_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path()).unwrap();
+        let (_run_id, quiet) = store.new_run::<Call>().unwrap();
+        let (_run_id, diverged) = store.new_run::<Call>().unwrap();
+
+        fs::write(diverged.root_path().join("divergence.json"), "{}").unwrap();
+        KeepPolicy::All.apply(quiet.root_path()).unwrap();
+        assert!(quiet.root_path().exists());
+
+        KeepPolicy::Diffs.apply(quiet.root_path()).unwrap();
+        KeepPolicy::Diffs.apply(diverged.root_path()).unwrap();
+        assert!(!quiet.root_path().exists());
+        assert!(diverged.root_path().exists());
+    }
+
+    #[test]
+    fn stalled_runtime_ends_the_run() {
+        let stage: Stage<()> = (Mutex::new((BTreeSet::new(), 0, ())), Condvar::new());
+        let runtimes = vec!["wasmtime".to_string(), "wasmer".to_string()];
+        let over = AtomicBool::new(false);
+
+        stage.0.lock().unwrap().0.insert("wasmtime".to_string());
+        stage.1.notify_all();
+
+        let result = wait_for_runtimes(
+            "select_function",
+            &stage,
+            &runtimes,
+            Some(Duration::from_millis(50)),
+            &over,
+        );
+
+        assert_eq!(result.err(), Some(StageError::Stalled(vec!["wasmer".to_string()])));
+        assert!(over.load(atomic::Ordering::SeqCst));
+
+        over.store(false, atomic::Ordering::SeqCst);
+        stage.0.lock().unwrap().0.insert("wasmer".to_string());
+
+        let state = wait_for_runtimes(
+            "select_function",
+            &stage,
+            &runtimes,
+            Some(Duration::from_millis(50)),
+            &over,
+        )
+        .unwrap();
+
+        assert!(state.0.is_empty());
+        assert_eq!(state.1, 1);
+    }
 }
@@ -1,7 +1,10 @@
-use std::{collections::HashSet, path::PathBuf};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
 
 use dyn_clone::{clone_trait_object, DynClone};
-use wazzi_runners::{MappedDir, Node, Wamr, WasiRunner, Wasmedge, Wasmer, Wasmtime, Wazero};
+use wazzi_runners::{MappedDir, Node, Wamr, WasiRunner, Wasmedge, Wasmer, Wasmtime, WasmtimeComponent, Wazero};
 
 use crate::{
     spec::{Spec, TypeRef, WasiValue},
@@ -15,6 +18,36 @@ pub trait Runtime: InitializeState + WasiRunner + DynClone {
 
 clone_trait_object!(Runtime);
 
+/// Names `runtime_by_name` accepts.
+pub const RUNTIME_NAMES: &[&str] = &[
+    "node",
+    "wamr",
+    "wasmedge",
+    "wasmer",
+    "wasmtime",
+    "wasmtime-component",
+    "wazero",
+];
+
+/// Looks up a runtime by the name used in fuzz configs, optionally overriding
+/// its binary and passing it extra flags.
+pub fn runtime_by_name(name: &str, path: Option<&'static Path>, extra_args: Vec<String>) -> Option<Box<dyn Runtime>> {
+    let path = |default: &'static str| path.unwrap_or(Path::new(default));
+
+    Some(match name {
+        | "node" => Box::new(Node::new(name.to_string(), path("node")).with_extra_args(extra_args)),
+        | "wamr" => Box::new(Wamr::new(name.to_string(), path("iwasm")).with_extra_args(extra_args)),
+        | "wasmedge" => Box::new(Wasmedge::new(name.to_string(), path("wasmedge")).with_extra_args(extra_args)),
+        | "wasmer" => Box::new(Wasmer::new(name.to_string(), path("wasmer")).with_extra_args(extra_args)),
+        | "wasmtime" => Box::new(Wasmtime::new(name.to_string(), path("wasmtime")).with_extra_args(extra_args)),
+        | "wasmtime-component" => {
+            Box::new(WasmtimeComponent::new(name.to_string(), path("wasmtime")).with_extra_args(extra_args))
+        },
+        | "wazero" => Box::new(Wazero::new(name.to_string(), path("wazero")).with_extra_args(extra_args)),
+        | _ => return None,
+    })
+}
+
 impl Runtime for Node<'_> {
     fn name(&self) -> &str {
         self.name()
@@ -39,6 +72,12 @@ impl Runtime for Wasmtime<'_> {
     }
 }
 
+impl Runtime for WasmtimeComponent<'_> {
+    fn name(&self) -> &str {
+        self.name()
+    }
+}
+
 impl Runtime for Wamr<'_> {
     fn name(&self) -> &str {
         self.name()
@@ -93,7 +132,7 @@ fn initialize(
             call.results.pop().unwrap(),
             spec,
             spec.types.get_by_key("prestat").unwrap(),
-        )
+        )?
         .variant()
         .unwrap()
         .payload
@@ -145,7 +184,7 @@ fn initialize(
         assert_eq!(call.errno_some(), 0);
 
         let full_dir_name = String::from_utf8(
-            WasiValue::from_pb(call.params[1].clone(), spec, spec.types.get_by_key("path").unwrap())
+            WasiValue::from_pb(call.params[1].clone(), spec, spec.types.get_by_key("path").unwrap())?
                 .string()
                 .unwrap()
                 .to_vec(),
@@ -160,7 +199,7 @@ fn initialize(
         fd += 1;
     }
 
-    Ok(EnvironmentInitializer { name, preopens })
+    Ok(EnvironmentInitializer::new(name, preopens))
 }
 
 impl InitializeState for Node<'_> {
@@ -312,11 +351,11 @@ impl InitializeState for Wasmer<'_> {
             preopens.push((
                 dir.name.clone(),
                 dir.host_path.clone(),
-                WasiValue::from_pb(call.results[0].clone(), spec, spec.types.get_by_key("fd").unwrap()),
+                WasiValue::from_pb(call.results[0].clone(), spec, spec.types.get_by_key("fd").unwrap())?,
             ));
         }
 
-        Ok(EnvironmentInitializer { name, preopens })
+        Ok(EnvironmentInitializer::new(name, preopens))
     }
 }
 
@@ -332,6 +371,18 @@ impl InitializeState for Wasmtime<'_> {
     }
 }
 
+impl InitializeState for WasmtimeComponent<'_> {
+    fn initialize_state(
+        &self,
+        name: String,
+        spec: &Spec,
+        executor: &RunningExecutor,
+        mapped_dirs: Vec<MappedDir>,
+    ) -> Result<EnvironmentInitializer, eyre::Error> {
+        initialize(name, spec, executor, mapped_dirs)
+    }
+}
+
 impl InitializeState for Wazero<'_> {
     fn initialize_state(
         &self,
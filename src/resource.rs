@@ -4,7 +4,18 @@ use crate::spec::WasiValue;
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 pub struct Resource {
-    pub state: WasiValue,
+    pub state:  WasiValue,
+    /// The call whose results created the resource. Preopens have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<ResourceOrigin>,
+}
+
+/// Where in a run a resource came from.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct ResourceOrigin {
+    /// Position of the call in the trace.
+    pub call_idx: usize,
+    pub function: String,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
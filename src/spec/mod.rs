@@ -1,8 +1,12 @@
 pub mod witx;
 
-use std::collections::{BTreeMap, HashSet};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
 
-use arbitrary::Unstructured;
+use arbitrary::{size_hint, Unstructured};
+use eyre::eyre as err;
 use idxspace::IndexSpace;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
@@ -12,6 +16,7 @@ use witx::slang;
 pub struct Spec {
     pub(crate) types:      IndexSpace<String, TypeDef>,
     pub(crate) interfaces: IndexSpace<String, Interface>,
+    pub(crate) constants:  BTreeMap<String, Constant>,
 }
 
 impl Spec {
@@ -54,6 +59,7 @@ impl Spec {
         Self {
             types,
             interfaces: Default::default(),
+            constants: Default::default(),
         }
     }
 
@@ -73,10 +79,94 @@ impl Spec {
             | None => tdef.wasi.clone(),
         })
     }
+
+    /// A constant declared with `(@witx const ...)`, by its name without `$`.
+    pub fn get_constant(&self, name: &str) -> Option<&Constant> {
+        self.constants.get(name)
+    }
+
+    pub fn get_function(&self, name: &str) -> Option<&Function> {
+        self.interface("wasi_snapshot_preview1")?.function(name)
+    }
+
+    /// Symbolic name of an errno, e.g. `ENOENT` for 44, taken from the cases of
+    /// the spec's `errno` type.
+    pub fn errno_name(&self, errno: i32) -> Option<String> {
+        let case = self
+            .get_wasi_type("errno")?
+            .variant()?
+            .case_name(usize::try_from(errno).ok()?)?
+            .to_uppercase();
+
+        Some(format!("E{case}"))
+    }
+
+    /// The interface declared by the module `name`, without its `$`.
+    pub fn interface(&self, name: &str) -> Option<&Interface> {
+        self.interfaces.get_by_key(name)
+    }
+
+    /// Checks that every param and result of every function refers to a
+    /// defined type, and counts what the spec defines.
+    pub fn check(&self) -> Result<SpecSummary, eyre::Error> {
+        let mut functions = 0;
+
+        for (_name, interface) in self.interfaces.iter() {
+            for function in interface.functions.values() {
+                let trefs = function
+                    .params
+                    .iter()
+                    .map(|param| (&param.name, &param.tref))
+                    .chain(function.results.iter().map(|result| (&result.name, &result.tref)));
+
+                for (name, tref) in trefs {
+                    if let TypeRef::Named(type_name) = tref {
+                        if !self.types.contains_key(type_name) {
+                            return Err(err!(
+                                "`{name}` of function `{}` refers to undefined type `{type_name}`",
+                                function.name
+                            ));
+                        }
+                    }
+                }
+
+                functions += 1;
+            }
+        }
+
+        Ok(SpecSummary {
+            types: self.types.len(),
+            functions,
+            resources: self
+                .types
+                .iter()
+                .filter(|(_name, tdef)| tdef.wasi == WasiType::Handle)
+                .count(),
+        })
+    }
+}
+
+/// What a spec defines, as reported by [`Spec::check`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct SpecSummary {
+    pub types:     usize,
+    pub functions: usize,
+    /// Handle types, whose values are tracked as resources.
+    pub resources: usize,
+}
+
+impl fmt::Display for SpecSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} types, {} functions, {} resources",
+            self.types, self.functions, self.resources
+        )
+    }
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
-pub(crate) struct Interface {
+pub struct Interface {
     pub(crate) functions: BTreeMap<String, Function>,
 }
 
@@ -86,6 +176,36 @@ impl Interface {
             functions: Default::default(),
         }
     }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    /// In name order.
+    pub fn functions(&self) -> impl Iterator<Item = &Function> {
+        self.functions.values()
+    }
+
+    /// Functions with a result that holds a value of the named type.
+    pub fn functions_producing(&self, spec: &Spec, type_name: &str) -> Vec<&Function> {
+        self.functions
+            .values()
+            .filter(|function| {
+                function
+                    .results
+                    .iter()
+                    .any(|result| result.tref.mentions(spec, type_name))
+            })
+            .collect()
+    }
+
+    /// Functions with a param that holds a value of the named type.
+    pub fn functions_consuming(&self, spec: &Spec, type_name: &str) -> Vec<&Function> {
+        self.functions
+            .values()
+            .filter(|function| function.params.iter().any(|param| param.tref.mentions(spec, type_name)))
+            .collect()
+    }
 }
 
 impl Default for Interface {
@@ -102,6 +222,41 @@ pub struct Function {
     pub r#return:               Option<()>,
     pub(crate) input_contract:  Option<slang::Term>,
     pub(crate) output_contract: Option<slang::Term>,
+    /// Params whose resources no longer exist after a successful call, like
+    /// the fd passed to `fd_close`.
+    pub consumes:               Vec<String>,
+}
+
+/// Renders the signature, e.g. `fd_tell(fd: fd) -> expected<(ok: filesize), errno>`.
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}({})",
+            self.name,
+            self.params
+                .iter()
+                .map(|param| format!("{}: {}", param.name, param.tref))
+                .join(", ")
+        )?;
+
+        let results = match self.results.as_slice() {
+            | [] => "_".to_string(),
+            | results => format!(
+                "({})",
+                results
+                    .iter()
+                    .map(|result| format!("{}: {}", result.name, result.tref))
+                    .join(", ")
+            ),
+        };
+
+        match (self.r#return, self.results.is_empty()) {
+            | (Some(()), _) => write!(f, " -> expected<{results}, errno>"),
+            | (None, true) => Ok(()),
+            | (None, false) => write!(f, " -> {results}"),
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -112,8 +267,11 @@ pub struct FunctionParam {
 
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct FunctionResult {
-    pub name: String,
-    pub tref: TypeRef,
+    pub name:        String,
+    pub tref:        TypeRef,
+    /// Marked `(@unspecified)`: the value is implementation-defined, so
+    /// runtimes are free to disagree on it.
+    pub unspecified: bool,
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -154,12 +312,59 @@ impl TypeRef {
         }
     }
 
-    fn resolve_wasi<'ctx, 'spec>(&self, spec: &'spec Spec) -> WasiType {
+    pub fn resolve_wasi<'ctx, 'spec>(&self, spec: &'spec Spec) -> WasiType {
         match self {
             | Self::Named(name) => spec.types.get_by_key(name).unwrap().wasi.clone(),
             | Self::Anonymous(wasi_type) => wasi_type.to_owned(),
         }
     }
+
+    /// Whether a value of this type is, or holds somewhere inside it, a value of
+    /// the named type.
+    fn mentions(&self, spec: &Spec, type_name: &str) -> bool {
+        let wasi_type = match self {
+            | Self::Named(name) if name == type_name => return true,
+            | Self::Named(name) => &spec.types.get_by_key(name).unwrap().wasi,
+            | Self::Anonymous(wasi_type) => wasi_type,
+        };
+
+        match wasi_type {
+            | WasiType::Variant(variant) => {
+                variant
+                    .cases
+                    .iter()
+                    .filter_map(|case| case.payload.as_ref())
+                    .any(|payload| match payload {
+                        | VariantPayload::TypeRef(tref) => tref.mentions(spec, type_name),
+                        | VariantPayload::Tuple(trefs) => trefs.iter().any(|tref| tref.mentions(spec, type_name)),
+                    })
+            },
+            | WasiType::Record(record) => record
+                .members
+                .iter()
+                .any(|member| member.tref.mentions(spec, type_name)),
+            | WasiType::Pointer(pointer) => pointer.item.mentions(spec, type_name),
+            | WasiType::List(list) => list.item.mentions(spec, type_name),
+            | _ => false,
+        }
+    }
+}
+
+impl fmt::Display for TypeRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            | Self::Named(name) => f.write_str(name),
+            | Self::Anonymous(wasi_type) => wasi_type.fmt(f),
+        }
+    }
+}
+
+/// A named constant, like `(@witx const $clockid $monotonic 1)`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Constant {
+    /// The type the constant is a value of, if declared.
+    pub ty:    Option<String>,
+    pub value: u64,
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -169,6 +374,32 @@ pub struct TypeDef {
     pub state: Option<WasiType>,
 }
 
+/// Bounds for generating arbitrary values, so that one call cannot use up
+/// the whole fuzz input.
+#[derive(Clone, Debug)]
+pub struct GenConfig {
+    pub max_list_len:   usize,
+    pub max_string_len: usize,
+
+    /// Generate strings from arbitrary chars so they are always valid UTF-8.
+    pub utf8_strings: bool,
+
+    /// Now and then set a flag bit past the declared fields, to probe how
+    /// runtimes validate reserved bits.
+    pub reserved_flag_bits: bool,
+}
+
+impl Default for GenConfig {
+    fn default() -> Self {
+        Self {
+            max_list_len:       16,
+            max_string_len:     16,
+            utf8_strings:       false,
+            reserved_flag_bits: false,
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub enum WasiType {
     S64,
@@ -185,6 +416,55 @@ pub enum WasiType {
     List(Box<ListType>),
 }
 
+impl fmt::Display for WasiType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let payload = |payload: &Option<VariantPayload>| match payload {
+            | None => "_".to_string(),
+            | Some(VariantPayload::TypeRef(tref)) => tref.to_string(),
+            | Some(VariantPayload::Tuple(trefs)) => format!("({})", trefs.iter().join(", ")),
+        };
+
+        match self {
+            | WasiType::S64 => f.write_str("s64"),
+            | WasiType::U8 => f.write_str("u8"),
+            | WasiType::U16 => f.write_str("u16"),
+            | WasiType::U32 => f.write_str("u32"),
+            | WasiType::U64 => f.write_str("u64"),
+            | WasiType::Handle => f.write_str("handle"),
+            | WasiType::String => f.write_str("string"),
+            | WasiType::Flags(flags) => write!(f, "flags<{}>", flags.fields.iter().join(", ")),
+            | WasiType::Variant(variant) => match variant.cases.as_slice() {
+                | [ok, err] if ok.name == "ok" && err.name == "error" => {
+                    write!(f, "expected<{}, {}>", payload(&ok.payload), payload(&err.payload))
+                },
+                | cases => write!(
+                    f,
+                    "variant<{}>",
+                    cases
+                        .iter()
+                        .map(|case| match &case.payload {
+                            | None => case.name.clone(),
+                            | Some(_) => format!("{}({})", case.name, payload(&case.payload)),
+                        })
+                        .join(", ")
+                ),
+            },
+            | WasiType::Record(record) => write!(
+                f,
+                "record<{}>",
+                record
+                    .members
+                    .iter()
+                    .map(|member| format!("{}: {}", member.name, member.tref))
+                    .join(", ")
+            ),
+            | WasiType::Pointer(pointer) if pointer.r#const => write!(f, "const_pointer<{}>", pointer.item),
+            | WasiType::Pointer(pointer) => write!(f, "pointer<{}>", pointer.item),
+            | WasiType::List(list) => write!(f, "list<{}>", list.item),
+        }
+    }
+}
+
 impl WasiType {
     pub fn zero_value(&self, spec: &Spec) -> WasiValue {
         match self {
@@ -205,13 +485,13 @@ impl WasiType {
                     .unwrap()
                     .payload
                     .as_ref()
-                    .map(|payload| payload.tref().unwrap().resolve(spec).wasi.zero_value(spec)),
+                    .map(|payload| payload.tref().unwrap().resolve_wasi(spec).zero_value(spec)),
             })),
             | WasiType::Record(record) => WasiValue::Record(RecordValue {
                 members: record
                     .members
                     .iter()
-                    .map(|member| member.tref.resolve(spec).wasi.zero_value(spec))
+                    .map(|member| member.tref.resolve_wasi(spec).zero_value(spec))
                     .collect::<Vec<_>>(),
             }),
             | WasiType::Pointer(_pointer) => WasiValue::Pointer(PointerValue { items: vec![] }),
@@ -220,21 +500,36 @@ impl WasiType {
         }
     }
 
-    pub(crate) fn arbitrary_value(&self, spec: &Spec, u: &mut Unstructured) -> Result<WasiValue, arbitrary::Error> {
+    pub(crate) fn arbitrary_value(
+        &self,
+        spec: &Spec,
+        config: &GenConfig,
+        u: &mut Unstructured,
+    ) -> Result<WasiValue, arbitrary::Error> {
         Ok(match self {
-            | WasiType::S64 => WasiValue::S64(u.arbitrary()?),
-            | WasiType::U8 => WasiValue::U8(u.arbitrary()?),
-            | WasiType::U16 => WasiValue::U16(u.arbitrary()?),
-            | WasiType::U32 => WasiValue::U32(u.arbitrary()?),
-            | WasiType::U64 => WasiValue::U64(u.arbitrary()?),
+            | WasiType::S64 => WasiValue::S64(arbitrary_int(u, &[0, i64::MIN, i64::MAX, -1, 1])?),
+            | WasiType::U8 => WasiValue::U8(arbitrary_int(u, &[0, u8::MAX, 1])?),
+            | WasiType::U16 => WasiValue::U16(arbitrary_int(u, &[0, u16::MAX, 1])?),
+            | WasiType::U32 => WasiValue::U32(arbitrary_int(u, &[0, u32::MAX, 1])?),
+            | WasiType::U64 => WasiValue::U64(arbitrary_int(u, &[0, u64::MAX, 1])?),
             | WasiType::Handle => WasiValue::Handle(u.arbitrary()?),
-            | WasiType::Flags(flags) => WasiValue::Flags(FlagsValue {
-                fields: flags
+            | WasiType::Flags(flags) => {
+                let mut fields = flags
                     .fields
                     .iter()
                     .map(|_field| u.arbitrary())
-                    .collect::<Result<Vec<_>, _>>()?,
-            }),
+                    .collect::<Result<Vec<_>, _>>()?;
+                let bits = flags.repr.mem_size() as usize * 8;
+
+                if config.reserved_flag_bits && fields.len() < bits && u.ratio(1, 8)? {
+                    let bit = u.int_in_range(fields.len()..=bits - 1)?;
+
+                    fields.resize(bit + 1, false);
+                    fields[bit] = true;
+                }
+
+                WasiValue::Flags(FlagsValue { fields })
+            },
             | WasiType::Variant(variant) => {
                 let case_idx: usize = u.choose_index(variant.cases.len())?;
                 let case = variant.cases.get(case_idx).unwrap();
@@ -244,7 +539,13 @@ impl WasiType {
                     payload: case
                         .payload
                         .as_ref()
-                        .map(|payload| payload.tref().unwrap().resolve_wasi(spec).arbitrary_value(spec, u))
+                        .map(|payload| {
+                            payload
+                                .tref()
+                                .unwrap()
+                                .resolve_wasi(spec)
+                                .arbitrary_value(spec, config, u)
+                        })
                         .transpose()?,
                 }))
             },
@@ -266,37 +567,47 @@ impl WasiType {
                         members: record
                             .members
                             .iter()
-                            .map(|member| member.tref.resolve_wasi(spec).arbitrary_value(spec, u))
+                            .map(|member| member.tref.resolve_wasi(spec).arbitrary_value(spec, config, u))
                             .collect::<Result<Vec<_>, _>>()?,
                     })
                 }
             },
             | WasiType::String => {
-                let n = u.choose_index(16)?;
+                let n = u.int_in_range(0..=config.max_string_len)?;
                 let mut bytes = Vec::with_capacity(n);
 
                 for _ in 0..n {
-                    bytes.push(*u.choose(&[b'.', b'/', b'a'])?);
+                    if config.utf8_strings {
+                        let c: char = u.arbitrary()?;
+
+                        if bytes.len() + c.len_utf8() > config.max_string_len {
+                            break;
+                        }
+
+                        bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                    } else {
+                        bytes.push(*u.choose(&[b'.', b'/', b'a'])?);
+                    }
                 }
 
                 WasiValue::String(bytes)
             },
             | WasiType::Pointer(pointer) => {
-                let n = u.choose_index(16)?;
+                let n = u.int_in_range(0..=config.max_list_len)?;
                 let mut items = Vec::with_capacity(n);
 
                 for _ in 0..n {
-                    items.push(pointer.item.resolve_wasi(spec).arbitrary_value(spec, u)?);
+                    items.push(pointer.item.resolve_wasi(spec).arbitrary_value(spec, config, u)?);
                 }
 
                 WasiValue::Pointer(PointerValue { items })
             },
             | WasiType::List(list) => {
-                let n = u.choose_index(16)?;
+                let n = u.int_in_range(0..=config.max_list_len)?;
                 let mut items = Vec::with_capacity(n);
 
                 for _ in 0..n {
-                    items.push(list.item.resolve_wasi(spec).arbitrary_value(spec, u)?);
+                    items.push(list.item.resolve_wasi(spec).arbitrary_value(spec, config, u)?);
                 }
 
                 WasiValue::List(ListValue { items })
@@ -304,6 +615,85 @@ impl WasiType {
         })
     }
 
+    /// Bounds on the bytes `arbitrary_value` takes from its input, like
+    /// `Arbitrary::size_hint`. An upper bound of `None` means unbounded.
+    pub fn arbitrary_size_hint(&self, spec: &Spec, config: &GenConfig) -> (usize, Option<usize>) {
+        let exact = |n: usize| (n, Some(n));
+        let int = |size: usize, nboundaries: usize| {
+            size_hint::and(
+                exact(range_bytes(7)),
+                size_hint::or(exact(range_bytes(nboundaries - 1)), exact(size)),
+            )
+        };
+        // A length from `0..=max` followed by up to `max` items.
+        let items = |max: usize, item: (usize, Option<usize>)| {
+            (range_bytes(max), item.1.map(|upper| range_bytes(max) + max * upper))
+        };
+
+        match self {
+            | WasiType::S64 => int(8, 5),
+            | WasiType::U8 => int(1, 3),
+            | WasiType::U16 => int(2, 3),
+            | WasiType::U32 => int(4, 3),
+            | WasiType::U64 => int(8, 3),
+            | WasiType::Handle => exact(4),
+            | WasiType::Flags(flags) => {
+                let nfields = flags.fields.len();
+                let bits = flags.repr.mem_size() as usize * 8;
+
+                if config.reserved_flag_bits && nfields < bits {
+                    (nfields + 1, Some(nfields + 1 + range_bytes(bits - 1 - nfields)))
+                } else {
+                    exact(nfields)
+                }
+            },
+            | WasiType::Variant(variant) => size_hint::and(
+                exact(range_bytes(variant.cases.len().saturating_sub(1))),
+                size_hint::or_all(
+                    &variant
+                        .cases
+                        .iter()
+                        .map(|case| match &case.payload {
+                            | None => exact(0),
+                            | Some(VariantPayload::TypeRef(tref)) => {
+                                tref.resolve_wasi(spec).arbitrary_size_hint(spec, config)
+                            },
+                            | Some(VariantPayload::Tuple(trefs)) => size_hint::and_all(
+                                &trefs
+                                    .iter()
+                                    .map(|tref| tref.resolve_wasi(spec).arbitrary_size_hint(spec, config))
+                                    .collect_vec(),
+                            ),
+                        })
+                        .collect_vec(),
+                ),
+            ),
+            | WasiType::Record(record) => {
+                if record.members.len() == 2 && record.members[0].name == "buf" && record.members[1].name == "buf_len" {
+                    items(63, exact(1))
+                } else {
+                    size_hint::and_all(
+                        &record
+                            .members
+                            .iter()
+                            .map(|member| member.tref.resolve_wasi(spec).arbitrary_size_hint(spec, config))
+                            .collect_vec(),
+                    )
+                }
+            },
+            | WasiType::String if config.utf8_strings => items(config.max_string_len, exact(4)),
+            | WasiType::String => items(config.max_string_len, exact(range_bytes(2))),
+            | WasiType::Pointer(pointer) => items(
+                config.max_list_len,
+                pointer.item.resolve_wasi(spec).arbitrary_size_hint(spec, config),
+            ),
+            | WasiType::List(list) => items(
+                config.max_list_len,
+                list.item.resolve_wasi(spec).arbitrary_size_hint(spec, config),
+            ),
+        }
+    }
+
     pub fn flags(&self) -> Option<&FlagsType> {
         match self {
             | Self::Flags(flags) => Some(flags),
@@ -429,6 +819,11 @@ pub struct VariantType {
 }
 
 impl VariantType {
+    /// Name of the case at `case_idx`.
+    pub fn case_name(&self, case_idx: usize) -> Option<&str> {
+        self.cases.get(case_idx).map(|case| case.name.as_str())
+    }
+
     pub fn value_from_name(&self, case_name: &str, payload: Option<WasiValue>) -> Option<WasiValue> {
         Some(WasiValue::Variant(Box::new(VariantValue {
             case_idx: self
@@ -485,7 +880,7 @@ pub enum VariantPayload {
 }
 
 impl VariantPayload {
-    pub(crate) fn tref(&self) -> Option<&TypeRef> {
+    pub fn tref(&self) -> Option<&TypeRef> {
         match self {
             | VariantPayload::TypeRef(tref) => Some(tref),
             | VariantPayload::Tuple(_) => None,
@@ -505,6 +900,11 @@ pub struct RecordType {
 }
 
 impl RecordType {
+    /// Position of the member named `name`.
+    pub fn member_idx(&self, name: &str) -> Option<usize> {
+        self.members.iter().position(|member| member.name == name)
+    }
+
     fn alignment(&self, spec: &Spec) -> u32 {
         self.members
             .iter()
@@ -545,8 +945,11 @@ impl RecordType {
 
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct RecordMemberType {
-    pub name: String,
-    pub tref: TypeRef,
+    pub name:    String,
+    pub tref:    TypeRef,
+    /// Variant case this member takes in the state of a preopened handle.
+    /// Members without one start out as their zero value.
+    pub preopen: Option<String>,
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -631,6 +1034,127 @@ impl WasiValue {
         }
     }
 
+    /// Whether the values two runtimes produced for `tdef` agree. Each runtime
+    /// numbers its own handles, so handles anywhere in the value always match.
+    pub fn equivalent(&self, other: &WasiValue, tdef: &TypeDef, spec: &Spec) -> bool {
+        self.equivalent_as(other, &tdef.wasi, spec)
+    }
+
+    /// Like [`WasiValue::equivalent`], but for a type that needn't be named.
+    pub fn equivalent_as(&self, other: &WasiValue, wasi: &WasiType, spec: &Spec) -> bool {
+        let items_equivalent = |item: &TypeRef, a: &[WasiValue], b: &[WasiValue]| {
+            let item = item.resolve_wasi(spec);
+
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.equivalent_as(b, &item, spec))
+        };
+
+        match (wasi, self, other) {
+            | (_, Self::Handle(_), Self::Handle(_)) => true,
+            | (WasiType::Record(record), Self::Record(a), Self::Record(b)) => {
+                a.members.len() == b.members.len()
+                    && record
+                        .members
+                        .iter()
+                        .zip(a.members.iter().zip(&b.members))
+                        .all(|(member, (a, b))| a.equivalent_as(b, &member.tref.resolve_wasi(spec), spec))
+            },
+            | (WasiType::Variant(variant), Self::Variant(a), Self::Variant(b)) => {
+                let payload_tref = variant
+                    .cases
+                    .get(a.case_idx)
+                    .and_then(|case| case.payload.as_ref())
+                    .and_then(VariantPayload::tref);
+
+                a.case_idx == b.case_idx
+                    && match (payload_tref, &a.payload, &b.payload) {
+                        | (Some(tref), Some(a), Some(b)) => a.equivalent_as(b, &tref.resolve_wasi(spec), spec),
+                        | (_, a, b) => a == b,
+                    }
+            },
+            | (WasiType::List(list), Self::List(a), Self::List(b)) => items_equivalent(&list.item, &a.items, &b.items),
+            | (WasiType::Pointer(pointer), Self::Pointer(a), Self::Pointer(b)) => {
+                items_equivalent(&pointer.item, &a.items, &b.items)
+            },
+            | _ => self == other,
+        }
+    }
+
+    /// Renders the value in witx-like syntax, e.g. `fdflags { append }` or
+    /// `filetype::directory`, using `tref` to name flags, cases and fields.
+    pub fn pretty(&self, spec: &Spec, tref: &TypeRef) -> String {
+        let wasi = tref.resolve_wasi(spec);
+        let prefix = match tref {
+            | TypeRef::Named(name) => format!("{name} "),
+            | TypeRef::Anonymous(_) => String::new(),
+        };
+
+        match (&wasi, self) {
+            | (_, Self::Handle(handle)) => handle.to_string(),
+            | (_, Self::S64(i)) => i.to_string(),
+            | (_, Self::U8(i)) => i.to_string(),
+            | (_, Self::U16(i)) => i.to_string(),
+            | (_, Self::U32(i)) => i.to_string(),
+            | (_, Self::U64(i)) => i.to_string(),
+            | (_, Self::String(bytes)) => format!("{:?}", String::from_utf8_lossy(bytes)),
+            | (WasiType::Flags(flags), Self::Flags(value)) => {
+                // Bits past the declared fields are reserved and named by position.
+                let set = value
+                    .fields
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_bit, &set)| set)
+                    .map(|(bit, _set)| match flags.fields.get(bit) {
+                        | Some(name) => name.clone(),
+                        | None => format!("1 << {bit}"),
+                    })
+                    .join(", ");
+
+                if set.is_empty() {
+                    format!("{prefix}{{}}")
+                } else {
+                    format!("{prefix}{{ {set} }}")
+                }
+            },
+            | (WasiType::Variant(variant), Self::Variant(value)) => {
+                let case = &variant.cases[value.case_idx];
+                let name = match tref {
+                    | TypeRef::Named(name) => format!("{name}::{}", case.name),
+                    | TypeRef::Anonymous(_) => case.name.clone(),
+                };
+
+                match (case.payload.as_ref().and_then(VariantPayload::tref), &value.payload) {
+                    | (Some(tref), Some(payload)) => format!("{name}({})", payload.pretty(spec, tref)),
+                    | _ => name,
+                }
+            },
+            | (WasiType::Record(record), Self::Record(value)) => {
+                let members = record
+                    .members
+                    .iter()
+                    .zip(&value.members)
+                    .map(|(member, value)| format!("{}: {}", member.name, value.pretty(spec, &member.tref)))
+                    .join(", ");
+
+                format!("{prefix}{{ {members} }}")
+            },
+            | (WasiType::List(list), Self::List(ListValue { items })) => {
+                format!(
+                    "[{}]",
+                    items.iter().map(|item| item.pretty(spec, &list.item)).join(", ")
+                )
+            },
+            | (WasiType::Pointer(pointer), Self::Pointer(PointerValue { items })) => {
+                format!(
+                    "[{}]",
+                    items.iter().map(|item| item.pretty(spec, &pointer.item)).join(", ")
+                )
+            },
+            | _ => format!("{self:?}"),
+        }
+    }
+
+    /// Lowers the value for the executor. Strings and lists carry no separate
+    /// length: the executor takes it from the byte or item count.
     pub fn into_pb(self, spec: &Spec, tref: &TypeRef) -> wazzi_executor_pb_rust::Value {
         let which = match (&tref.resolve(spec).wasi, self) {
             | (_, Self::Handle(handle)) => wazzi_executor_pb_rust::value::Which::Handle(handle),
@@ -687,12 +1211,13 @@ impl WasiValue {
             | (WasiType::Flags(flags_type), Self::Flags(flags)) => {
                 wazzi_executor_pb_rust::value::Which::Bitflags(wazzi_executor_pb_rust::value::Bitflags {
                     repr:           wazzi_executor_pb_rust::IntRepr::from(flags_type.repr).into(),
-                    members:        flags_type
+                    // Bits past the declared fields are reserved and go unnamed.
+                    members:        flags
                         .fields
-                        .iter()
-                        .zip(flags.fields)
-                        .map(|(field_name, field)| wazzi_executor_pb_rust::value::bitflags::Member {
-                            name:           field_name.to_owned(),
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, field)| wazzi_executor_pb_rust::value::bitflags::Member {
+                            name:           flags_type.fields.get(i).cloned().unwrap_or_default(),
                             value:          field,
                             special_fields: Default::default(),
                         })
@@ -764,21 +1289,28 @@ impl WasiValue {
         }
     }
 
-    pub fn from_pb(value: wazzi_executor_pb_rust::Value, spec: &Spec, tdef: &TypeDef) -> Self {
-        match (&tdef.wasi, value.which.unwrap()) {
-            | (_, wazzi_executor_pb_rust::value::Which::Handle(handle)) => Self::Handle(handle),
-            | (_, wazzi_executor_pb_rust::value::Which::Builtin(builtin)) => match builtin.which.unwrap() {
-                | wazzi_executor_pb_rust::value::builtin::Which::Char(_c) => panic!(),
-                | wazzi_executor_pb_rust::value::builtin::Which::U8(i) => Self::U8(i.try_into().unwrap()),
-                | wazzi_executor_pb_rust::value::builtin::Which::U32(i) => Self::U32(i),
-                | wazzi_executor_pb_rust::value::builtin::Which::U64(i) => Self::U64(i),
-                | wazzi_executor_pb_rust::value::builtin::Which::S64(i) => Self::S64(i),
-                | _ => todo!(),
+    /// Decodes a value the executor sent back. Errors instead of panicking on
+    /// a value that doesn't match `tdef`, since a misbehaving runtime can send
+    /// anything.
+    pub fn from_pb(value: wazzi_executor_pb_rust::Value, spec: &Spec, tdef: &TypeDef) -> Result<Self, eyre::Error> {
+        let which = value.which.ok_or_else(|| err!("value of `{}` is empty", tdef.name))?;
+
+        Ok(match (&tdef.wasi, which) {
+            | (WasiType::Handle, wazzi_executor_pb_rust::value::Which::Handle(handle)) => Self::Handle(handle),
+            | (wasi_type, wazzi_executor_pb_rust::value::Which::Builtin(builtin)) => match (wasi_type, builtin.which) {
+                | (WasiType::U8, Some(wazzi_executor_pb_rust::value::builtin::Which::U8(i))) => Self::U8(i.try_into()?),
+                | (WasiType::U16, Some(wazzi_executor_pb_rust::value::builtin::Which::U16(i))) => {
+                    Self::U16(i.try_into()?)
+                },
+                | (WasiType::U32, Some(wazzi_executor_pb_rust::value::builtin::Which::U32(i))) => Self::U32(i),
+                | (WasiType::U64, Some(wazzi_executor_pb_rust::value::builtin::Which::U64(i))) => Self::U64(i),
+                | (WasiType::S64, Some(wazzi_executor_pb_rust::value::builtin::Which::S64(i))) => Self::S64(i),
+                | (_, builtin) => return Err(err!("builtin {builtin:?} is not a value of `{}`", tdef.name)),
             },
-            | (_, wazzi_executor_pb_rust::value::Which::Bitflags(flags)) => Self::Flags(FlagsValue {
+            | (WasiType::Flags(_), wazzi_executor_pb_rust::value::Which::Bitflags(flags)) => Self::Flags(FlagsValue {
                 fields: flags.members.into_iter().map(|member| member.value).collect(),
             }),
-            | (_, wazzi_executor_pb_rust::value::Which::String(string)) => Self::String(string),
+            | (WasiType::String, wazzi_executor_pb_rust::value::Which::String(string)) => Self::String(string),
             | (WasiType::String, wazzi_executor_pb_rust::value::Which::Array(array)) => {
                 Self::String(array.items.iter().map(|item| item.builtin().u8() as u8).collect())
             },
@@ -789,28 +1321,56 @@ impl WasiValue {
                         .iter()
                         .zip(record_value.members)
                         .map(|(member, member_value)| {
-                            WasiValue::from_pb(member_value.value.unwrap(), spec, member.tref.resolve(spec))
+                            let value = member_value
+                                .value
+                                .into_option()
+                                .ok_or_else(|| err!("member `{}` has no value", member.name))?;
+
+                            WasiValue::from_pb(value, spec, member.tref.resolve(spec))
                         })
-                        .collect(),
+                        .collect::<Result<_, _>>()?,
                 })
             },
+            | (
+                WasiType::Pointer(pointer),
+                wazzi_executor_pb_rust::value::Which::Pointer(array)
+                | wazzi_executor_pb_rust::value::Which::ConstPointer(array),
+            ) => Self::Pointer(PointerValue {
+                items: array
+                    .items
+                    .into_iter()
+                    .map(|item| WasiValue::from_pb(item, spec, pointer.item.resolve(spec)))
+                    .collect::<Result<_, _>>()?,
+            }),
+            | (WasiType::List(list), wazzi_executor_pb_rust::value::Which::Array(array)) => Self::List(ListValue {
+                items: array
+                    .items
+                    .into_iter()
+                    .map(|item| WasiValue::from_pb(item, spec, list.item.resolve(spec)))
+                    .collect::<Result<_, _>>()?,
+            }),
             | (WasiType::Variant(variant_type), wazzi_executor_pb_rust::value::Which::Variant(variant)) => {
                 let case_idx = variant.case_idx as usize;
-                let payload = variant_type.cases[case_idx].payload.as_ref().map(|payload| {
-                    let tdef = payload.tref().unwrap().resolve(spec);
-
-                    match variant.payload_option {
-                        | Some(wazzi_executor_pb_rust::value::variant::Payload_option::PayloadSome(p)) => {
-                            Self::from_pb(*p, spec, tdef)
-                        },
-                        | _ => panic!(),
-                    }
-                });
+                let case = variant_type
+                    .cases
+                    .get(case_idx)
+                    .ok_or_else(|| err!("`{}` has no case {case_idx}", tdef.name))?;
+                let payload = match (&case.payload, variant.payload_option) {
+                    | (None, _) => None,
+                    | (Some(payload), Some(wazzi_executor_pb_rust::value::variant::Payload_option::PayloadSome(p))) => {
+                        let tref = payload
+                            .tref()
+                            .ok_or_else(|| err!("case `{}` has a tuple payload", case.name))?;
+
+                        Some(Self::from_pb(*p, spec, tref.resolve(spec))?)
+                    },
+                    | (Some(_), _) => return Err(err!("case `{}` is missing its payload", case.name)),
+                };
 
                 Self::Variant(Box::new(VariantValue { case_idx, payload }))
             },
-            | _ => unreachable!("{:#?}", tdef),
-        }
+            | (wasi_type, which) => return Err(err!("`{which:?}` is not a value of {wasi_type}")),
+        })
     }
 }
 
@@ -819,6 +1379,17 @@ pub struct RecordValue {
     pub members: Vec<WasiValue>,
 }
 
+impl RecordValue {
+    /// The member named `name`, for a value of `record_type`.
+    pub fn get(&self, record_type: &RecordType, name: &str) -> Option<&WasiValue> {
+        self.members.get(record_type.member_idx(name)?)
+    }
+
+    pub fn get_mut(&mut self, record_type: &RecordType, name: &str) -> Option<&mut WasiValue> {
+        self.members.get_mut(record_type.member_idx(name)?)
+    }
+}
+
 #[derive(Serialize, Deserialize, Hash, PartialOrd, Ord, PartialEq, Eq, Clone, Debug)]
 pub struct FlagsValue {
     pub fields: Vec<bool>,
@@ -840,6 +1411,633 @@ pub struct VariantValue {
     pub payload:  Option<WasiValue>,
 }
 
+impl VariantValue {
+    /// Name of this value's case, for a value of `variant_type`.
+    pub fn case_name<'t>(&self, variant_type: &'t VariantType) -> Option<&'t str> {
+        variant_type.case_name(self.case_idx)
+    }
+}
+
+/// Picks one of the `boundaries` every so often instead of a uniform value,
+/// since short inputs rarely produce extremes on their own. The first boundary
+/// is what an exhausted input yields.
+fn arbitrary_int<'a, T>(u: &mut Unstructured<'a>, boundaries: &[T]) -> Result<T, arbitrary::Error>
+where
+    T: arbitrary::Arbitrary<'a> + Copy,
+{
+    if u.ratio(1, 8)? {
+        return u.choose(boundaries).copied();
+    }
+
+    u.arbitrary()
+}
+
+/// Bytes `Unstructured::int_in_range` takes for a range spanning `delta`.
+fn range_bytes(delta: usize) -> usize {
+    (usize::BITS - delta.leading_zeros()).div_ceil(8) as usize
+}
+
 fn align_to(ptr: u32, alignment: u32) -> u32 {
     ptr.div_ceil(alignment) * alignment
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn arbitrary_size_hints() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+        let config = GenConfig::default();
+        let list = WasiType::List(Box::new(ListType {
+            item: TypeRef::Anonymous(WasiType::U8),
+        }));
+        let record = WasiType::Record(RecordType {
+            members: [("offset", WasiType::U64), ("whence", WasiType::U8)]
+                .into_iter()
+                .map(|(name, wasi_type)| RecordMemberType {
+                    name:    name.to_string(),
+                    tref:    TypeRef::Anonymous(wasi_type),
+                    preopen: None,
+                })
+                .collect(),
+        });
+
+        assert_eq!(WasiType::U8.arbitrary_size_hint(&spec, &config), (2, Some(2)));
+        assert_eq!(WasiType::U64.arbitrary_size_hint(&spec, &config), (2, Some(9)));
+        assert_eq!(WasiType::Handle.arbitrary_size_hint(&spec, &config), (4, Some(4)));
+        assert_eq!(list.arbitrary_size_hint(&spec, &config), (1, Some(1 + 16 * 2)));
+        assert_eq!(record.arbitrary_size_hint(&spec, &config), (4, Some(11)));
+    }
+
+    #[test]
+    fn arbitrary_values_stay_within_size_hints() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+        let config = GenConfig {
+            utf8_strings: true,
+            reserved_flag_bits: true,
+            ..GenConfig::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut data = vec![0; 1 << 14];
+
+        for function in spec.interface("wasi_snapshot_preview1").unwrap().functions() {
+            for param in &function.params {
+                let wasi_type = param.tref.resolve_wasi(&spec);
+                let (lower, upper) = wasi_type.arbitrary_size_hint(&spec, &config);
+
+                for _ in 0..16 {
+                    rng.fill_bytes(&mut data);
+
+                    let mut u = Unstructured::new(&data);
+
+                    wasi_type.arbitrary_value(&spec, &config, &mut u).unwrap();
+
+                    let used = data.len() - u.len();
+
+                    assert!(lower <= used, "{}.{}: {used} < {lower}", function.name, param.name);
+                    assert!(
+                        upper.is_none_or(|upper| used <= upper),
+                        "{}.{}: {used} > {upper:?}",
+                        function.name,
+                        param.name
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn signatures_render_types() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+
+        assert_eq!(
+            spec.get_function("fd_close").unwrap().to_string(),
+            "fd_close(fd: fd) -> expected<_, errno>"
+        );
+        assert_eq!(
+            spec.get_function("args_sizes_get").unwrap().to_string(),
+            "args_sizes_get() -> expected<(ok_0: size, ok_1: size), errno>"
+        );
+    }
+
+    #[test]
+    fn expected_types_render_as_expected() {
+        let spec = Spec::preview1(
+            "
+(typename $size u32)
+(typename $errno (enum (@witx tag u16) $success $badf))
+(typename $sizes (expected (tuple $size $size) (error $errno)))
+(typename $status (expected (error $errno)))
+",
+        )
+        .unwrap();
+        let render = |name: &str| TypeRef::Named(name.to_string()).resolve(&spec).wasi.to_string();
+
+        assert_eq!(render("sizes"), "expected<(size, size), errno>");
+        assert_eq!(render("status"), "expected<_, errno>");
+    }
+
+    #[test]
+    fn functions_producing_and_consuming_fds() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+        let interface = spec.interface("wasi_snapshot_preview1").unwrap();
+        fn names(functions: Vec<&Function>) -> Vec<&str> {
+            functions.into_iter().map(|function| function.name.as_str()).collect()
+        }
+
+        let producing = names(interface.functions_producing(&spec, "fd"));
+        let consuming = names(interface.functions_consuming(&spec, "fd"));
+
+        assert!(producing.contains(&"path_open"));
+        assert!(!producing.contains(&"fd_read"));
+        assert!(consuming.contains(&"fd_read"));
+        assert!(consuming.contains(&"path_open"));
+        assert!(!consuming.contains(&"args_get"));
+    }
+
+    #[test]
+    fn record_members_by_name() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+        let fd_state = spec.get_wasi_type("fd").unwrap();
+        let fd_type = fd_state.record().unwrap();
+        let mut state = fd_state.zero_value(&spec);
+        let record = state.record_mut().unwrap();
+
+        *record.get_mut(fd_type, "offset").unwrap() = WasiValue::U64(42);
+
+        let directory = fd_type.members[fd_type.member_idx("type").unwrap()]
+            .tref
+            .resolve(&spec)
+            .wasi
+            .variant()
+            .unwrap()
+            .value_from_name("directory", None)
+            .unwrap();
+
+        *record.get_mut(fd_type, "type").unwrap() = directory.clone();
+
+        assert_eq!(record.get(fd_type, "offset"), Some(&WasiValue::U64(42)));
+        assert_eq!(record.get(fd_type, "type"), Some(&directory));
+        assert_eq!(record.members[0], WasiValue::U64(42));
+        assert_eq!(record.get(fd_type, "inode"), None);
+    }
+
+    #[test]
+    fn string_len_is_clamped() {
+        let spec = Spec::new();
+        let config = GenConfig {
+            max_string_len: 2,
+            ..Default::default()
+        };
+        let data = [0xffu8; 256];
+        let mut u = Unstructured::new(&data);
+
+        for _ in 0..32 {
+            match WasiType::String.arbitrary_value(&spec, &config, &mut u).unwrap() {
+                | WasiValue::String(bytes) => assert!(bytes.len() <= 2, "{bytes:?}"),
+                | value => panic!("unexpected value {value:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn zero_variant_with_list_payload() {
+        let spec = Spec::new();
+        let variant = WasiType::Variant(VariantType {
+            tag_repr: IntRepr::U8,
+            cases:    vec![
+                VariantCaseType {
+                    name:    "items".to_string(),
+                    payload: Some(VariantPayload::TypeRef(TypeRef::Anonymous(WasiType::List(Box::new(
+                        ListType {
+                            item: TypeRef::Anonymous(WasiType::U32),
+                        },
+                    ))))),
+                },
+                VariantCaseType {
+                    name:    "none".to_string(),
+                    payload: None,
+                },
+            ],
+        });
+
+        assert_eq!(
+            variant.zero_value(&spec),
+            WasiValue::Variant(Box::new(VariantValue {
+                case_idx: 0,
+                payload:  Some(WasiValue::List(ListValue { items: vec![] })),
+            }))
+        );
+    }
+
+    #[test]
+    fn pretty_values() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+        let named = |name: &str| TypeRef::Named(name.to_string());
+        let fdflags = spec.types.get_by_key("fdflags").unwrap();
+        let fields = |set: &[&str]| match &fdflags.wasi {
+            | WasiType::Flags(flags) => FlagsValue {
+                fields: flags.fields.iter().map(|field| set.contains(&field.as_str())).collect(),
+            },
+            | wasi => panic!("unexpected type {wasi:?}"),
+        };
+        let filetype = match &spec.types.get_by_key("filetype").unwrap().wasi {
+            | WasiType::Variant(variant) => variant.clone(),
+            | wasi => panic!("unexpected type {wasi:?}"),
+        };
+
+        assert_eq!(
+            WasiValue::Flags(fields(&["append", "nonblock"])).pretty(&spec, &named("fdflags")),
+            "fdflags { append, nonblock }"
+        );
+        assert_eq!(
+            WasiValue::Flags(FlagsValue {
+                fields: [fields(&["append"]).fields, vec![false, false, true]].concat(),
+            })
+            .pretty(&spec, &named("fdflags")),
+            "fdflags { append, 1 << 7 }"
+        );
+        assert_eq!(
+            filetype
+                .value_from_name("directory", None)
+                .unwrap()
+                .pretty(&spec, &named("filetype")),
+            "filetype::directory"
+        );
+
+        assert_eq!(
+            WasiValue::Variant(Box::new(VariantValue {
+                case_idx: 0,
+                payload:  Some(WasiValue::Record(RecordValue {
+                    members: vec![WasiValue::U32(4)],
+                })),
+            }))
+            .pretty(&spec, &named("prestat")),
+            "prestat::dir(prestat_dir { pr_name_len: 4 })"
+        );
+        assert_eq!(
+            WasiValue::List(ListValue {
+                items: vec![WasiValue::U32(1), WasiValue::U32(2), WasiValue::U32(3)],
+            })
+            .pretty(
+                &spec,
+                &TypeRef::Anonymous(WasiType::List(Box::new(ListType {
+                    item: TypeRef::Anonymous(WasiType::U32),
+                })))
+            ),
+            "[1, 2, 3]"
+        );
+
+        let fdstat = WasiValue::Record(RecordValue {
+            members: vec![
+                filetype.value_from_name("regular_file", None).unwrap(),
+                WasiValue::Flags(fields(&["sync"])),
+                WasiValue::Flags(FlagsValue {
+                    fields: vec![false; 64],
+                }),
+                WasiValue::Flags(FlagsValue {
+                    fields: vec![false; 64],
+                }),
+            ],
+        });
+
+        assert_eq!(
+            fdstat.pretty(&spec, &named("fdstat")),
+            "fdstat { fs_filetype: filetype::regular_file, fs_flags: fdflags { sync }, fs_rights_base: rights {}, \
+             fs_rights_inheriting: rights {} }"
+        );
+    }
+
+    #[test]
+    fn s64_hits_boundaries() {
+        let spec = Spec::new();
+        let data = (0..=255u8).cycle().take(4096).collect_vec();
+        let mut u = Unstructured::new(&data);
+        let mut seen = HashSet::new();
+
+        for _ in 0..256 {
+            match WasiType::S64
+                .arbitrary_value(&spec, &GenConfig::default(), &mut u)
+                .unwrap()
+            {
+                | WasiValue::S64(i) => seen.insert(i),
+                | value => panic!("unexpected value {value:?}"),
+            };
+        }
+
+        assert!([i64::MIN, i64::MAX].iter().any(|i| seen.contains(i)), "{seen:?}");
+    }
+
+    fn arbitrary_strings(config: &GenConfig) -> Vec<Vec<u8>> {
+        let spec = Spec::new();
+        let data = (0..=255u8).cycle().take(4096).collect_vec();
+        let mut u = Unstructured::new(&data);
+
+        (0..64)
+            .map(
+                |_| match WasiType::String.arbitrary_value(&spec, config, &mut u).unwrap() {
+                    | WasiValue::String(bytes) => bytes,
+                    | value => panic!("unexpected value {value:?}"),
+                },
+            )
+            .collect()
+    }
+
+    #[test]
+    fn strings_from_path_alphabet_by_default() {
+        for bytes in arbitrary_strings(&GenConfig::default()) {
+            assert!(bytes.iter().all(|b| b"./a".contains(b)), "{bytes:?}");
+        }
+    }
+
+    #[test]
+    fn utf8_strings_are_valid() {
+        let config = GenConfig {
+            utf8_strings: true,
+            ..Default::default()
+        };
+        let strings = arbitrary_strings(&config);
+
+        assert!(strings.iter().any(|bytes| bytes.iter().any(|b| !b.is_ascii())));
+
+        for bytes in strings {
+            assert!(bytes.len() <= config.max_string_len);
+            String::from_utf8(bytes).unwrap();
+        }
+    }
+
+    #[test]
+    fn reserved_flag_bits_are_set_when_enabled() {
+        let spec = Spec::preview1("(typename $fdflags (flags (@witx repr u16) $append $dsync $nonblock $rsync $sync))")
+            .unwrap();
+        let fdflags = spec.get_wasi_type("fdflags").unwrap();
+        let data = (0..8192u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect_vec();
+        let generate = |config: &GenConfig| {
+            let mut u = Unstructured::new(&data);
+
+            (0..256)
+                .map(|_| match fdflags.arbitrary_value(&spec, config, &mut u).unwrap() {
+                    | WasiValue::Flags(flags) => flags,
+                    | value => panic!("unexpected value {value:?}"),
+                })
+                .collect_vec()
+        };
+        let reserved = generate(&GenConfig {
+            reserved_flag_bits: true,
+            ..Default::default()
+        });
+
+        assert!(generate(&GenConfig::default())
+            .iter()
+            .all(|flags| flags.fields.len() == 5));
+        assert!(reserved.iter().all(|flags| flags.fields.len() <= 16));
+
+        let flags = reserved
+            .into_iter()
+            .find(|flags| flags.fields.len() > 5)
+            .expect("no reserved bit was set");
+        let pb = WasiValue::Flags(flags.clone()).into_pb(&spec, &TypeRef::Named("fdflags".to_string()));
+
+        assert!(flags.fields.last().unwrap());
+        assert_eq!(pb.bitflags().members.len(), flags.fields.len());
+        assert_eq!(pb.bitflags().members.last().unwrap().name, "");
+    }
+
+    #[test]
+    fn int_comparisons_on_u32_param() {
+        let spec = Spec::preview1(
+            "
+(typename $size u32)
+(module $wasi_snapshot_preview1
+  (@interface func (export \"f\")
+    (param $len $size)
+    (@input
+      (@and
+        (@int.ge (param $len) 1)
+        (@int.lt (param $len) 10)
+        (@int.eq (param $len) 4)
+      )
+    )
+  )
+)
+",
+        )
+        .unwrap();
+        let len = || {
+            slang::Term::Param(slang::Param {
+                name: "len".to_string(),
+            })
+        };
+        let int = |i: u32| slang::Term::IntConst(i.into());
+
+        assert_eq!(
+            spec.get_function("f").unwrap().input_contract,
+            Some(slang::Term::And(slang::And {
+                clauses: vec![
+                    slang::Term::IntGe(Box::new(slang::IntGe {
+                        lhs: len(),
+                        rhs: int(1),
+                    })),
+                    slang::Term::IntLt(Box::new(slang::IntLt {
+                        lhs: len(),
+                        rhs: int(10),
+                    })),
+                    slang::Term::IntEq(Box::new(slang::IntEq {
+                        lhs: len(),
+                        rhs: int(4),
+                    })),
+                ],
+            }))
+        );
+    }
+
+    #[test]
+    fn or_and_not_contracts() {
+        let spec = Spec::preview1(
+            "
+(typename $size u32)
+(module $wasi_snapshot_preview1
+  (@interface func (export \"f\")
+    (param $offset $size)
+    (@input
+      (@or
+        (@int.lt (param $offset) 10)
+        (@not (@int.le (param $offset) 1000))
+      )
+    )
+  )
+)
+",
+        )
+        .unwrap();
+        let function = spec.get_function("f").unwrap();
+        let contract = function.input_contract.as_ref().unwrap();
+        let holds = |offset: u32| {
+            contract.eval(
+                &spec,
+                function,
+                &HashMap::from([("offset".to_string(), WasiValue::U32(offset))]),
+            )
+        };
+
+        assert!(holds(9));
+        assert!(!holds(10));
+        assert!(!holds(1000));
+        assert!(holds(1001));
+    }
+
+    #[test]
+    fn iovec_pointer_round_trips() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+        let iovecs = TypeRef::Named("iovec_array".to_string());
+        let value = WasiValue::List(ListValue {
+            items: vec![WasiValue::Record(RecordValue {
+                members: vec![
+                    WasiValue::Pointer(PointerValue {
+                        items: vec![WasiValue::U8(1), WasiValue::U8(2)],
+                    }),
+                    WasiValue::U32(2),
+                ],
+            })],
+        });
+        let pb = value.clone().into_pb(&spec, &iovecs);
+        let iovec = &pb.array().items[0].record().members;
+
+        assert_eq!(iovec[0].name, "buf");
+        assert_eq!(iovec[0].value.pointer().item_size, 1);
+        assert_eq!(iovec[0].value.pointer().items.len(), 2);
+        assert_eq!(iovec[1].offset, 4);
+        assert_eq!(WasiValue::from_pb(pb, &spec, iovecs.resolve(&spec)).unwrap(), value);
+    }
+
+    #[test]
+    fn string_and_list_lengths_round_trip() {
+        let spec = Spec::preview1("(typename $path string) (typename $sizes (list u32))").unwrap();
+        let path = TypeRef::Named("path".to_string());
+        let sizes = TypeRef::Named("sizes".to_string());
+        let path_value = WasiValue::String(b"a/\0b".to_vec());
+        let sizes_value = WasiValue::List(ListValue {
+            items: vec![WasiValue::U32(1), WasiValue::U32(2), WasiValue::U32(3)],
+        });
+        let path_pb = path_value.clone().into_pb(&spec, &path);
+        let sizes_pb = sizes_value.clone().into_pb(&spec, &sizes);
+
+        assert_eq!(path_pb.string().len(), 4);
+        assert_eq!(sizes_pb.array().items.len(), 3);
+        assert_eq!(sizes_pb.array().item_size, 4);
+        assert_eq!(
+            WasiValue::from_pb(path_pb, &spec, path.resolve(&spec)).unwrap(),
+            path_value
+        );
+        assert_eq!(
+            WasiValue::from_pb(sizes_pb, &spec, sizes.resolve(&spec)).unwrap(),
+            sizes_value
+        );
+    }
+
+    #[test]
+    fn errno_names_come_from_spec() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+
+        assert_eq!(spec.errno_name(2).as_deref(), Some("EACCES"));
+        assert_eq!(spec.errno_name(8).as_deref(), Some("EBADF"));
+        assert_eq!(spec.errno_name(44).as_deref(), Some("ENOENT"));
+        assert_eq!(spec.errno_name(-1), None);
+        assert_eq!(spec.errno_name(1000), None);
+    }
+
+    #[test]
+    fn case_names_resolve_from_index() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+        let filetype = spec.get_wasi_type("filetype").unwrap();
+        let filetype = filetype.variant().unwrap();
+        let directory = VariantValue {
+            case_idx: 3,
+            payload:  None,
+        };
+
+        assert_eq!(filetype.case_name(0), Some("unknown"));
+        assert_eq!(directory.case_name(filetype), Some("directory"));
+        assert_eq!(filetype.case_name(filetype.cases.len()), None);
+    }
+
+    #[test]
+    fn negative_s64_round_trips() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+        let filedelta = TypeRef::Named("filedelta".to_string());
+        let pb = WasiValue::S64(-1).into_pb(&spec, &filedelta);
+
+        assert_eq!(pb.builtin().s64(), -1);
+        assert_eq!(
+            WasiValue::from_pb(pb, &spec, filedelta.resolve(&spec)).unwrap(),
+            WasiValue::S64(-1)
+        );
+    }
+
+    #[test]
+    fn mismatched_pb_is_an_error() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+        let iovecs = TypeRef::Named("iovec_array".to_string());
+        let path = TypeRef::Named("path".to_string());
+        let pb = WasiValue::S64(-1).into_pb(&spec, &TypeRef::Named("filedelta".to_string()));
+
+        assert!(WasiValue::from_pb(pb.clone(), &spec, iovecs.resolve(&spec)).is_err());
+        assert!(WasiValue::from_pb(pb, &spec, path.resolve(&spec)).is_err());
+        assert!(WasiValue::from_pb(Default::default(), &spec, path.resolve(&spec)).is_err());
+    }
+
+    #[test]
+    fn functions_are_looked_up_by_interface() {
+        let spec = Spec::preview1(
+            r#"
+            (module $wasi_snapshot_preview1
+              (@interface func (export "sched_yield"))
+            )
+            "#,
+        )
+        .unwrap();
+        let interface = spec.interface("wasi_snapshot_preview1").unwrap();
+
+        assert_eq!(interface.function("sched_yield").unwrap().name, "sched_yield");
+        assert!(interface.function("fd_close").is_none());
+        assert!(spec.interface("wasi_unstable").is_none());
+        assert_eq!(spec.get_function("sched_yield"), interface.function("sched_yield"));
+    }
+
+    #[test]
+    fn handles_are_equivalent_anywhere() {
+        let spec = Spec::preview1(
+            r#"
+            (typename $fd (handle))
+            (typename $opened (record (field $fd $fd) (field $size u64)))
+            (typename $opened_list (list $opened))
+            "#,
+        )
+        .unwrap();
+        let opened = |fd: u32, size: u64| {
+            WasiValue::Record(RecordValue {
+                members: vec![WasiValue::Handle(fd), WasiValue::U64(size)],
+            })
+        };
+        let list = |items: Vec<WasiValue>| WasiValue::List(ListValue { items });
+        let record_tdef = TypeRef::Named("opened".to_string()).resolve(&spec).clone();
+        let list_tdef = TypeRef::Named("opened_list".to_string()).resolve(&spec).clone();
+
+        assert!(opened(3, 10).equivalent(&opened(4, 10), &record_tdef, &spec));
+        assert!(!opened(3, 10).equivalent(&opened(3, 11), &record_tdef, &spec));
+        assert!(list(vec![opened(3, 1), opened(5, 2)]).equivalent(
+            &list(vec![opened(9, 1), opened(8, 2)]),
+            &list_tdef,
+            &spec
+        ));
+        assert!(!list(vec![opened(3, 1)]).equivalent(&list(vec![opened(3, 1), opened(3, 1)]), &list_tdef, &spec));
+        assert!(!list(vec![opened(3, 1)]).equivalent(&list(vec![opened(3, 2)]), &list_tdef, &spec));
+    }
+}
@@ -9,6 +9,7 @@ use pest::{
 use pest_derive::Parser;
 
 use super::{
+    Constant,
     FlagsType,
     Function,
     FunctionParam,
@@ -59,8 +60,13 @@ pub(super) fn preview1(s: &str) -> Result<Spec, eyre::Error> {
                         match pair.as_rule() {
                             | Rule::id => {
                                 let target_type_name = pair.as_str().strip_prefix('$').unwrap();
+                                let (line, col) = pair.as_span().start_pos().line_col();
+                                let target = spec.types.get_by_key(target_type_name).wrap_err(format!(
+                                    "type alias `{name}` refers to undefined type `{target_type_name}` at {line}:{col}"
+                                ))?;
+                                let (wasi_type, state) = (target.wasi.clone(), target.state.clone());
 
-                                panic!("{target_type_name}");
+                                spec.insert_type_def(name.to_string(), wasi_type, state);
                             },
                             | Rule::r#type => {
                                 let wasi_type =
@@ -97,6 +103,13 @@ pub(super) fn preview1(s: &str) -> Result<Spec, eyre::Error> {
                     | _ => return Err(err!("expected type ref")),
                 }
             },
+            | Rule::constant => {
+                let (name, constant) = preview1_constant(&spec, pair)?;
+
+                if spec.constants.insert(name.clone(), constant).is_some() {
+                    return Err(err!("constant `{name}` is declared twice"));
+                }
+            },
             | Rule::module => {
                 let mut pairs = pair.into_inner();
                 let id = pairs.next().unwrap();
@@ -106,16 +119,77 @@ pub(super) fn preview1(s: &str) -> Result<Spec, eyre::Error> {
                 };
                 let interface = preview1_module(&spec, pairs)?;
 
+                tracing::trace!(module = name, nfunctions = interface.functions.len(), "Parsed module.");
                 spec.interfaces.push(name.to_string(), interface);
             },
             | Rule::EOI => (),
-            | _ => panic!("{:?}", pair.as_rule()),
+            | rule => {
+                let (line, col) = pair.as_span().start_pos().line_col();
+
+                return Err(err!("unexpected top-level rule {rule:?} at {line}:{col}"));
+            },
+        }
+    }
+
+    for (_name, interface) in spec.interfaces.iter() {
+        for function in interface.functions.values() {
+            let contracts = function.input_contract.iter().chain(&function.output_contract);
+
+            for name in contracts.flat_map(slang::Term::witx_consts) {
+                if spec.get_constant(name).is_none() {
+                    return Err(err!(
+                        "function `{}` refers to undeclared constant `{name}`",
+                        function.name
+                    ));
+                }
+            }
         }
     }
 
     Ok(spec)
 }
 
+/// A `(@witx const $type? $name value)` declaration, whose value must fit
+/// its type if it has one.
+fn preview1_constant(spec: &Spec, pair: Pair<'_, Rule>) -> Result<(String, Constant), eyre::Error> {
+    let (line, col) = pair.as_span().start_pos().line_col();
+    let mut pairs = pair.into_inner().collect_vec();
+    let value_pair = pairs.pop().unwrap();
+    let value = match value_pair.as_str().strip_prefix("0x") {
+        | Some(hex) => u64::from_str_radix(hex, 16),
+        | None => value_pair.as_str().parse(),
+    }
+    .wrap_err(format!("constant value out of range at {line}:{col}"))?;
+    let mut ids = pairs
+        .iter()
+        .map(|pair| pair.as_str().strip_prefix('$').unwrap().to_string());
+    let (ty, name) = match (ids.next(), ids.next()) {
+        | (Some(ty), Some(name)) => (Some(ty), name),
+        | (Some(name), None) => (None, name),
+        | _ => unreachable!(),
+    };
+
+    if let Some(ty) = &ty {
+        let tdef = spec
+            .types
+            .get_by_key(ty)
+            .wrap_err(format!("constant `{name}` has undefined type `{ty}` at {line}:{col}"))?;
+        let max = match &tdef.wasi {
+            | WasiType::U8 => u8::MAX.into(),
+            | WasiType::U16 => u16::MAX.into(),
+            | WasiType::U32 => u32::MAX.into(),
+            | WasiType::S64 => i64::MAX as u64,
+            | _ => u64::MAX,
+        };
+
+        if value > max {
+            return Err(err!("constant `{name}` doesn't fit its type `{ty}` at {line}:{col}"));
+        }
+    }
+
+    Ok((name, Constant { ty, value }))
+}
+
 fn preview1_module(spec: &Spec, pairs: Pairs<'_, Rule>) -> Result<Interface, eyre::Error> {
     let mut interface = Interface::new();
 
@@ -140,6 +214,7 @@ fn preview1_module(spec: &Spec, pairs: Pairs<'_, Rule>) -> Result<Interface, eyr
         let mut r#return = None;
         let mut input_contract = None;
         let mut output_contract = None;
+        let mut consumes = Vec::new();
 
         for pair in pairs {
             match pair.as_rule() {
@@ -164,33 +239,47 @@ fn preview1_module(spec: &Spec, pairs: Pairs<'_, Rule>) -> Result<Interface, eyr
                         | _ => unreachable!(),
                     };
                     let tref_pair = pairs.next().unwrap();
+                    let expected = is_expected(&tref_pair);
                     let tref = preview1_tref(spec, tref_pair).wrap_err("failed to handle param tref")?[0].clone();
+                    let mut unspecified = false;
+
+                    for pair in pairs {
+                        let annot_pair = pair.into_inner().next().unwrap();
+
+                        match annot_pair.as_str() {
+                            | "@unspecified" => unspecified = true,
+                            | _ => return Err(err!("unexpected result annotation {:?}", annot_pair)),
+                        }
+                    }
 
                     match &tref.resolve_wasi(spec) {
-                        | WasiType::Variant(variant) => match (variant.cases.first(), variant.cases.get(1)) {
-                            | (Some(c1), Some(c2)) if c1.name == "ok" && c2.name == "error" => {
-                                r#return = Some(());
-
-                                if let Some(payload) = c1.payload.as_ref() {
-                                    match payload {
-                                        | VariantPayload::TypeRef(tref) => results.push(FunctionResult {
-                                            name: "ok".to_owned(),
-                                            tref: tref.to_owned(),
-                                        }),
-                                        | VariantPayload::Tuple(trefs) => {
-                                            for (i, tref) in trefs.iter().enumerate() {
-                                                results.push(FunctionResult {
-                                                    name: format!("ok_{i}"),
-                                                    tref: tref.to_owned(),
-                                                });
-                                            }
-                                        },
-                                    }
+                        | WasiType::Variant(variant) if expected => {
+                            r#return = Some(());
+
+                            if let Some(payload) = variant.cases[0].payload.as_ref() {
+                                match payload {
+                                    | VariantPayload::TypeRef(tref) => results.push(FunctionResult {
+                                        name: "ok".to_owned(),
+                                        tref: tref.to_owned(),
+                                        unspecified,
+                                    }),
+                                    | VariantPayload::Tuple(trefs) => {
+                                        for (i, tref) in trefs.iter().enumerate() {
+                                            results.push(FunctionResult {
+                                                name: format!("ok_{i}"),
+                                                tref: tref.to_owned(),
+                                                unspecified,
+                                            });
+                                        }
+                                    },
                                 }
-                            },
-                            | _ => results.push(FunctionResult { name, tref }),
+                            }
                         },
-                        | _ => results.push(FunctionResult { name, tref }),
+                        | _ => results.push(FunctionResult {
+                            name,
+                            tref,
+                            unspecified,
+                        }),
                     }
                 },
                 | Rule::annotation_expr => {
@@ -218,6 +307,14 @@ fn preview1_module(spec: &Spec, pairs: Pairs<'_, Rule>) -> Result<Interface, eyr
 
                             output_contract = Some(term);
                         },
+                        | Rule::annotation if annot_pair.as_str() == "@consume" => {
+                            for pair in pairs {
+                                match pair.as_rule() {
+                                    | Rule::id => consumes.push(pair.as_str().strip_prefix('$').unwrap().to_owned()),
+                                    | _ => return Err(err!("expected param ids in @consume")),
+                                }
+                            }
+                        },
                         | _ => panic!("{:?}", annot_pair),
                     }
                 },
@@ -234,6 +331,7 @@ fn preview1_module(spec: &Spec, pairs: Pairs<'_, Rule>) -> Result<Interface, eyr
                 r#return,
                 input_contract,
                 output_contract,
+                consumes,
             },
         );
     }
@@ -319,16 +417,25 @@ fn preview1_wasi_type(spec: &Spec, pair: Pair<'_, Rule>) -> Result<WasiType, eyr
                 | _ => panic!(),
             };
 
-            for (case_pair, case_name_type) in pairs.zip(tag.cases.iter()) {
-                let case_type_name = match case_pair.as_rule() {
-                    | Rule::id => case_pair.as_str().strip_prefix('$').unwrap(),
-                    | Rule::comment => {
-                        tracing::trace!("Ignoring enum variant comment.");
-                        continue;
-                    },
+            let mut case_type_names = Vec::new();
+
+            for case_pair in pairs {
+                match case_pair.as_rule() {
+                    | Rule::id => case_type_names.push(case_pair.as_str().strip_prefix('$').unwrap()),
+                    | Rule::comment => tracing::trace!("Ignoring enum variant comment."),
                     | _ => return Err(err!("unexpected field {:?}", case_pair)),
-                };
+                }
+            }
+
+            if case_type_names.len() != tag.cases.len() {
+                return Err(err!(
+                    "union has {} cases but its tag `{tag_name}` has {}",
+                    case_type_names.len(),
+                    tag.cases.len()
+                ));
+            }
 
+            for (case_type_name, case_name_type) in case_type_names.into_iter().zip(tag.cases.iter()) {
                 cases.push(VariantCaseType {
                     name:    case_name_type.name.clone(),
                     payload: Some(VariantPayload::TypeRef(TypeRef::Named(case_type_name.to_string()))),
@@ -395,8 +502,21 @@ fn preview1_wasi_type(spec: &Spec, pair: Pair<'_, Rule>) -> Result<WasiType, eyr
                     | _ => unreachable!(),
                 };
                 let tref = preview1_tref(spec, tref_pair)?[0].clone();
+                let mut preopen = None;
 
-                members.push(RecordMemberType { name, tref });
+                for pair in pairs {
+                    let mut pairs = pair.into_inner();
+                    let annot_pair = pairs.next().unwrap();
+
+                    match (annot_pair.as_str(), pairs.next()) {
+                        | ("@preopen", Some(case)) if case.as_rule() == Rule::id => {
+                            preopen = Some(case.as_str().strip_prefix('$').unwrap().to_owned());
+                        },
+                        | _ => return Err(err!("unexpected field annotation {:?}", annot_pair)),
+                    }
+                }
+
+                members.push(RecordMemberType { name, tref, preopen });
             }
 
             WasiType::Record(RecordType { members })
@@ -428,7 +548,11 @@ fn preview1_tref(spec: &Spec, pair: Pair<'_, Rule>) -> Result<Vec<TypeRef>, eyre
     match pair.as_rule() {
         | Rule::id => {
             let id = pair.as_str().strip_prefix('$').unwrap();
-            let _tdef = spec.types.get_by_key(id).wrap_err("type ref to non-existent type")?;
+            let (line, col) = pair.as_span().start_pos().line_col();
+            let _tdef = spec
+                .types
+                .get_by_key(id)
+                .wrap_err(format!("type ref to undefined type `{id}` at {line}:{col}"))?;
 
             Ok(vec![TypeRef::Named(id.to_string())])
         },
@@ -448,6 +572,24 @@ fn preview1_tref(spec: &Spec, pair: Pair<'_, Rule>) -> Result<Vec<TypeRef>, eyre
     }
 }
 
+/// Whether `pair` is an inline `(expected ...)` type, whose success payload
+/// becomes the function's results. Named variants are never unwrapped, even
+/// if their cases happen to be called `ok` and `error`.
+fn is_expected(pair: &Pair<'_, Rule>) -> bool {
+    let mut pair = pair.clone();
+
+    loop {
+        match pair.as_rule() {
+            | Rule::expected => return true,
+            | Rule::type_ref | Rule::r#type => match pair.into_inner().next() {
+                | Some(inner) => pair = inner,
+                | None => return false,
+            },
+            | _ => return false,
+        }
+    }
+}
+
 fn preview1_int_repr(pair: Pair<'_, Rule>) -> Result<IntRepr, eyre::Error> {
     Ok(match pair.as_rule() {
         | Rule::r#u8 => IntRepr::U8,
@@ -457,3 +599,172 @@ fn preview1_int_repr(pair: Pair<'_, Rule>) -> Result<IntRepr, eyre::Error> {
         | _ => return Err(err!("unknown int repr {:?}", pair)),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::WasiValue;
+
+    #[test]
+    fn chained_type_aliases() {
+        let spec = preview1(
+            r#"
+            (typename $size u32)
+            (typename $filesize $size)
+            (typename $dirsize $filesize)
+            "#,
+        )
+        .unwrap();
+
+        let filesize = spec.types.get_by_key("filesize").unwrap();
+        let dirsize = spec.types.get_by_key("dirsize").unwrap();
+
+        assert_eq!(filesize.wasi, WasiType::U32);
+        assert_eq!(dirsize.wasi, filesize.wasi);
+        assert_eq!(dirsize.name, "dirsize");
+    }
+
+    #[test]
+    fn alias_to_undefined_type() {
+        assert!(preview1("(typename $filesize $nonexistent)").is_err());
+    }
+
+    #[test]
+    fn unexpected_top_level_rule() {
+        assert!(preview1("(typename $size u32) (foo $bar)").is_err());
+    }
+
+    #[test]
+    fn undefined_type_ref_reports_position() {
+        let err = preview1(
+            r#"(module $wasi_snapshot_preview1
+  (@interface func (export "fd_close")
+    (param $fd $nonexistent)
+  )
+)"#,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err.root_cause().to_string(),
+            "type ref to undefined type `nonexistent` at 3:16"
+        );
+    }
+
+    fn union_with_cases(cases: &str) -> Result<Spec, eyre::Error> {
+        preview1(&format!(
+            r#"
+            (typename $tag (enum (@witx tag u8) $a $b))
+            (typename $u (union (@witx tag $tag) {cases}))
+            "#
+        ))
+    }
+
+    #[test]
+    fn union_case_count_must_match_tag() {
+        assert_eq!(
+            union_with_cases("$tag").unwrap_err().root_cause().to_string(),
+            "union has 1 cases but its tag `tag` has 2"
+        );
+        assert!(union_with_cases("$tag $tag $tag").is_err());
+        assert!(union_with_cases("$tag ; comment\n $tag").is_ok());
+    }
+
+    #[test]
+    fn ok_error_enum_result_is_not_unwrapped() {
+        let spec = preview1(
+            r#"
+            (typename $errno (enum (@witx tag u16) $success $badf))
+            (typename $outcome (enum (@witx tag u8) $ok $error))
+            (module $wasi_snapshot_preview1
+              (@interface func (export "get_outcome")
+                (result $outcome $outcome)
+              )
+              (@interface func (export "get_size")
+                (result $error (expected u32 (error $errno)))
+              )
+            )
+            "#,
+        )
+        .unwrap();
+        let get_outcome = spec.get_function("get_outcome").unwrap();
+        let get_size = spec.get_function("get_size").unwrap();
+
+        assert_eq!(get_outcome.r#return, None);
+        assert_eq!(get_outcome.results.len(), 1);
+        assert_eq!(get_outcome.results[0].tref, TypeRef::Named("outcome".to_string()));
+        assert_eq!(get_size.r#return, Some(()));
+        assert_eq!(get_size.results.len(), 1);
+        assert_eq!(get_size.results[0].tref, TypeRef::Anonymous(WasiType::U32));
+    }
+
+    #[test]
+    fn constants_can_be_referenced_in_contracts() {
+        let spec = preview1(
+            r#"
+            (typename $clockid u32)
+            (@witx const $clockid $monotonic 1)
+            (@witx const $max_len 0x100)
+            (module $wasi_snapshot_preview1
+              (@interface func (export "clock_res_get")
+                (param $id $clockid)
+                (@input (@int.eq (param $id) (@witx.const $monotonic)))
+              )
+            )
+            "#,
+        )
+        .unwrap();
+        let function = spec.get_function("clock_res_get").unwrap();
+        let contract = function.input_contract.as_ref().unwrap();
+        let params = |id: u32| [("id".to_string(), WasiValue::U32(id))].into_iter().collect();
+
+        assert_eq!(
+            spec.get_constant("monotonic"),
+            Some(&Constant {
+                ty:    Some("clockid".to_string()),
+                value: 1,
+            })
+        );
+        assert_eq!(spec.get_constant("max_len").unwrap().value, 256);
+        assert!(contract.eval(&spec, function, &params(1)));
+        assert!(!contract.eval(&spec, function, &params(0)));
+        assert!(preview1("(@witx const $nonexistent $x 1)").is_err());
+        assert!(preview1("(typename $small u8) (@witx const $small $x 256)").is_err());
+        assert!(preview1("(@witx const $x 1) (@witx const $x 2)").is_err());
+        assert!(preview1(
+            r#"
+            (module $wasi_snapshot_preview1
+              (@interface func (export "clock_res_get")
+                (param $id u32)
+                (@input (@int.eq (param $id) (@witx.const $monotonic)))
+              )
+            )
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn unspecified_result_is_flagged() {
+        let spec = preview1(
+            r#"
+            (typename $errno (enum (@witx tag u16) $success $badf))
+            (module $wasi_snapshot_preview1
+              (@interface func (export "get_sizes")
+                (result $error (expected (tuple u32 u32) (error $errno))
+                  (@unspecified))
+              )
+              (@interface func (export "get_size")
+                (result $error (expected u32 (error $errno)))
+              )
+            )
+            "#,
+        )
+        .unwrap();
+        let get_sizes = spec.get_function("get_sizes").unwrap();
+
+        assert_eq!(get_sizes.results.len(), 2);
+        assert!(get_sizes.results.iter().all(|result| result.unspecified));
+        assert!(!spec.get_function("get_size").unwrap().results[0].unspecified);
+    }
+}
@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
 
 use eyre::Context as _;
 use itertools::Itertools;
@@ -6,6 +6,39 @@ use num_bigint::BigInt;
 use pest::iterators::Pair;
 use pest_derive::Parser;
 
+use crate::spec::{self, Function, Spec, WasiType, WasiValue};
+
+/// Concrete values a term is evaluated against. Without the spec and function,
+/// variant constants and record members can't be resolved, so terms on them
+/// are undecided.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Scope<'a> {
+    pub(crate) spec:     Option<&'a Spec>,
+    pub(crate) function: Option<&'a Function>,
+    pub(crate) params:   &'a HashMap<String, WasiValue>,
+    pub(crate) results:  &'a HashMap<String, WasiValue>,
+}
+
+impl Scope<'_> {
+    /// Type of the value held for `param`, which for a resource is its state.
+    fn param_type(&self, param: &str) -> Option<WasiType> {
+        match &self.function?.params.iter().find(|p| p.name == param)?.tref {
+            | spec::TypeRef::Named(name) => self.spec?.get_wasi_type(name),
+            | spec::TypeRef::Anonymous(wasi_type) => Some(wasi_type.clone()),
+        }
+    }
+}
+
+/// A resource state member that an output contract pins to a value, like the
+/// `offset` of `fd'` after `fd_seek`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub(crate) struct AttrSet {
+    /// The param holding the resource, without the prime.
+    pub(crate) param:  String,
+    pub(crate) member: String,
+    pub(crate) value:  BigInt,
+}
+
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub(crate) enum Term {
     Foldl(Box<Foldl>),
@@ -30,9 +63,14 @@ pub(crate) enum Term {
     IntConst(BigInt),
     IntAdd(Box<IntAdd>),
     IntGt(Box<IntGt>),
+    IntGe(Box<IntGe>),
+    IntLt(Box<IntLt>),
     IntLe(Box<IntLe>),
+    IntEq(Box<IntEq>),
     StrAt(Box<BinaryTerm>),
     U64Const(Box<UnaryTerm>),
+    /// A constant declared in the witx, by name.
+    WitxConst(String),
     ValueEq(Box<ValueEq>),
     VariantConst(Box<VariantConst>),
 
@@ -42,6 +80,204 @@ pub(crate) enum Term {
     NoNonExistentDirBacktrack(Box<NoNonExistentDirBacktrack>),
 }
 
+impl Term {
+    /// Checks the term against concrete param values of `function`. Terms that
+    /// can't be decided from `params` alone, like those on resources or the
+    /// filesystem, are taken to hold.
+    pub(crate) fn eval(&self, spec: &Spec, function: &Function, params: &HashMap<String, WasiValue>) -> bool {
+        self.eval_bool(Scope {
+            spec: Some(spec),
+            function: Some(function),
+            params,
+            results: &HashMap::new(),
+        })
+        .unwrap_or(true)
+    }
+
+    /// Names of the witx constants the term refers to, at any depth.
+    pub(crate) fn witx_consts(&self) -> Vec<&str> {
+        match self {
+            | Term::WitxConst(name) => vec![name],
+            | term => term.subterms().into_iter().flat_map(Term::witx_consts).collect(),
+        }
+    }
+
+    /// The terms directly under this one.
+    fn subterms(&self) -> Vec<&Term> {
+        match self {
+            | Term::Foldl(t) => vec![&t.target, &t.acc, &t.func],
+            | Term::Lambda(t) => vec![&t.body],
+            | Term::Map(t) => vec![&t.target, &t.func],
+            | Term::Not(t) => vec![&t.term],
+            | Term::And(And { clauses }) | Term::Or(Or { clauses }) => clauses.iter().collect(),
+            | Term::RecordField(t) => vec![&t.target],
+            | Term::FlagsGet(t) => vec![&t.target],
+            | Term::ListLen(t) => vec![&t.op],
+            | Term::IntWrap(t) => vec![&t.op],
+            | Term::IntAdd(t) => vec![&t.lhs, &t.rhs],
+            | Term::IntGt(t) => vec![&t.lhs, &t.rhs],
+            | Term::IntGe(t) => vec![&t.lhs, &t.rhs],
+            | Term::IntLt(t) => vec![&t.lhs, &t.rhs],
+            | Term::IntLe(t) => vec![&t.lhs, &t.rhs],
+            | Term::IntEq(t) => vec![&t.lhs, &t.rhs],
+            | Term::StrAt(t) => vec![&t.lhs, &t.rhs],
+            | Term::U64Const(t) => vec![&t.term],
+            | Term::ValueEq(t) => vec![&t.lhs, &t.rhs],
+            | Term::VariantConst(t) => t.payload.iter().collect(),
+            | Term::FsFileSizeGet(t) => vec![&t.path],
+            | Term::Binding(_)
+            | Term::True
+            | Term::String(_)
+            | Term::Param(_)
+            | Term::Result(_)
+            | Term::ResourceId(_)
+            | Term::IntConst(_)
+            | Term::WitxConst(_)
+            | Term::FsFileTypeGet(_)
+            | Term::FsFileTypeGetl(_)
+            | Term::NoNonExistentDirBacktrack(_) => vec![],
+        }
+    }
+
+    /// The state updates an output contract makes for a call with the values
+    /// in `scope`, where resource params hold their state before the call.
+    /// Of `@or` clauses, the first whose conditions hold applies. Returns
+    /// `None` if no clause applies.
+    pub(crate) fn effects(&self, scope: Scope) -> Option<Vec<AttrSet>> {
+        match self {
+            | Term::And(and) => {
+                let mut effects = Vec::new();
+
+                for clause in &and.clauses {
+                    effects.extend(clause.effects(scope)?);
+                }
+
+                Some(effects)
+            },
+            | Term::Or(or) => or.clauses.iter().find_map(|clause| clause.effects(scope)),
+            | Term::ValueEq(t) => match (t.lhs.primed_field(), t.rhs.primed_field()) {
+                | (Some((param, member)), _) => Some(t.rhs.attr_set(param, member, scope).into_iter().collect()),
+                | (_, Some((param, member))) => Some(t.lhs.attr_set(param, member, scope).into_iter().collect()),
+                | (None, None) => (self.eval_bool(scope) != Some(false)).then(Vec::new),
+            },
+            | _ => (self.eval_bool(scope) != Some(false)).then(Vec::new),
+        }
+    }
+
+    /// The param and member of a term like `(@record.field (param $fd') $offset)`.
+    fn primed_field(&self) -> Option<(&str, &str)> {
+        match self {
+            | Term::IntWrap(t) => t.op.primed_field(),
+            | Term::U64Const(t) => t.term.primed_field(),
+            | Term::RecordField(field) => match &field.target {
+                | Term::Param(param) => Some((param.name.strip_suffix('\'')?, &field.member)),
+                | _ => None,
+            },
+            | _ => None,
+        }
+    }
+
+    /// Sets `param.member` to this term's value. Only integer members are
+    /// tracked, since the rest of the frame conditions keep values as they are.
+    fn attr_set(&self, param: &str, member: &str, scope: Scope) -> Option<AttrSet> {
+        Some(AttrSet {
+            param:  param.to_string(),
+            member: member.to_string(),
+            value:  self.eval_int(scope)?,
+        })
+    }
+
+    fn eval_bool(&self, scope: Scope) -> Option<bool> {
+        match self {
+            | Term::True => Some(true),
+            | Term::Not(not) => not.term.eval_bool(scope).map(|b| !b),
+            | Term::And(and) => {
+                let values = and.clauses.iter().map(|clause| clause.eval_bool(scope)).collect_vec();
+
+                if values.contains(&Some(false)) {
+                    Some(false)
+                } else {
+                    values.into_iter().collect::<Option<Vec<_>>>().map(|_| true)
+                }
+            },
+            | Term::Or(or) => {
+                let values = or.clauses.iter().map(|clause| clause.eval_bool(scope)).collect_vec();
+
+                if values.contains(&Some(true)) {
+                    Some(true)
+                } else {
+                    values.into_iter().collect::<Option<Vec<_>>>().map(|_| false)
+                }
+            },
+            | Term::IntGt(t) => Some(t.lhs.eval_int(scope)? > t.rhs.eval_int(scope)?),
+            | Term::IntGe(t) => Some(t.lhs.eval_int(scope)? >= t.rhs.eval_int(scope)?),
+            | Term::IntLt(t) => Some(t.lhs.eval_int(scope)? < t.rhs.eval_int(scope)?),
+            | Term::IntLe(t) => Some(t.lhs.eval_int(scope)? <= t.rhs.eval_int(scope)?),
+            | Term::IntEq(t) => Some(t.lhs.eval_int(scope)? == t.rhs.eval_int(scope)?),
+            | Term::ValueEq(t) => match (&t.lhs, &t.rhs) {
+                | (Term::Param(lhs), Term::Param(rhs)) => {
+                    Some(scope.params.get(&lhs.name)? == scope.params.get(&rhs.name)?)
+                },
+                | (Term::VariantConst(variant), term) | (term, Term::VariantConst(variant)) => {
+                    match term.eval_value(scope)? {
+                        | WasiValue::Variant(value) => Some(value.case_idx == variant.case_idx(scope.spec?)?),
+                        | _ => None,
+                    }
+                },
+                | _ => Some(t.lhs.eval_int(scope)? == t.rhs.eval_int(scope)?),
+            },
+            | _ => None,
+        }
+    }
+
+    /// The value a param, result or member of one refers to.
+    fn eval_value<'s>(&self, scope: Scope<'s>) -> Option<&'s WasiValue> {
+        match self {
+            | Term::Param(param) => scope.params.get(&param.name),
+            | Term::Result(result) => scope.results.get(&result.name),
+            | Term::RecordField(field) => {
+                let Term::Param(param) = &field.target else {
+                    return None;
+                };
+                let WasiValue::Record(record) = scope.params.get(&param.name)? else {
+                    return None;
+                };
+
+                record.get(scope.param_type(&param.name)?.record()?, &field.member)
+            },
+            | _ => None,
+        }
+    }
+
+    fn eval_int(&self, scope: Scope) -> Option<BigInt> {
+        match self {
+            | Term::IntConst(i) => Some(i.clone()),
+            | Term::IntWrap(t) => t.op.eval_int(scope),
+            | Term::U64Const(t) => t.term.eval_int(scope),
+            | Term::WitxConst(name) => Some(scope.spec?.get_constant(name)?.value.into()),
+            | Term::IntAdd(t) => Some(t.lhs.eval_int(scope)? + t.rhs.eval_int(scope)?),
+            | Term::Param(_) | Term::Result(_) | Term::RecordField(_) => match self.eval_value(scope)? {
+                | &WasiValue::Handle(i) => Some(i.into()),
+                | &WasiValue::S64(i) => Some(i.into()),
+                | &WasiValue::U8(i) => Some(i.into()),
+                | &WasiValue::U16(i) => Some(i.into()),
+                | &WasiValue::U32(i) => Some(i.into()),
+                | &WasiValue::U64(i) => Some(i.into()),
+                | _ => None,
+            },
+            | Term::ListLen(t) => match &t.op {
+                | Term::Param(param) => match scope.params.get(&param.name)? {
+                    | WasiValue::List(list) => Some(list.items.len().into()),
+                    | WasiValue::String(s) => Some(s.len().into()),
+                    | _ => None,
+                },
+                | _ => None,
+            },
+            | _ => None,
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub(crate) struct UnaryTerm {
     pub(crate) term: Term,
@@ -156,12 +392,30 @@ pub(crate) struct IntGt {
     pub(crate) rhs: Term,
 }
 
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub(crate) struct IntGe {
+    pub(crate) lhs: Term,
+    pub(crate) rhs: Term,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub(crate) struct IntLt {
+    pub(crate) lhs: Term,
+    pub(crate) rhs: Term,
+}
+
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub(crate) struct IntLe {
     pub(crate) lhs: Term,
     pub(crate) rhs: Term,
 }
 
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub(crate) struct IntEq {
+    pub(crate) lhs: Term,
+    pub(crate) rhs: Term,
+}
+
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub(crate) struct ValueEq {
     pub(crate) lhs: Term,
@@ -175,6 +429,16 @@ pub(crate) struct VariantConst {
     pub(crate) payload: Option<Term>,
 }
 
+impl VariantConst {
+    fn case_idx(&self, spec: &Spec) -> Option<usize> {
+        spec.get_wasi_type(&self.ty)?
+            .variant()?
+            .cases
+            .iter()
+            .position(|case| case.name == self.case)
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub(crate) struct NoNonExistentDirBacktrack {
     pub(crate) fd_param:   String,
@@ -315,6 +579,20 @@ pub(super) fn to_term(pair: Pair<'_, Rule>) -> Result<Term, eyre::Error> {
 
             Term::IntGt(Box::new(IntGt { lhs, rhs }))
         },
+        | Rule::int_ge => {
+            let mut pairs = pair.into_inner();
+            let lhs = to_term(pairs.next().unwrap())?;
+            let rhs = to_term(pairs.next().unwrap())?;
+
+            Term::IntGe(Box::new(IntGe { lhs, rhs }))
+        },
+        | Rule::int_lt => {
+            let mut pairs = pair.into_inner();
+            let lhs = to_term(pairs.next().unwrap())?;
+            let rhs = to_term(pairs.next().unwrap())?;
+
+            Term::IntLt(Box::new(IntLt { lhs, rhs }))
+        },
         | Rule::int_le => {
             let mut pairs = pair.into_inner();
             let lhs = to_term(pairs.next().unwrap())?;
@@ -322,6 +600,13 @@ pub(super) fn to_term(pair: Pair<'_, Rule>) -> Result<Term, eyre::Error> {
 
             Term::IntLe(Box::new(IntLe { lhs, rhs }))
         },
+        | Rule::int_eq => {
+            let mut pairs = pair.into_inner();
+            let lhs = to_term(pairs.next().unwrap())?;
+            let rhs = to_term(pairs.next().unwrap())?;
+
+            Term::IntEq(Box::new(IntEq { lhs, rhs }))
+        },
         | Rule::list_len => Term::ListLen(Box::new(ListLen {
             op: to_term(pair.into_inner().next().unwrap())?,
         })),
@@ -343,6 +628,15 @@ pub(super) fn to_term(pair: Pair<'_, Rule>) -> Result<Term, eyre::Error> {
         | Rule::u64_const => Term::U64Const(Box::new(UnaryTerm {
             term: to_term(pair.into_inner().next().unwrap())?,
         })),
+        | Rule::witx_const => Term::WitxConst(
+            pair.into_inner()
+                .next()
+                .unwrap()
+                .as_str()
+                .strip_prefix('$')
+                .unwrap()
+                .to_string(),
+        ),
         | Rule::string => Term::String(pair.into_inner().as_str().to_string()),
         | Rule::value_eq => {
             let mut pairs = pair.into_inner();
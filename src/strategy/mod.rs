@@ -1,6 +1,14 @@
+mod resume;
+mod reuse;
 mod stateful;
 mod stateless;
 
+use std::collections::{BTreeSet, HashMap};
+
+use arbitrary::Unstructured;
+use eyre::eyre as err;
+pub use resume::ResumeStrategy;
+pub use reuse::ReuseStrategy;
 pub use stateful::StatefulStrategy;
 pub use stateless::StatelessStrategy;
 
@@ -31,3 +39,89 @@ pub trait CallStrategy {
         result_values: Option<&[WasiValue]>,
     ) -> Result<(), eyre::Error>;
 }
+
+/// Restricts the functions a strategy may select.
+#[derive(Clone, Default, Debug)]
+pub struct FunctionFilter {
+    only:    Option<BTreeSet<String>>,
+    exclude: BTreeSet<String>,
+}
+
+impl FunctionFilter {
+    /// An empty `only` allows every function that isn't excluded.
+    pub fn new(only: impl IntoIterator<Item = String>, exclude: impl IntoIterator<Item = String>) -> Self {
+        let only: BTreeSet<_> = only.into_iter().collect();
+
+        Self {
+            only:    (!only.is_empty()).then_some(only),
+            exclude: exclude.into_iter().collect(),
+        }
+    }
+
+    /// Errors if the filter names a function `spec` doesn't have.
+    pub fn validate(&self, spec: &Spec) -> Result<(), eyre::Error> {
+        for name in self.only.iter().flatten().chain(&self.exclude) {
+            if spec.get_function(name).is_none() {
+                return Err(err!("unknown function {name}"));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn allows(&self, function: &Function) -> bool {
+        self.only.as_ref().is_none_or(|only| only.contains(&function.name)) && !self.exclude.contains(&function.name)
+    }
+}
+
+/// Picks one of `candidates` with probability proportional to its weight.
+/// Functions without a weight count as 1.
+fn choose_weighted<'spec>(
+    u: &mut Unstructured,
+    candidates: &[&'spec Function],
+    weights: &HashMap<String, u32>,
+) -> Result<&'spec Function, eyre::Error> {
+    let weight = |function: &Function| u64::from(weights.get(&function.name).copied().unwrap_or(1));
+    let total: u64 = candidates.iter().map(|function| weight(function)).sum();
+
+    if total == 0 {
+        return Err(err!("no function with a non-zero weight to choose"));
+    }
+
+    let mut pick = u.int_in_range(0..=total - 1)?;
+
+    for &function in candidates {
+        match pick.checked_sub(weight(function)) {
+            | Some(rest) => pick = rest,
+            | None => return Ok(function),
+        }
+    }
+
+    unreachable!()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heavy_weight_dominates() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+        let candidates = ["fd_read", "fd_write", "path_open", "fd_seek"]
+            .map(|name| spec.get_function(name).unwrap())
+            .to_vec();
+        let weights = HashMap::from([("path_open".to_string(), 1000), ("fd_seek".to_string(), 0)]);
+        let data = (0..=255u8).cycle().take(4096).collect::<Vec<_>>();
+        let mut u = Unstructured::new(&data);
+        let mut picks = HashMap::<&str, usize>::new();
+
+        for _ in 0..500 {
+            *picks
+                .entry(&choose_weighted(&mut u, &candidates, &weights).unwrap().name)
+                .or_default() += 1;
+        }
+
+        assert!(picks["path_open"] > 450, "{picks:?}");
+        assert!(!picks.contains_key("fd_seek"), "{picks:?}");
+    }
+}
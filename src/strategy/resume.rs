@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+
+use eyre::eyre as err;
+
+use super::CallStrategy;
+use crate::{
+    resource::HighLevelValue,
+    spec::{Function, Spec, WasiValue},
+    Call,
+    Environment,
+    ResourceIdx,
+};
+
+/// Makes the calls of a recorded run again, then hands over to `inner`.
+///
+/// Resources are referred to by the indices they were recorded with, which
+/// line up as long as the calls are made in the same order on fresh runtimes.
+pub struct ResumeStrategy<'a> {
+    calls: VecDeque<Call>,
+    inner: Box<dyn CallStrategy + 'a>,
+}
+
+impl<'a> ResumeStrategy<'a> {
+    pub fn new(calls: Vec<Call>, inner: Box<dyn CallStrategy + 'a>) -> Self {
+        Self {
+            calls: calls.into(),
+            inner,
+        }
+    }
+}
+
+impl CallStrategy for ResumeStrategy<'_> {
+    fn select_function<'spec>(&mut self, spec: &'spec Spec, env: &Environment) -> Result<&'spec Function, eyre::Error> {
+        match self.calls.front() {
+            | Some(call) => spec
+                .get_function(&call.function)
+                .ok_or_else(|| err!("unknown function {}", call.function)),
+            | None => self.inner.select_function(spec, env),
+        }
+    }
+
+    fn prepare_arguments(
+        &mut self,
+        spec: &Spec,
+        function: &Function,
+        env: &Environment,
+    ) -> Result<Vec<HighLevelValue>, eyre::Error> {
+        match self.calls.pop_front() {
+            | Some(call) => Ok(call
+                .params
+                .into_iter()
+                .map(|param| match param.resource_idx {
+                    | Some(idx) => HighLevelValue::Resource(idx),
+                    | None => HighLevelValue::Concrete(param.value),
+                })
+                .collect()),
+            | None => self.inner.prepare_arguments(spec, function, env),
+        }
+    }
+
+    fn handle_results(
+        &mut self,
+        spec: &Spec,
+        function: &Function,
+        env: &mut Environment,
+        params: Vec<HighLevelValue>,
+        results: Vec<Option<ResourceIdx>>,
+        result_values: Option<&[WasiValue]>,
+    ) -> Result<(), eyre::Error> {
+        self.inner
+            .handle_results(spec, function, env, params, results, result_values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arbitrary::Unstructured;
+
+    use super::*;
+    use crate::{spec::VariantValue, StatelessStrategy};
+
+    #[test]
+    fn resumed_run_continues_after_recorded_calls() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+        let env = Environment::new();
+        let recorded = vec![
+            Call::succeeded("args_sizes_get", vec![]),
+            Call::succeeded(
+                "clock_res_get",
+                vec![WasiValue::Variant(Box::new(VariantValue {
+                    case_idx: 1,
+                    payload:  None,
+                }))],
+            ),
+            Call::succeeded("args_sizes_get", vec![]),
+        ];
+        let data = (0..=255u8).cycle().take(4096).collect::<Vec<_>>();
+        let mut u = Unstructured::new(&data);
+        let mut strategy = ResumeStrategy::new(recorded.clone(), Box::new(StatelessStrategy::new(&mut u)));
+
+        for call in &recorded {
+            let function = strategy.select_function(&spec, &env).unwrap();
+            let params = strategy.prepare_arguments(&spec, function, &env).unwrap();
+
+            assert_eq!(function.name, call.function);
+            assert_eq!(
+                params,
+                call.params
+                    .iter()
+                    .map(|param| HighLevelValue::Concrete(param.value.clone()))
+                    .collect::<Vec<_>>()
+            );
+        }
+
+        let function = strategy.select_function(&spec, &env).unwrap();
+
+        strategy.prepare_arguments(&spec, function, &env).unwrap();
+        assert!(strategy.calls.is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use arbitrary::Unstructured;
+use eyre::Context;
+use itertools::Itertools;
+
+use super::{
+    choose_weighted,
+    stateless::{callable_functions, generate_satisfying},
+    CallStrategy,
+    FunctionFilter,
+};
+use crate::{
+    resource::HighLevelValue,
+    spec::{Function, GenConfig, Spec, WasiValue},
+    Environment,
+    ResourceIdx,
+};
+
+/// Odds of filling a path param with a path an earlier call used, if any,
+/// instead of a fresh one.
+const REUSE_RATIO: (u8, u8) = (7, 8);
+
+/// Like `StatelessStrategy`, but mostly passes paths that earlier successful
+/// calls used, so that calls act on the files earlier ones created. Stateful
+/// params are always filled with live resources.
+pub struct ReuseStrategy<'u, 'data> {
+    u:          &'u mut Unstructured<'data>,
+    gen_config: GenConfig,
+    weights:    HashMap<String, u32>,
+    filter:     FunctionFilter,
+    paths:      Vec<Vec<u8>>,
+}
+
+impl<'u, 'data> ReuseStrategy<'u, 'data> {
+    pub fn new(u: &'u mut Unstructured<'data>) -> Self {
+        Self {
+            u,
+            gen_config: GenConfig::default(),
+            weights: HashMap::new(),
+            filter: FunctionFilter::default(),
+            paths: Vec::new(),
+        }
+    }
+
+    pub fn with_gen_config(mut self, gen_config: GenConfig) -> Self {
+        self.gen_config = gen_config;
+        self
+    }
+
+    /// Relative chance of picking each function by name. Unlisted functions
+    /// weigh 1.
+    pub fn with_weights(mut self, weights: HashMap<String, u32>) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    pub fn with_function_filter(mut self, filter: FunctionFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Generates one set of arguments, ignoring the input contract.
+    fn generate_arguments(
+        &mut self,
+        spec: &Spec,
+        function: &Function,
+        env: &Environment,
+    ) -> Result<Vec<HighLevelValue>, eyre::Error> {
+        let mut params = Vec::with_capacity(function.params.len());
+
+        for param in function.params.iter() {
+            let tdef = param.tref.resolve(spec);
+
+            if tdef.state.is_some() {
+                let resources = env.resources_of_type(&tdef.name).map(|(idx, _)| idx).collect_vec();
+                let resource_idx = *self.u.choose(&resources).wrap_err("failed to choose a resource")?;
+
+                params.push(HighLevelValue::Resource(resource_idx));
+                continue;
+            }
+
+            if tdef.name == "path" && !self.paths.is_empty() && self.u.ratio(REUSE_RATIO.0, REUSE_RATIO.1)? {
+                let path = self.u.choose(&self.paths)?.clone();
+
+                params.push(HighLevelValue::Concrete(WasiValue::String(path)));
+                continue;
+            }
+
+            params.push(HighLevelValue::Concrete(tdef.wasi.arbitrary_value(
+                spec,
+                &self.gen_config,
+                self.u,
+            )?));
+        }
+
+        Ok(params)
+    }
+}
+
+impl CallStrategy for ReuseStrategy<'_, '_> {
+    fn select_function<'spec>(&mut self, spec: &'spec Spec, env: &Environment) -> Result<&'spec Function, eyre::Error> {
+        let pool = callable_functions(spec, env)
+            .into_iter()
+            .filter(|function| self.filter.allows(function))
+            .collect_vec();
+
+        choose_weighted(self.u, &pool, &self.weights).wrap_err("failed to choose a function")
+    }
+
+    fn prepare_arguments(
+        &mut self,
+        spec: &Spec,
+        function: &Function,
+        env: &Environment,
+    ) -> Result<Vec<HighLevelValue>, eyre::Error> {
+        generate_satisfying(spec, function, || self.generate_arguments(spec, function, env))
+    }
+
+    fn handle_results(
+        &mut self,
+        spec: &Spec,
+        function: &Function,
+        _env: &mut Environment,
+        params: Vec<HighLevelValue>,
+        _results: Vec<Option<ResourceIdx>>,
+        _result_values: Option<&[WasiValue]>,
+    ) -> Result<(), eyre::Error> {
+        for (param, value) in function.params.iter().zip(params) {
+            if param.tref.resolve(spec).name != "path" {
+                continue;
+            }
+
+            if let HighLevelValue::Concrete(WasiValue::String(path)) = value {
+                if !self.paths.contains(&path) {
+                    self.paths.push(path);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::Resource;
+
+    #[test]
+    fn existing_resources_and_paths_are_reused() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+        let fd_close = spec.get_function("fd_close").unwrap();
+        let path_create_directory = spec.get_function("path_create_directory").unwrap();
+        let mut env = Environment::new();
+        let fd = env.new_resource(
+            "fd".to_string(),
+            Resource {
+                state:  WasiValue::U64(0),
+                origin: None,
+            },
+        );
+        let data = (0..=255u8).cycle().take(1 << 16).collect_vec();
+        let mut u = Unstructured::new(&data);
+        let mut strategy = ReuseStrategy::new(&mut u);
+        let dir = HighLevelValue::Concrete(WasiValue::String(b"dir".to_vec()));
+
+        assert_eq!(
+            strategy.prepare_arguments(&spec, fd_close, &env).unwrap(),
+            [HighLevelValue::Resource(fd)]
+        );
+
+        strategy
+            .handle_results(
+                &spec,
+                path_create_directory,
+                &mut env,
+                vec![HighLevelValue::Resource(fd), dir.clone()],
+                Vec::new(),
+                None,
+            )
+            .unwrap();
+
+        let reused = (0..64)
+            .filter(|_| strategy.prepare_arguments(&spec, path_create_directory, &env).unwrap()[1] == dir)
+            .count();
+
+        assert!(reused >= 32, "{reused}");
+    }
+}
@@ -16,7 +16,7 @@ use z3::{
     FuncDecl,
 };
 
-use super::CallStrategy;
+use super::{choose_weighted, CallStrategy, FunctionFilter};
 use crate::{
     resource::HighLevelValue,
     spec::{
@@ -63,28 +63,9 @@ impl State {
     fn push_resource(&mut self, idx: ResourceIdx, tdef: &TypeDef, value: WasiValue) {
         if tdef.name == "fd" {
             let node_idx = self.fds_graph.add_node(idx);
-            let (_parent_member, parent_value) = tdef
-                .state
-                .as_ref()
-                .unwrap()
-                .record()
-                .unwrap()
-                .members
-                .iter()
-                .zip(value.record().unwrap().members.iter())
-                .find(|(member, _val)| member.name == "parent")
-                .unwrap();
-            let (_path_member, path_value) = tdef
-                .state
-                .as_ref()
-                .unwrap()
-                .record()
-                .unwrap()
-                .members
-                .iter()
-                .zip(value.record().unwrap().members.iter())
-                .find(|(member, _val)| member.name == "path")
-                .unwrap();
+            let fd_type = tdef.state.as_ref().unwrap().record().unwrap();
+            let parent_value = value.record().unwrap().get(fd_type, "parent").unwrap();
+            let path_value = value.record().unwrap().get(fd_type, "path").unwrap();
             let path = String::from_utf8(path_value.string().unwrap().to_vec()).unwrap();
 
             self.fds_idxs.insert(idx, node_idx);
@@ -310,12 +291,31 @@ impl State {
                     | Term::ListLen(_t) => todo!(),
                     | Term::IntWrap(t) => scan_primed_in_output_contract(ctx, types, spec, function, &t.op, to_solves),
                     | Term::IntConst(_t) => (),
+                    | Term::WitxConst(_t) => (),
                     | Term::IntAdd(t) => {
                         scan_primed_in_output_contract(ctx, types, spec, function, &t.lhs, to_solves);
                         scan_primed_in_output_contract(ctx, types, spec, function, &t.rhs, to_solves);
                     },
-                    | Term::IntGt(_t) => todo!(),
-                    | Term::IntLe(_t) => todo!(),
+                    | Term::IntGt(t) => {
+                        scan_primed_in_output_contract(ctx, types, spec, function, &t.lhs, to_solves);
+                        scan_primed_in_output_contract(ctx, types, spec, function, &t.rhs, to_solves);
+                    },
+                    | Term::IntGe(t) => {
+                        scan_primed_in_output_contract(ctx, types, spec, function, &t.lhs, to_solves);
+                        scan_primed_in_output_contract(ctx, types, spec, function, &t.rhs, to_solves);
+                    },
+                    | Term::IntLt(t) => {
+                        scan_primed_in_output_contract(ctx, types, spec, function, &t.lhs, to_solves);
+                        scan_primed_in_output_contract(ctx, types, spec, function, &t.rhs, to_solves);
+                    },
+                    | Term::IntLe(t) => {
+                        scan_primed_in_output_contract(ctx, types, spec, function, &t.lhs, to_solves);
+                        scan_primed_in_output_contract(ctx, types, spec, function, &t.rhs, to_solves);
+                    },
+                    | Term::IntEq(t) => {
+                        scan_primed_in_output_contract(ctx, types, spec, function, &t.lhs, to_solves);
+                        scan_primed_in_output_contract(ctx, types, spec, function, &t.rhs, to_solves);
+                    },
                     | Term::U64Const(t) => {
                         scan_primed_in_output_contract(ctx, types, spec, function, &t.term, to_solves)
                     },
@@ -573,6 +573,31 @@ impl State {
             ));
         }
 
+        // Integer params stay within the range of their fixed-width type, so
+        // negated or disjunctive contracts can't solve to values that don't fit.
+        for param in function.params.iter() {
+            let tdef = param.tref.resolve(spec);
+            let (min, max) = match &tdef.wasi {
+                | WasiType::U8 => (Int::from_u64(ctx, 0), Int::from_u64(ctx, u8::MAX.into())),
+                | WasiType::U16 => (Int::from_u64(ctx, 0), Int::from_u64(ctx, u16::MAX.into())),
+                | WasiType::U32 => (Int::from_u64(ctx, 0), Int::from_u64(ctx, u32::MAX.into())),
+                | WasiType::U64 => (Int::from_u64(ctx, 0), Int::from_u64(ctx, u64::MAX)),
+                | WasiType::S64 => (Int::from_i64(ctx, i64::MIN), Int::from_i64(ctx, i64::MAX)),
+                | _ => continue,
+            };
+            let node = match decls.params.get(&param.name) {
+                | Some(ParamDecl::Node(node)) if tdef.state.is_none() => node,
+                | _ => continue,
+            };
+            let value = types.resource_wrappers.get(&tdef.name).unwrap().variants[0].accessors[1].apply(&[node]);
+            let int = types.resources.get(&tdef.name).unwrap().variants[0].accessors[0]
+                .apply(&[&value])
+                .as_int()
+                .unwrap();
+
+            clauses.push(Bool::and(ctx, &[&int.ge(&min), &int.le(&max)]));
+        }
+
         let separator = types.segment.variants.first().unwrap();
         let component = types.segment.variants.get(1).unwrap();
         let paths = decls
@@ -1138,6 +1163,10 @@ impl State {
                 Dynamic::from_ast(&Int::from_big_int(ctx, t)),
                 Type::Wazzi(WazziType::Int),
             ),
+            | Term::WitxConst(name) => (
+                Dynamic::from_ast(&Int::from_u64(ctx, spec.get_constant(name).unwrap().value)),
+                Type::Wazzi(WazziType::Int),
+            ),
             | Term::IntWrap(t) => {
                 let (op, op_type) = self.term_to_z3_ast(
                     ctx, env, eval_ctx, spec, types, decls, decls2, &t.op, function, params, results,
@@ -1184,6 +1213,40 @@ impl State {
                     Type::Wazzi(WazziType::Bool),
                 )
             },
+            | Term::IntGe(t) => {
+                let (lhs, lhs_type) = self.term_to_z3_ast(
+                    ctx, env, eval_ctx, spec, types, decls, decls2, &t.lhs, function, params, results,
+                );
+                let (rhs, rhs_type) = self.term_to_z3_ast(
+                    ctx, env, eval_ctx, spec, types, decls, decls2, &t.rhs, function, params, results,
+                );
+
+                (
+                    Dynamic::from_ast(
+                        &lhs_type
+                            .unwrap_ast_as_int(types, &lhs)
+                            .ge(&rhs_type.unwrap_ast_as_int(types, &rhs)),
+                    ),
+                    Type::Wazzi(WazziType::Bool),
+                )
+            },
+            | Term::IntLt(t) => {
+                let (lhs, lhs_type) = self.term_to_z3_ast(
+                    ctx, env, eval_ctx, spec, types, decls, decls2, &t.lhs, function, params, results,
+                );
+                let (rhs, rhs_type) = self.term_to_z3_ast(
+                    ctx, env, eval_ctx, spec, types, decls, decls2, &t.rhs, function, params, results,
+                );
+
+                (
+                    Dynamic::from_ast(
+                        &lhs_type
+                            .unwrap_ast_as_int(types, &lhs)
+                            .lt(&rhs_type.unwrap_ast_as_int(types, &rhs)),
+                    ),
+                    Type::Wazzi(WazziType::Bool),
+                )
+            },
             | Term::IntLe(t) => {
                 let (lhs, lhs_type) = self.term_to_z3_ast(
                     ctx, env, eval_ctx, spec, types, decls, decls2, &t.lhs, function, params, results,
@@ -1201,6 +1264,23 @@ impl State {
                     Type::Wazzi(WazziType::Bool),
                 )
             },
+            | Term::IntEq(t) => {
+                let (lhs, lhs_type) = self.term_to_z3_ast(
+                    ctx, env, eval_ctx, spec, types, decls, decls2, &t.lhs, function, params, results,
+                );
+                let (rhs, rhs_type) = self.term_to_z3_ast(
+                    ctx, env, eval_ctx, spec, types, decls, decls2, &t.rhs, function, params, results,
+                );
+
+                (
+                    Dynamic::from_ast(
+                        &lhs_type
+                            .unwrap_ast_as_int(types, &lhs)
+                            ._eq(&rhs_type.unwrap_ast_as_int(types, &rhs)),
+                    ),
+                    Type::Wazzi(WazziType::Bool),
+                )
+            },
             | Term::ListLen(t) => {
                 let (op, op_type) = self.term_to_z3_ast(
                     ctx, env, eval_ctx, spec, types, decls, decls2, &t.op, function, params, results,
@@ -1328,18 +1408,6 @@ impl State {
                 let mut fd_resource = env.resources.get(fd_resource_idx).unwrap();
                 let fd_tdef = spec.types.get_by_key("fd").unwrap();
                 let fd_type = fd_tdef.state.as_ref().unwrap().record().unwrap();
-                let (parent_member_idx, _parent_member_type) = fd_type
-                    .members
-                    .iter()
-                    .enumerate()
-                    .find(|(_i, member)| member.name == "parent")
-                    .unwrap();
-                let (path_member_idx, _path_member_type) = fd_type
-                    .members
-                    .iter()
-                    .enumerate()
-                    .find(|(_i, member)| member.name == "path")
-                    .unwrap();
                 let mut curr_fd_resource_idx = fd_resource_idx;
                 let mut paths = if path.is_empty() { vec![] } else { vec![path.clone()] };
 
@@ -1349,8 +1417,7 @@ impl State {
                             .state
                             .record()
                             .unwrap()
-                            .members
-                            .get(parent_member_idx)
+                            .get(fd_type, "parent")
                             .unwrap()
                             .u64()
                             .unwrap() as usize,
@@ -1366,8 +1433,7 @@ impl State {
                                 .state
                                 .record()
                                 .unwrap()
-                                .members
-                                .get(path_member_idx)
+                                .get(fd_type, "path")
                                 .unwrap()
                                 .string()
                                 .unwrap()
@@ -1471,18 +1537,6 @@ impl State {
                 let mut fd_resource = env.resources.get(fd_resource_idx).unwrap();
                 let fd_tdef = spec.types.get_by_key("fd").unwrap();
                 let fd_type = fd_tdef.state.as_ref().unwrap().record().unwrap();
-                let (parent_member_idx, _parent_member_type) = fd_type
-                    .members
-                    .iter()
-                    .enumerate()
-                    .find(|(_i, member)| member.name == "parent")
-                    .unwrap();
-                let (path_member_idx, _path_member_type) = fd_type
-                    .members
-                    .iter()
-                    .enumerate()
-                    .find(|(_i, member)| member.name == "path")
-                    .unwrap();
                 let mut curr_fd_resource_idx = fd_resource_idx;
                 let mut paths = vec![String::from_utf8(path_value.string().unwrap().to_vec()).unwrap()];
 
@@ -1492,8 +1546,7 @@ impl State {
                             .state
                             .record()
                             .unwrap()
-                            .members
-                            .get(parent_member_idx)
+                            .get(fd_type, "parent")
                             .unwrap()
                             .u64()
                             .unwrap() as usize,
@@ -1509,8 +1562,7 @@ impl State {
                                 .state
                                 .record()
                                 .unwrap()
-                                .members
-                                .get(path_member_idx)
+                                .get(fd_type, "path")
                                 .unwrap()
                                 .string()
                                 .unwrap()
@@ -1719,18 +1771,6 @@ impl State {
                 let mut fd_resource = env.resources.get(fd_resource_idx).unwrap();
                 let fd_tdef = spec.types.get_by_key("fd").unwrap();
                 let fd_type = fd_tdef.state.as_ref().unwrap().record().unwrap();
-                let (parent_member_idx, _parent_member_type) = fd_type
-                    .members
-                    .iter()
-                    .enumerate()
-                    .find(|(_i, member)| member.name == "parent")
-                    .unwrap();
-                let (path_member_idx, _path_member_type) = fd_type
-                    .members
-                    .iter()
-                    .enumerate()
-                    .find(|(_i, member)| member.name == "path")
-                    .unwrap();
                 let mut curr_fd_resource_idx = fd_resource_idx;
                 let mut paths = vec![String::from_utf8(path_value.string().unwrap().to_vec()).unwrap()];
 
@@ -1740,8 +1780,7 @@ impl State {
                             .state
                             .record()
                             .unwrap()
-                            .members
-                            .get(parent_member_idx)
+                            .get(fd_type, "parent")
                             .unwrap()
                             .u64()
                             .unwrap() as usize,
@@ -1757,8 +1796,7 @@ impl State {
                                 .state
                                 .record()
                                 .unwrap()
-                                .members
-                                .get(path_member_idx)
+                                .get(fd_type, "path")
                                 .unwrap()
                                 .string()
                                 .unwrap()
@@ -3084,6 +3122,8 @@ pub struct StatefulStrategy<'u, 'data, 'ctx> {
     ctx:      &'ctx z3::Context,
     u:        &'u mut Unstructured<'data>,
     preopens: BTreeMap<ResourceIdx, PathBuf>,
+    weights:  HashMap<String, u32>,
+    filter:   FunctionFilter,
 }
 
 impl<'u, 'data, 'ctx> StatefulStrategy<'u, 'data, 'ctx> {
@@ -3092,7 +3132,25 @@ impl<'u, 'data, 'ctx> StatefulStrategy<'u, 'data, 'ctx> {
         ctx: &'ctx z3::Context,
         preopens: BTreeMap<ResourceIdx, PathBuf>,
     ) -> Self {
-        Self { ctx, u, preopens }
+        Self {
+            ctx,
+            u,
+            preopens,
+            weights: HashMap::new(),
+            filter: FunctionFilter::default(),
+        }
+    }
+
+    /// Relative chance of picking each function by name. Unlisted functions
+    /// weigh 1.
+    pub fn with_weights(mut self, weights: HashMap<String, u32>) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    pub fn with_function_filter(mut self, filter: FunctionFilter) -> Self {
+        self.filter = filter;
+        self
     }
 }
 
@@ -3102,6 +3160,10 @@ impl<'u, 'data, 'ctx> CallStrategy for StatefulStrategy<'u, 'data, 'ctx> {
         let mut candidates = Vec::new();
 
         for (_name, function) in &interface.functions {
+            if !self.filter.allows(function) {
+                continue;
+            }
+
             let mut state = State::new();
 
             for (&idx, path) in &self.preopens {
@@ -3150,7 +3212,7 @@ impl<'u, 'data, 'ctx> CallStrategy for StatefulStrategy<'u, 'data, 'ctx> {
             };
         }
 
-        let function = *self.u.choose(&candidates).wrap_err("failed to choose a function")?;
+        let function = choose_weighted(self.u, &candidates, &self.weights).wrap_err("failed to choose a function")?;
 
         Ok(function)
     }
@@ -1,65 +1,53 @@
+use std::collections::HashMap;
+
 use arbitrary::Unstructured;
 use eyre::Context;
 use itertools::Itertools;
 
-use super::CallStrategy;
+use super::{choose_weighted, CallStrategy, FunctionFilter};
 use crate::{
     resource::HighLevelValue,
-    spec::{Function, Spec, WasiValue},
+    spec::{Function, GenConfig, Spec, WasiValue},
     Environment,
     ResourceIdx,
 };
 
 pub struct StatelessStrategy<'u, 'data> {
-    u: &'u mut Unstructured<'data>,
+    u:          &'u mut Unstructured<'data>,
+    gen_config: GenConfig,
+    weights:    HashMap<String, u32>,
+    filter:     FunctionFilter,
 }
 
 impl<'u, 'data> StatelessStrategy<'u, 'data> {
     pub fn new(u: &'u mut Unstructured<'data>) -> Self {
-        Self { u }
+        Self {
+            u,
+            gen_config: GenConfig::default(),
+            weights: HashMap::new(),
+            filter: FunctionFilter::default(),
+        }
     }
-}
 
-impl CallStrategy for StatelessStrategy<'_, '_> {
-    fn select_function<'spec>(&mut self, spec: &'spec Spec, env: &Environment) -> Result<&'spec Function, eyre::Error> {
-        let mut pool: Vec<&Function> = Default::default();
-
-        for (_interface_name, interface) in spec.interfaces.iter() {
-            for (_function_name, function) in &interface.functions {
-                let mut is_candidate = true;
-
-                for param in function.params.iter() {
-                    let tdef = param.tref.resolve(spec);
-
-                    if tdef.state.is_none() {
-                        continue;
-                    }
-
-                    let resources = match env.resources_by_types.get(&param.name) {
-                        | None => {
-                            is_candidate = false;
-                            break;
-                        },
-                        | Some(resources) => resources,
-                    };
-
-                    if resources.is_empty() {
-                        is_candidate = false;
-                        break;
-                    }
-                }
-
-                if is_candidate {
-                    pool.push(function);
-                }
-            }
-        }
+    pub fn with_gen_config(mut self, gen_config: GenConfig) -> Self {
+        self.gen_config = gen_config;
+        self
+    }
 
-        Ok(self.u.choose(pool.as_slice()).wrap_err("failed to choose a function")?)
+    /// Relative chance of picking each function by name. Unlisted functions
+    /// weigh 1.
+    pub fn with_weights(mut self, weights: HashMap<String, u32>) -> Self {
+        self.weights = weights;
+        self
     }
 
-    #[tracing::instrument(skip(self, spec))]
-    fn prepare_arguments(
+    pub fn with_function_filter(mut self, filter: FunctionFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Generates one set of arguments, ignoring the input contract.
+    fn generate_arguments(
         &mut self,
         spec: &Spec,
         function: &Function,
@@ -72,7 +60,11 @@ impl CallStrategy for StatelessStrategy<'_, '_> {
 
             match &tdef.state {
                 | None => {
-                    params.push(HighLevelValue::Concrete(tdef.wasi.arbitrary_value(spec, self.u)?));
+                    params.push(HighLevelValue::Concrete(tdef.wasi.arbitrary_value(
+                        spec,
+                        &self.gen_config,
+                        self.u,
+                    )?));
                 },
                 | Some(_state_type) => {
                     let resources = env
@@ -91,6 +83,83 @@ impl CallStrategy for StatelessStrategy<'_, '_> {
 
         Ok(params)
     }
+}
+
+/// How many argument sets to generate before giving up on satisfying a
+/// function's input contract.
+const MAX_CONTRACT_ATTEMPTS: usize = 16;
+
+/// Functions whose stateful parameters can all be filled from live resources
+/// in `env`.
+pub(super) fn callable_functions<'spec>(spec: &'spec Spec, env: &Environment) -> Vec<&'spec Function> {
+    spec.interfaces
+        .iter()
+        .flat_map(|(_interface_name, interface)| interface.functions.values())
+        .filter(|function| {
+            function.params.iter().all(|param| {
+                let tdef = param.tref.resolve(spec);
+
+                tdef.state.is_none()
+                    || env
+                        .resources_by_types
+                        .get(&tdef.name)
+                        .is_some_and(|resources| !resources.is_empty())
+            })
+        })
+        .collect()
+}
+
+/// Calls `generate` until the arguments it returns satisfy `function`'s input
+/// contract, settling for the last ones after `MAX_CONTRACT_ATTEMPTS`.
+pub(super) fn generate_satisfying(
+    spec: &Spec,
+    function: &Function,
+    mut generate: impl FnMut() -> Result<Vec<HighLevelValue>, eyre::Error>,
+) -> Result<Vec<HighLevelValue>, eyre::Error> {
+    let mut params = generate()?;
+
+    if let Some(contract) = &function.input_contract {
+        for _ in 1..MAX_CONTRACT_ATTEMPTS {
+            let values = function
+                .params
+                .iter()
+                .zip(&params)
+                .filter_map(|(param, value)| match value {
+                    | HighLevelValue::Concrete(value) => Some((param.name.clone(), value.clone())),
+                    | HighLevelValue::Resource(_) => None,
+                })
+                .collect::<HashMap<_, _>>();
+
+            if contract.eval(spec, function, &values) {
+                break;
+            }
+
+            params = generate()?;
+        }
+    }
+
+    Ok(params)
+}
+
+impl CallStrategy for StatelessStrategy<'_, '_> {
+    fn select_function<'spec>(&mut self, spec: &'spec Spec, env: &Environment) -> Result<&'spec Function, eyre::Error> {
+        let pool = callable_functions(spec, env)
+            .into_iter()
+            .filter(|function| self.filter.allows(function))
+            .collect_vec();
+
+        choose_weighted(self.u, &pool, &self.weights).wrap_err("failed to choose a function")
+    }
+
+    #[tracing::instrument(skip(self, spec))]
+    fn prepare_arguments(
+        &mut self,
+        spec: &Spec,
+        function: &Function,
+        env: &Environment,
+    ) -> Result<Vec<HighLevelValue>, eyre::Error> {
+        generate_satisfying(spec, function, || self.generate_arguments(spec, function, env))
+    }
 
     fn handle_results(
         &mut self,
@@ -104,3 +173,89 @@ impl CallStrategy for StatelessStrategy<'_, '_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::Resource;
+
+    #[test]
+    fn fd_functions_wait_for_an_fd() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+        let takes_fd = |function: &Function| {
+            function
+                .params
+                .iter()
+                .any(|param| param.tref.resolve(&spec).state.is_some())
+        };
+        let mut env = Environment::new();
+        let before = callable_functions(&spec, &env);
+
+        assert!(!before.is_empty());
+        assert!(before.iter().all(|function| !takes_fd(function)));
+
+        env.new_resource(
+            "fd".to_string(),
+            Resource {
+                state:  WasiValue::U64(0),
+                origin: None,
+            },
+        );
+
+        let after = callable_functions(&spec, &env);
+
+        assert!(after.iter().any(|function| function.name == "fd_write"));
+        assert!(after.len() > before.len());
+    }
+
+    #[test]
+    fn allowlist_restricts_selection() {
+        let spec = Spec::preview1(include_str!("../../preview1.witx")).unwrap();
+        let mut env = Environment::new();
+
+        env.new_resource(
+            "fd".to_string(),
+            Resource {
+                state:  WasiValue::U64(0),
+                origin: None,
+            },
+        );
+
+        let data = (0..=255u8).cycle().take(4096).collect_vec();
+        let mut u = Unstructured::new(&data);
+        let mut strategy = StatelessStrategy::new(&mut u)
+            .with_function_filter(FunctionFilter::new(["fd_write".to_string()], Vec::new()));
+
+        for _ in 0..64 {
+            assert_eq!(strategy.select_function(&spec, &env).unwrap().name, "fd_write");
+        }
+    }
+
+    #[test]
+    fn input_contract_filters_arguments() {
+        let spec = Spec::preview1(
+            "
+(typename $size u32)
+(module $wasi_snapshot_preview1
+  (@interface func (export \"f\")
+    (param $offset $size)
+    (@input (@int.gt (param $offset) 0))
+  )
+)
+",
+        )
+        .unwrap();
+        let function = spec.get_function("f").unwrap();
+        let env = Environment::new();
+        // Every other u32 drawn from this data is zero.
+        let data = (0..4096).map(|i| if i % 8 < 4 { 0 } else { i as u8 | 1 }).collect_vec();
+        let mut u = Unstructured::new(&data);
+        let mut strategy = StatelessStrategy::new(&mut u);
+
+        for _ in 0..64 {
+            let params = strategy.prepare_arguments(&spec, function, &env).unwrap();
+
+            assert_ne!(params, vec![HighLevelValue::Concrete(WasiValue::U32(0))]);
+        }
+    }
+}
@@ -1,40 +1,269 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet},
     fs,
-    io::{self, BufWriter},
+    io::{self, BufWriter, Write as _},
     path::{Path, PathBuf},
+    str::FromStr,
     sync::{
         atomic::{self, AtomicUsize},
         Arc,
+        Mutex,
         RwLock,
     },
+    time::Duration,
 };
 
 use dunce::canonicalize;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use serde::{de::DeserializeOwned, Serialize};
 use tracing_subscriber::layer::SubscriberExt as _;
 
 #[derive(Serialize, Debug)]
 pub struct FuzzMetadata {
+    pub ncalls:     usize,
+    pub nruns:      usize,
+    /// Runs that ended in a divergence.
+    pub ndiffs:     usize,
+    /// Diverged runs by the kind of divergence.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub diff_kinds: BTreeMap<String, usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed:       Option<u64>,
+    /// Most calls a single run makes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_calls:  Option<usize>,
+    /// Call latency by runtime, then by function.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub latency:    BTreeMap<String, BTreeMap<String, LatencySummary>>,
+}
+
+#[derive(Serialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct LatencySummary {
     pub ncalls: usize,
+    pub p50_us: u64,
+    pub p99_us: u64,
+}
+
+impl LatencySummary {
+    fn new(histogram: &LatencyHistogram) -> Self {
+        Self {
+            ncalls: histogram.ncalls,
+            p50_us: histogram.percentile(50),
+            p99_us: histogram.percentile(99),
+        }
+    }
+}
+
+/// Buckets per power of two in a `LatencyHistogram`, which keeps reported
+/// percentiles within 1/16th of the actual ones.
+const LATENCY_SUB_BUCKET_BITS: u32 = 4;
+const LATENCY_SUB_BUCKETS: usize = 1 << LATENCY_SUB_BUCKET_BITS;
+
+/// Call durations in microseconds, counted in log-linear buckets so that it
+/// takes the same space however many calls are made.
+#[derive(Clone, Debug)]
+struct LatencyHistogram {
+    counts: Vec<usize>,
+    ncalls: usize,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; (u64::BITS - LATENCY_SUB_BUCKET_BITS + 1) as usize * LATENCY_SUB_BUCKETS],
+            ncalls: 0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    fn record(&mut self, duration: Duration) {
+        self.counts[Self::bucket(duration.as_micros().try_into().unwrap_or(u64::MAX))] += 1;
+        self.ncalls += 1;
+    }
+
+    /// Nearest-rank percentile, rounded up to the end of its bucket.
+    fn percentile(&self, p: usize) -> u64 {
+        let rank = (self.ncalls * p).div_ceil(100).max(1);
+        let mut seen = 0;
+
+        for (idx, count) in self.counts.iter().enumerate() {
+            seen += count;
+
+            if seen >= rank {
+                return Self::upper_bound(idx);
+            }
+        }
+
+        0
+    }
+
+    fn bucket(us: u64) -> usize {
+        if us < LATENCY_SUB_BUCKETS as u64 {
+            return us as usize;
+        }
+
+        // Keep the top bits below the leading one.
+        let shift = u64::BITS - 1 - us.leading_zeros() - LATENCY_SUB_BUCKET_BITS;
+
+        (shift as usize + 1) * LATENCY_SUB_BUCKETS + (us >> shift) as usize - LATENCY_SUB_BUCKETS
+    }
+
+    /// Largest duration that falls in bucket `idx`.
+    fn upper_bound(idx: usize) -> u64 {
+        if idx < LATENCY_SUB_BUCKETS {
+            return idx as u64;
+        }
+
+        let shift = idx / LATENCY_SUB_BUCKETS - 1;
+        let lower = (LATENCY_SUB_BUCKETS + idx % LATENCY_SUB_BUCKETS) as u64;
+
+        (lower << shift) + ((1 << shift) - 1)
+    }
+}
+
+/// Call durations by runtime, then by function.
+type Latencies = BTreeMap<String, BTreeMap<String, LatencyHistogram>>;
+
+/// How recorded calls are written to a runtime's `trace` directory.
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug)]
+pub enum TraceFormat {
+    #[default]
+    JsonPretty,
+    JsonCompact,
+    Bincode,
+}
+
+impl TraceFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            | TraceFormat::JsonPretty | TraceFormat::JsonCompact => "json",
+            | TraceFormat::Bincode => "bin",
+        }
+    }
+
+    fn write<T: Serialize>(self, writer: impl io::Write, value: &T) -> Result<(), eyre::Error> {
+        match self {
+            | TraceFormat::JsonPretty => serde_json::to_writer_pretty(writer, value)?,
+            | TraceFormat::JsonCompact => serde_json::to_writer(writer, value)?,
+            | TraceFormat::Bincode => bincode::serialize_into(writer, value)?,
+        }
+
+        Ok(())
+    }
+
+    fn read<T: DeserializeOwned>(self, reader: impl io::Read) -> Result<T, eyre::Error> {
+        Ok(match self {
+            | TraceFormat::JsonPretty | TraceFormat::JsonCompact => serde_json::from_reader(reader)?,
+            | TraceFormat::Bincode => bincode::deserialize_from(reader)?,
+        })
+    }
+}
+
+impl FromStr for TraceFormat {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            | "json-pretty" => TraceFormat::JsonPretty,
+            | "json-compact" => TraceFormat::JsonCompact,
+            | "bincode" => TraceFormat::Bincode,
+            | _ => return Err(eyre::eyre!("unknown trace format {s}")),
+        })
+    }
+}
+
+/// What the store needs to know about a recorded call.
+pub trait RecordedCall {
+    fn function_name(&self) -> &str;
+
+    /// How long the runtime took to answer the call, if it was timed.
+    fn duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Runtimes disagreed on the calls they made during a run.
+#[derive(thiserror::Error, PartialEq, Eq, Clone, Debug)]
+pub enum FinishError {
+    #[error("{runtime_a} made {ncalls_a} calls but {runtime_b} made {ncalls_b}")]
+    CallCount {
+        runtime_a: String,
+        ncalls_a:  usize,
+        runtime_b: String,
+        ncalls_b:  usize,
+    },
+    #[error("call {idx} was {function_a} on {runtime_a} but {function_b} on {runtime_b}")]
+    Function {
+        idx:        usize,
+        runtime_a:  String,
+        function_a: String,
+        runtime_b:  String,
+        function_b: String,
+    },
 }
 
 #[derive(Debug)]
 pub struct Store {
-    path:   PathBuf,
-    next:   Arc<AtomicUsize>,
-    ncalls: Arc<AtomicUsize>,
+    path:         PathBuf,
+    next:         Arc<AtomicUsize>,
+    ncalls:       Arc<AtomicUsize>,
+    nruns:        Arc<AtomicUsize>,
+    diff_kinds:   Arc<Mutex<BTreeMap<String, usize>>>,
+    latencies:    Arc<Mutex<Latencies>>,
+    seed:         Option<u64>,
+    max_calls:    Option<usize>,
+    trace_format: TraceFormat,
+    compress:     bool,
 }
 
 impl Store {
     pub fn new(path: &Path) -> Result<Self, io::Error> {
         Ok(Self {
-            path:   canonicalize(path)?,
-            next:   Arc::new(AtomicUsize::new(0)),
-            ncalls: Arc::new(AtomicUsize::new(0)),
+            path:         canonicalize(path)?,
+            next:         Arc::new(AtomicUsize::new(0)),
+            ncalls:       Arc::new(AtomicUsize::new(0)),
+            nruns:        Arc::new(AtomicUsize::new(0)),
+            diff_kinds:   Default::default(),
+            latencies:    Default::default(),
+            seed:         None,
+            max_calls:    None,
+            trace_format: TraceFormat::default(),
+            compress:     false,
         })
     }
 
+    pub fn with_trace_format(mut self, trace_format: TraceFormat) -> Self {
+        self.trace_format = trace_format;
+        self
+    }
+
+    /// Gzip every recorded call.
+    pub fn with_compressed_traces(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Records the seed that the fuzz buffers are generated from.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Records that each run stops after `max_calls` calls.
+    pub fn with_max_calls(mut self, max_calls: usize) -> Self {
+        self.max_calls = Some(max_calls);
+        self
+    }
+
+    pub fn max_calls(&self) -> Option<usize> {
+        self.max_calls
+    }
+
     pub fn new_run<T>(&self) -> Result<(String, RunStore<T>), io::Error> {
         let idx = self.next.fetch_add(1, atomic::Ordering::AcqRel);
         let id = format!("{idx}");
@@ -54,6 +283,13 @@ impl Store {
                 runtimes:       Default::default(),
                 tracing_guards: Vec::new(),
                 total_ncalls:   self.ncalls.clone(),
+                total_nruns:    self.nruns.clone(),
+                diff_kinds:     self.diff_kinds.clone(),
+                diff_kind:      Mutex::new(None),
+                suppressed:     Mutex::new(Vec::new()),
+                latencies:      self.latencies.clone(),
+                trace_format:   self.trace_format,
+                compress:       self.compress,
             },
         ))
     }
@@ -63,12 +299,89 @@ impl Store {
     }
 
     pub fn metadata(&self) -> FuzzMetadata {
+        let diff_kinds = self.diff_kinds.lock().unwrap();
+
         FuzzMetadata {
-            ncalls: self.ncalls.load(atomic::Ordering::Acquire),
+            ncalls:     self.ncalls.load(atomic::Ordering::Acquire),
+            nruns:      self.nruns.load(atomic::Ordering::Acquire),
+            ndiffs:     diff_kinds.values().sum(),
+            diff_kinds: diff_kinds.clone(),
+            seed:       self.seed,
+            max_calls:  self.max_calls,
+            latency:    self
+                .latencies
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(runtime, functions)| {
+                    let summaries = functions
+                        .iter()
+                        .map(|(function, histogram)| (function.clone(), LatencySummary::new(histogram)))
+                        .collect();
+
+                    (runtime.clone(), summaries)
+                })
+                .collect(),
         }
     }
 }
 
+/// Names of the runtimes recorded under the run directory `run_path`.
+pub fn recorded_runtimes(run_path: &Path) -> Result<BTreeSet<String>, io::Error> {
+    fs::read_dir(run_path.join("runtimes"))?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect()
+}
+
+/// Reads back the calls recorded by the runtime whose root is `runtime_path`, in order.
+pub fn read_trace<T: DeserializeOwned>(runtime_path: &Path) -> Result<Vec<T>, eyre::Error> {
+    trace_calls(runtime_path)?.collect()
+}
+
+/// Lazily deserializes the calls recorded by the runtime whose root is
+/// `runtime_path`, in numeric order of their trace files.
+fn trace_calls<T: DeserializeOwned>(
+    runtime_path: &Path,
+) -> Result<impl Iterator<Item = Result<T, eyre::Error>> + use<T>, eyre::Error> {
+    let mut entries = Vec::new();
+
+    for entry in fs::read_dir(runtime_path.join("trace"))? {
+        let path = entry?.path();
+        let unexpected = || eyre::eyre!("unexpected trace file {}", path.display());
+        // Named `<idx>.<format>[.gz]`.
+        let mut parts = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(unexpected)?
+            .split('.');
+        let idx: usize = parts.next().and_then(|idx| idx.parse().ok()).ok_or_else(unexpected)?;
+        // Both JSON formats parse the same way.
+        let format = match parts.next() {
+            | Some("json") => TraceFormat::JsonCompact,
+            | Some("bin") => TraceFormat::Bincode,
+            | _ => return Err(unexpected()),
+        };
+        let compressed = match parts.next() {
+            | None => false,
+            | Some("gz") => true,
+            | Some(_) => return Err(unexpected()),
+        };
+
+        entries.push((idx, path, format, compressed));
+    }
+
+    entries.sort_by_key(|&(idx, ..)| idx);
+
+    Ok(entries.into_iter().map(|(_idx, path, format, compressed)| {
+        let reader = io::BufReader::new(fs::File::open(path)?);
+
+        match compressed {
+            | true => format.read(GzDecoder::new(reader)),
+            | false => format.read(reader),
+        }
+    }))
+}
+
 #[derive(Debug)]
 pub struct RunStore<T> {
     path:           PathBuf,
@@ -76,8 +389,18 @@ pub struct RunStore<T> {
     data_dir:       PathBuf,
     tracing_guards: Vec<tracing::dispatcher::DefaultGuard>,
     runtimes_dir:   PathBuf,
-    runtimes:       HashMap<String, Arc<RwLock<RuntimeStore<T>>>>,
+    /// In the order they were added.
+    runtimes:       Vec<(String, Arc<RwLock<RuntimeStore<T>>>)>,
     total_ncalls:   Arc<AtomicUsize>,
+    total_nruns:    Arc<AtomicUsize>,
+    diff_kinds:     Arc<Mutex<BTreeMap<String, usize>>>,
+    /// Kind of divergence the run ended in, if any.
+    diff_kind:      Mutex<Option<String>>,
+    /// Divergences ignore rules suppressed during the run.
+    suppressed:     Mutex<Vec<serde_json::Value>>,
+    latencies:      Arc<Mutex<Latencies>>,
+    trace_format:   TraceFormat,
+    compress:       bool,
 }
 
 impl<T: Serialize + DeserializeOwned> RunStore<T> {
@@ -89,27 +412,109 @@ impl<T: Serialize + DeserializeOwned> RunStore<T> {
         let store = Arc::new(RwLock::new(RuntimeStore::new(
             &self.runtimes_dir.join(&name),
             enable_logging,
+            self.trace_format,
+            self.compress,
         )?));
 
-        self.runtimes.insert(name, store.clone());
+        self.runtimes.push((name, store.clone()));
 
         Ok(store)
     }
 
-    pub fn finish(&mut self) {
-        let mut runtimes = self.runtimes.iter();
-        let (rt0_name, rt0) = runtimes.next().unwrap();
-        let rt0_ncalls = rt0.read().unwrap().next_call_idx;
+    /// Records that the run diverged. Counted by `finish`.
+    pub fn record_diff(&self, kind: &str) {
+        *self.diff_kind.lock().unwrap() = Some(kind.to_string());
+    }
+
+    /// Records a divergence that an ignore rule suppressed. Written out by
+    /// `write_suppressed`.
+    pub fn record_suppressed(&self, entry: serde_json::Value) {
+        self.suppressed.lock().unwrap().push(entry);
+    }
 
-        for (rt1_name, rt1) in runtimes {
-            let rt1_ncalls = rt1.read().unwrap().next_call_idx;
+    /// Writes the suppressed divergences to `suppressed.json`, if there are
+    /// any.
+    pub fn write_suppressed(&self) -> Result<(), io::Error> {
+        let suppressed = self.suppressed.lock().unwrap();
 
-            if rt0_ncalls != rt1_ncalls {
-                panic!("number of calls mistach {rt0_name} {rt1_name}");
+        if suppressed.is_empty() {
+            return Ok(());
+        }
+
+        serde_json::to_writer_pretty(
+            BufWriter::new(fs::File::create(self.path.join("suppressed.json"))?),
+            &*suppressed,
+        )?;
+
+        Ok(())
+    }
+
+    /// Counts the run and its divergence, if any, then checks that every
+    /// runtime made the same sequence of calls and adds them to the fuzz-wide
+    /// call count and latencies.
+    pub fn finish(&mut self) -> Result<(), FinishError> {
+        self.total_nruns.fetch_add(1, atomic::Ordering::AcqRel);
+
+        if let Some(kind) = self.diff_kind.lock().unwrap().take() {
+            *self.diff_kinds.lock().unwrap().entry(kind).or_default() += 1;
+        }
+
+        let mut runtimes = self
+            .runtimes
+            .iter()
+            .map(|(name, runtime)| (name, runtime.read().unwrap()))
+            .collect::<Vec<_>>();
+
+        runtimes.sort_by_key(|&(name, _)| name);
+
+        let (rt0_name, rt0) = &runtimes[0];
+
+        for (rt1_name, rt1) in &runtimes[1..] {
+            if rt0.functions.len() != rt1.functions.len() {
+                return Err(FinishError::CallCount {
+                    runtime_a: rt0_name.to_string(),
+                    ncalls_a:  rt0.functions.len(),
+                    runtime_b: rt1_name.to_string(),
+                    ncalls_b:  rt1.functions.len(),
+                });
+            }
+
+            if let Some((idx, (function_a, function_b))) = rt0
+                .functions
+                .iter()
+                .zip(&rt1.functions)
+                .enumerate()
+                .find(|(_idx, (a, b))| a != b)
+            {
+                return Err(FinishError::Function {
+                    idx,
+                    runtime_a: rt0_name.to_string(),
+                    function_a: function_a.clone(),
+                    runtime_b: rt1_name.to_string(),
+                    function_b: function_b.clone(),
+                });
+            }
+        }
+
+        self.total_ncalls
+            .fetch_add(rt0.functions.len(), atomic::Ordering::AcqRel);
+
+        let mut latencies = self.latencies.lock().unwrap();
+
+        for (name, runtime) in &runtimes {
+            for (function, duration) in runtime.functions.iter().zip(&runtime.durations) {
+                if let Some(duration) = duration {
+                    latencies
+                        .entry(name.to_string())
+                        .or_default()
+                        .entry(function.clone())
+                        .or_default()
+                        .record(*duration);
+                }
             }
         }
 
-        self.total_ncalls.fetch_add(rt0_ncalls, atomic::Ordering::AcqRel);
+        Ok(())
     }
 
     /// Should be called only once per thread.
@@ -142,6 +547,11 @@ impl<T: Serialize + DeserializeOwned> RunStore<T> {
         Ok(())
     }
 
+    pub fn root_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Runtimes in the order they were added.
     pub fn runtime_stores(&self) -> impl Iterator<Item = (&str, Arc<RwLock<RuntimeStore<T>>>)> + use<'_, T> {
         self.runtimes.iter().map(|(name, store)| (name.as_str(), store.clone()))
     }
@@ -149,17 +559,24 @@ impl<T: Serialize + DeserializeOwned> RunStore<T> {
 
 #[derive(Debug)]
 pub struct RuntimeStore<T> {
-    root_path:     PathBuf,
-    base_path:     PathBuf,
-    log_trace:     Option<PathBuf>,
-    next_call_idx: usize,
-    last_call:     Option<T>,
+    root_path:    PathBuf,
+    base_path:    PathBuf,
+    log_trace:    Option<PathBuf>,
+    trace_format: TraceFormat,
+    compress:     bool,
+    /// Names of the functions called so far, in order.
+    functions:    Vec<String>,
+    /// How long each of those calls took.
+    durations:    Vec<Option<Duration>>,
+    last_call:    Option<T>,
 }
 
 impl<T> RuntimeStore<T> {
-    pub fn new(path: &Path, log_trace: bool) -> Result<Self, io::Error> {
+    /// With `compress`, each call file is gzipped and gets a `.gz` extension.
+    pub fn new(path: &Path, log_trace: bool, trace_format: TraceFormat, compress: bool) -> Result<Self, io::Error> {
         fs::create_dir(path)?;
         fs::create_dir(&path.join("base"))?;
+        fs::File::create(path.join("stdout"))?;
 
         let root_path = path.to_path_buf();
         let base_path = root_path.join("base");
@@ -178,7 +595,10 @@ impl<T> RuntimeStore<T> {
             root_path,
             base_path,
             log_trace,
-            next_call_idx: 0,
+            trace_format,
+            compress,
+            functions: Vec::new(),
+            durations: Vec::new(),
             last_call: None,
         })
     }
@@ -190,32 +610,313 @@ impl<T> RuntimeStore<T> {
     pub fn base_path(&self) -> &Path {
         &self.base_path
     }
+
+    /// Everything the guest wrote to its stdout so far.
+    pub fn stdout_path(&self) -> PathBuf {
+        self.root_path.join("stdout")
+    }
+
+    pub fn append_stdout(&mut self, bytes: &[u8]) -> Result<(), io::Error> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        fs::OpenOptions::new()
+            .append(true)
+            .open(self.stdout_path())?
+            .write_all(bytes)
+    }
 }
 
 impl<T> RuntimeStore<T>
 where
-    T: Serialize + DeserializeOwned,
+    T: Serialize + DeserializeOwned + RecordedCall,
 {
     pub fn last_call(&self) -> Option<&T> {
         self.last_call.as_ref()
     }
 
+    /// Streams every call recorded so far from the trace, in order. Fails if
+    /// the store was made without trace logging.
+    pub fn iter_calls(&self) -> Result<impl Iterator<Item = Result<T, eyre::Error>> + use<T>, eyre::Error> {
+        match &self.log_trace {
+            | Some(_) => trace_calls(&self.root_path),
+            | None => Err(eyre::eyre!("calls of {} weren't logged", self.root_path.display())),
+        }
+    }
+
     pub fn record_call(&mut self, result: T) -> Result<(), eyre::Error> {
         if let Some(trace_path) = &self.log_trace {
-            serde_json::to_writer_pretty(
-                BufWriter::new(
-                    fs::OpenOptions::new()
-                        .write(true)
-                        .create_new(true)
-                        .open(trace_path.join(format!("{:04}.json", self.next_call_idx)))?,
-                ),
-                &result,
-            )?;
-        }
-
-        self.next_call_idx += 1;
+            let mut file_name = format!("{:04}.{}", self.functions.len(), self.trace_format.extension());
+
+            if self.compress {
+                file_name.push_str(".gz");
+            }
+
+            let mut writer = BufWriter::new(
+                fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(trace_path.join(file_name))?,
+            );
+
+            if self.compress {
+                let mut encoder = GzEncoder::new(writer, Compression::default());
+
+                self.trace_format.write(&mut encoder, &result)?;
+                writer = encoder.finish()?;
+            } else {
+                self.trace_format.write(&mut writer, &result)?;
+            }
+
+            writer.flush()?;
+        }
+
+        self.functions.push(result.function_name().to_string());
+        self.durations.push(result.duration());
         self.last_call = Some(result);
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+    struct Call {
+        function: String,
+        errno:    Option<i32>,
+        params:   Vec<u64>,
+        duration: Option<Duration>,
+    }
+
+    impl RecordedCall for Call {
+        fn function_name(&self) -> &str {
+            &self.function
+        }
+
+        fn duration(&self) -> Option<Duration> {
+            self.duration
+        }
+    }
+
+    fn call(function: &str) -> Call {
+        Call {
+            function: function.to_string(),
+            errno:    Some(0),
+            params:   vec![],
+            duration: None,
+        }
+    }
+
+    #[test]
+    fn max_calls_is_recorded_in_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path()).unwrap().with_max_calls(5);
+
+        assert_eq!(
+            serde_json::to_value(store.metadata()).unwrap(),
+            serde_json::json!({ "ncalls": 0, "nruns": 0, "ndiffs": 0, "max_calls": 5 })
+        );
+    }
+
+    #[test]
+    fn diverged_runs_are_counted_by_kind() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path()).unwrap();
+
+        for diff in [None, Some("errno"), Some("filesystem"), None, Some("errno")] {
+            let (_id, mut run) = store.new_run::<Call>().unwrap();
+
+            run.new_runtime("wasmtime".to_string(), false)
+                .unwrap()
+                .write()
+                .unwrap()
+                .record_call(call("fd_write"))
+                .unwrap();
+
+            if let Some(kind) = diff {
+                run.record_diff(kind);
+            }
+
+            run.finish().unwrap();
+        }
+
+        let metadata = store.metadata();
+
+        assert_eq!(metadata.nruns, 5);
+        assert_eq!(metadata.ndiffs, 3);
+        assert_eq!(
+            metadata.diff_kinds,
+            BTreeMap::from([("errno".to_string(), 2), ("filesystem".to_string(), 1)])
+        );
+        assert_eq!(serde_json::to_value(&metadata).unwrap()["diff_kinds"]["errno"], 2);
+    }
+
+    #[test]
+    fn finish_reports_first_differing_function() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path()).unwrap();
+        let (_id, mut run) = store.new_run::<Call>().unwrap();
+        let wasmtime = run.new_runtime("wasmtime".to_string(), false).unwrap();
+        let wasmer = run.new_runtime("wasmer".to_string(), false).unwrap();
+
+        for function in ["path_open", "fd_write", "fd_close"] {
+            wasmtime.write().unwrap().record_call(call(function)).unwrap();
+        }
+
+        for function in ["path_open", "fd_read", "fd_close"] {
+            wasmer.write().unwrap().record_call(call(function)).unwrap();
+        }
+
+        assert_eq!(
+            run.finish(),
+            Err(FinishError::Function {
+                idx:        1,
+                runtime_a:  "wasmer".to_string(),
+                function_a: "fd_read".to_string(),
+                runtime_b:  "wasmtime".to_string(),
+                function_b: "fd_write".to_string(),
+            })
+        );
+        assert_eq!(store.metadata().ncalls, 0);
+    }
+
+    #[test]
+    fn trace_round_trips_in_every_format() {
+        let calls = vec![
+            Call {
+                function: "path_open".to_string(),
+                errno:    Some(0),
+                params:   vec![3, 0, u64::MAX],
+                duration: Some(Duration::from_nanos(1_500)),
+            },
+            Call {
+                function: "fd_close".to_string(),
+                errno:    None,
+                params:   vec![],
+                duration: None,
+            },
+        ];
+
+        for format in [TraceFormat::JsonPretty, TraceFormat::JsonCompact, TraceFormat::Bincode] {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("wasmtime");
+            let mut store = RuntimeStore::new(&path, true, format, false).unwrap();
+
+            for call in &calls {
+                store.record_call(call.clone()).unwrap();
+            }
+
+            assert_eq!(read_trace::<Call>(&path).unwrap(), calls, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn compressed_trace_reads_back_the_same() {
+        let call = Call {
+            function: "fd_write".to_string(),
+            errno:    Some(8),
+            params:   vec![1, 2],
+            duration: None,
+        };
+
+        for format in [TraceFormat::JsonPretty, TraceFormat::Bincode] {
+            let dir = tempfile::tempdir().unwrap();
+            let plain_path = dir.path().join("plain");
+            let compressed_path = dir.path().join("compressed");
+            let mut plain = RuntimeStore::new(&plain_path, true, format, false).unwrap();
+            let mut compressed = RuntimeStore::new(&compressed_path, true, format, true).unwrap();
+
+            plain.record_call(call.clone()).unwrap();
+            compressed.record_call(call.clone()).unwrap();
+
+            let file_name = format!("0000.{}.gz", format.extension());
+
+            assert!(compressed_path.join("trace").join(file_name).exists());
+            assert_eq!(
+                read_trace::<Call>(&compressed_path).unwrap(),
+                read_trace::<Call>(&plain_path).unwrap(),
+            );
+        }
+    }
+
+    #[test]
+    fn recorded_calls_iterate_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = RuntimeStore::new(&dir.path().join("wasmtime"), true, TraceFormat::JsonCompact, false).unwrap();
+        let calls = vec![call("path_open"), call("fd_write"), call("fd_close")];
+
+        for call in &calls {
+            store.record_call(call.clone()).unwrap();
+        }
+
+        assert_eq!(
+            store.iter_calls().unwrap().collect::<Result<Vec<_>, _>>().unwrap(),
+            calls
+        );
+    }
+
+    #[test]
+    fn runtimes_iterate_in_insertion_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path()).unwrap();
+        let (_id, mut run) = store.new_run::<Call>().unwrap();
+        let names = ["wasmtime", "wamr", "wasmer", "node", "wasmedge"];
+
+        for name in names {
+            run.new_runtime(name.to_string(), false).unwrap();
+        }
+
+        assert_eq!(
+            run.runtime_stores().map(|(name, _store)| name).collect::<Vec<_>>(),
+            names
+        );
+    }
+
+    #[test]
+    fn latency_is_summarized_per_function() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path()).unwrap();
+        let (_id, mut run) = store.new_run::<Call>().unwrap();
+        let wasmtime = run.new_runtime("wasmtime".to_string(), false).unwrap();
+
+        for ms in 1..=100 {
+            wasmtime
+                .write()
+                .unwrap()
+                .record_call(Call {
+                    duration: Some(Duration::from_millis(ms)),
+                    ..call("fd_write")
+                })
+                .unwrap();
+        }
+
+        wasmtime.write().unwrap().record_call(call("fd_close")).unwrap();
+        run.finish().unwrap();
+
+        let metadata = store.metadata();
+
+        let summaries = &metadata.latency["wasmtime"];
+        let summary = summaries["fd_write"];
+
+        assert_eq!(metadata.ncalls, 101);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summary.ncalls, 100);
+        assert!((50_000..=50_000 * 17 / 16).contains(&summary.p50_us), "{summary:?}");
+        assert!((99_000..=99_000 * 17 / 16).contains(&summary.p99_us), "{summary:?}");
+    }
+
+    #[test]
+    fn latency_buckets_are_within_a_sixteenth() {
+        for us in (0..100_000).chain([u64::MAX / 3, u64::MAX]) {
+            let upper = LatencyHistogram::upper_bound(LatencyHistogram::bucket(us));
+
+            assert!(upper >= us && upper - us <= us / 16, "{us} {upper}");
+        }
+    }
+}